@@ -0,0 +1,376 @@
+use std::f64::consts::PI;
+use std::fmt;
+use crate::error::StegoError;
+use crate::psychoacoustic::bark;
+use crate::transform::{analyze, synthesize};
+
+// Искажения, которым стегаконтейнер может подвергнуться при обработке и передаче.
+// Все атаки работают с отсчетами, разделенными по каналам, и детерминированы:
+// шум порождается генератором с фиксированным зерном, чтобы результаты повторялись.
+
+// Длина КИХ-фильтров (нечетная, чтобы фильтр был симметричным и не сдвигал сигнал)
+const FILTER_TAPS: usize = 101;
+const NOISE_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+// Длина гранулы MP3: кадры MDCT упрощенного кодека сдвигаются на столько отсчетов
+const CODEC_HOP: usize = 576;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Attack {
+    // Белый гауссов шум с заданным отношением сигнал/шум, дБ
+    Noise { snr_db: f64 },
+    // Повторное квантование до указанной разрядности
+    Requantize { bits: u32 },
+    // Понижение частоты дискретизации и возврат к исходной
+    Resample { sample_rate: u32 },
+    LowPass { cutoff: f64 },
+    HighPass { cutoff: f64 },
+    Scale { factor: f64 },
+    // Обрезка последних отсчетов
+    Crop { samples: usize },
+    // Задержка на указанное количество отсчетов (длина сохраняется)
+    Shift { samples: usize },
+    // Эхо с задержкой в миллисекундах
+    Echo { delay_ms: f64, decay: f64 },
+    // Сжатие с потерями и декодирование упрощенным перцептивным кодеком с заданным битрейтом, кбит/с
+    Lossy { kbps: u32 },
+}
+
+// Набор атак по умолчанию для сравнения устойчивости
+pub const DEFAULT_ATTACKS: [Attack; 15] = [
+    Attack::Noise { snr_db: 40.0 },
+    Attack::Noise { snr_db: 20.0 },
+    Attack::Noise { snr_db: 10.0 },
+    Attack::Requantize { bits: 8 },
+    Attack::Resample { sample_rate: 22050 },
+    Attack::LowPass { cutoff: 4000.0 },
+    Attack::HighPass { cutoff: 500.0 },
+    Attack::Scale { factor: 0.5 },
+    Attack::Scale { factor: 2.0 },
+    Attack::Crop { samples: 1000 },
+    Attack::Shift { samples: 1 },
+    Attack::Shift { samples: 100 },
+    Attack::Echo { delay_ms: 50.0, decay: 0.3 },
+    Attack::Lossy { kbps: 128 },
+    Attack::Lossy { kbps: 64 },
+];
+
+impl Attack {
+    // Разбор записи вида "noise:20", "lowpass:4000", "echo:50:0.3"
+    pub fn parse(spec: &str) -> Result<Attack, StegoError> {
+        let invalid = |what: &str| StegoError::InvalidArgument(format!("атака \"{}\": {}", spec, what));
+        let mut tokens = spec.split(':');
+        let name = tokens.next().unwrap_or_default();
+        let params: Vec<f64> = tokens
+            .map(|t| t.trim().parse::<f64>().map_err(|_| invalid("параметры должны быть числами")))
+            .collect::<Result<_, _>>()?;
+
+        let expect = |count: usize| {
+            if params.len() == count {
+                Ok(())
+            } else {
+                Err(invalid(&format!("ожидается параметров: {}", count)))
+            }
+        };
+        let positive = |value: f64| if value > 0.0 { Ok(value) } else { Err(invalid("параметр должен быть положительным")) };
+
+        let attack = match name {
+            "noise" => {
+                expect(1)?;
+                Attack::Noise { snr_db: params[0] }
+            }
+            "requantize" => {
+                expect(1)?;
+                let bits = params[0] as u32;
+                if !(1..=32).contains(&bits) {
+                    return Err(invalid("разрядность должна быть от 1 до 32"));
+                }
+                Attack::Requantize { bits }
+            }
+            "resample" => {
+                expect(1)?;
+                Attack::Resample { sample_rate: positive(params[0])? as u32 }
+            }
+            "lowpass" => {
+                expect(1)?;
+                Attack::LowPass { cutoff: positive(params[0])? }
+            }
+            "highpass" => {
+                expect(1)?;
+                Attack::HighPass { cutoff: positive(params[0])? }
+            }
+            "scale" => {
+                expect(1)?;
+                Attack::Scale { factor: params[0] }
+            }
+            "crop" => {
+                expect(1)?;
+                Attack::Crop { samples: params[0] as usize }
+            }
+            "shift" => {
+                expect(1)?;
+                Attack::Shift { samples: params[0] as usize }
+            }
+            "echo" => {
+                expect(2)?;
+                Attack::Echo { delay_ms: positive(params[0])?, decay: params[1] }
+            }
+            "lossy" => {
+                expect(1)?;
+                Attack::Lossy { kbps: positive(params[0])? as u32 }
+            }
+            _ => return Err(invalid(
+                "неизвестный вид (noise, requantize, resample, lowpass, highpass, scale, crop, shift, echo, lossy)",
+            )),
+        };
+        Ok(attack)
+    }
+
+    pub fn apply(&self, channels: &[Vec<f64>], sample_rate: u32) -> Vec<Vec<f64>> {
+        let mut rng = NOISE_SEED;
+        channels.iter().map(|channel| self.apply_channel(channel, sample_rate, &mut rng)).collect()
+    }
+
+    fn apply_channel(&self, x: &[f64], sample_rate: u32, rng: &mut u64) -> Vec<f64> {
+        match *self {
+            Attack::Noise { snr_db } => {
+                let power = x.iter().map(|v| v * v).sum::<f64>() / x.len().max(1) as f64;
+                let sigma = (power / 10f64.powf(snr_db / 10.0)).sqrt();
+                x.iter().map(|v| v + sigma * gaussian(rng)).collect()
+            }
+            Attack::Requantize { bits } => {
+                let levels = 2f64.powi(bits as i32 - 1);
+                x.iter().map(|v| (v * levels).round().clamp(-levels, levels - 1.0) / levels).collect()
+            }
+            Attack::Resample { sample_rate: target } => {
+                if target >= sample_rate {
+                    return x.to_vec();
+                }
+                // Перед прореживанием убираются частоты выше новой частоты Найквиста
+                let filtered = fir(x, &lowpass_taps(target as f64 / 2.0 / sample_rate as f64));
+                let ratio = sample_rate as f64 / target as f64;
+                let decimated: Vec<f64> = (0..(x.len() as f64 / ratio) as usize)
+                    .map(|i| interpolate(&filtered, i as f64 * ratio))
+                    .collect();
+                (0..x.len()).map(|i| interpolate(&decimated, i as f64 / ratio)).collect()
+            }
+            Attack::LowPass { cutoff } => fir(x, &lowpass_taps(cutoff / sample_rate as f64)),
+            Attack::HighPass { cutoff } => {
+                let low = fir(x, &lowpass_taps(cutoff / sample_rate as f64));
+                x.iter().zip(low).map(|(v, l)| v - l).collect()
+            }
+            Attack::Scale { factor } => x.iter().map(|v| (v * factor).clamp(-1.0, 1.0)).collect(),
+            Attack::Crop { samples } => x[..x.len().saturating_sub(samples)].to_vec(),
+            Attack::Shift { samples } => {
+                let samples = samples.min(x.len());
+                let mut shifted = vec![0.0; samples];
+                shifted.extend_from_slice(&x[..x.len() - samples]);
+                shifted
+            }
+            Attack::Echo { delay_ms, decay } => {
+                let delay = (delay_ms / 1000.0 * sample_rate as f64).round() as usize;
+                (0..x.len())
+                    .map(|i| x[i] + if i >= delay { decay * x[i - delay] } else { 0.0 })
+                    .collect()
+            }
+            Attack::Lossy { kbps } => lossy(x, sample_rate, kbps),
+        }
+    }
+}
+
+impl fmt::Display for Attack {
+    // Та же запись, что принимает parse
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Attack::Noise { snr_db } => write!(f, "noise:{}", snr_db),
+            Attack::Requantize { bits } => write!(f, "requantize:{}", bits),
+            Attack::Resample { sample_rate } => write!(f, "resample:{}", sample_rate),
+            Attack::LowPass { cutoff } => write!(f, "lowpass:{}", cutoff),
+            Attack::HighPass { cutoff } => write!(f, "highpass:{}", cutoff),
+            Attack::Scale { factor } => write!(f, "scale:{}", factor),
+            Attack::Crop { samples } => write!(f, "crop:{}", samples),
+            Attack::Shift { samples } => write!(f, "shift:{}", samples),
+            Attack::Echo { delay_ms, decay } => write!(f, "echo:{}:{}", delay_ms, decay),
+            Attack::Lossy { kbps } => write!(f, "lossy:{}", kbps),
+        }
+    }
+}
+
+// Нормальное распределение по методу Бокса-Мюллера поверх xorshift64
+fn gaussian(state: &mut u64) -> f64 {
+    let mut uniform = || {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        ((*state >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    };
+    let (u1, u2) = (uniform(), uniform());
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+// Фильтр нижних частот с окном Хэмминга; cutoff - доля частоты дискретизации
+fn lowpass_taps(cutoff: f64) -> Vec<f64> {
+    let cutoff = cutoff.min(0.5);
+    let middle = (FILTER_TAPS / 2) as f64;
+    let taps: Vec<f64> = (0..FILTER_TAPS)
+        .map(|n| {
+            let t = n as f64 - middle;
+            let sinc = if t == 0.0 { 2.0 * cutoff } else { (2.0 * PI * cutoff * t).sin() / (PI * t) };
+            sinc * (0.54 - 0.46 * (2.0 * PI * n as f64 / (FILTER_TAPS - 1) as f64).cos())
+        })
+        .collect();
+    // Единичное усиление на нулевой частоте
+    let sum: f64 = taps.iter().sum();
+    taps.iter().map(|t| t / sum).collect()
+}
+
+// Свертка с центрированным симметричным фильтром: задержка не вносится
+fn fir(x: &[f64], taps: &[f64]) -> Vec<f64> {
+    let middle = taps.len() / 2;
+    (0..x.len())
+        .map(|i| {
+            taps.iter().enumerate()
+                .filter_map(|(k, t)| (i + k).checked_sub(middle).and_then(|j| x.get(j)).map(|v| v * t))
+                .sum()
+        })
+        .collect()
+}
+
+// Упрощенный перцептивный кодек: коэффициенты MDCT выше полосы, доступной при битрейте,
+// отбрасываются, а в каждой критической полосе квантуются с шагом, при котором шум
+// квантования на kbps / 8 дБ ниже мощности полосы (16 дБ при 128 кбит/с).
+// Слабые коэффициенты округляются до нуля, как в настоящих кодеках.
+fn lossy(x: &[f64], sample_rate: u32, kbps: u32) -> Vec<f64> {
+    // Сигнал дополняется нулями, чтобы каждый отсчет покрывали два кадра
+    let mut padded = vec![0.0; CODEC_HOP];
+    padded.extend_from_slice(x);
+    padded.resize((x.len().div_ceil(CODEC_HOP) + 2) * CODEC_HOP, 0.0);
+
+    let bin_freq = |k: usize| (k as f64 + 0.5) * sample_rate as f64 / (2 * CODEC_HOP) as f64;
+    let cutoff = (4000.0 + 100.0 * kbps as f64).min(sample_rate as f64 / 2.0);
+    let noise_ratio = 10f64.powf(-(kbps as f64 / 8.0) / 10.0);
+    let band_of: Vec<usize> = (0..CODEC_HOP).map(|k| bark(bin_freq(k)) as usize).collect();
+    let bands = band_of.last().map_or(0, |b| b + 1);
+
+    let frames: Vec<Vec<f64>> = analyze(&padded, CODEC_HOP).into_iter()
+        .map(|mut frame| {
+            let mut energy = vec![0.0; bands];
+            let mut count = vec![0usize; bands];
+            for (k, c) in frame.iter().enumerate() {
+                energy[band_of[k]] += c * c;
+                count[band_of[k]] += 1;
+            }
+            for (k, c) in frame.iter_mut().enumerate() {
+                // Равномерное квантование с шагом step дает шум мощностью step^2 / 12
+                let step = (12.0 * noise_ratio * energy[band_of[k]] / count[band_of[k]] as f64).sqrt();
+                *c = if bin_freq(k) > cutoff || step == 0.0 { 0.0 } else { (*c / step).round() * step };
+            }
+            frame
+        })
+        .collect();
+
+    synthesize(&frames, CODEC_HOP, padded.len())[CODEC_HOP..CODEC_HOP + x.len()].to_vec()
+}
+
+fn interpolate(x: &[f64], position: f64) -> f64 {
+    let i = position.floor() as usize;
+    let frac = position - i as f64;
+    match (x.get(i), x.get(i + 1)) {
+        (Some(a), Some(b)) => a + (b - a) * frac,
+        (Some(a), None) => *a,
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signals::sine;
+
+    fn power(x: &[f64]) -> f64 {
+        x.iter().map(|v| v * v).sum::<f64>() / x.len() as f64
+    }
+
+    #[test]
+    fn test_parse_round_trip() {
+        for attack in DEFAULT_ATTACKS {
+            assert_eq!(Attack::parse(&attack.to_string()).unwrap(), attack);
+        }
+        assert!(matches!(Attack::parse("noise"), Err(StegoError::InvalidArgument(_))));
+        assert!(matches!(Attack::parse("blur:3"), Err(StegoError::InvalidArgument(_))));
+        assert!(matches!(Attack::parse("lowpass:-1"), Err(StegoError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_noise_has_requested_snr() {
+        let signal = vec![sine(440.0, 0.5, 44100)];
+        let noisy = Attack::Noise { snr_db: 20.0 }.apply(&signal, 44100);
+
+        let noise: Vec<f64> = signal[0].iter().zip(&noisy[0]).map(|(x, y)| y - x).collect();
+        let snr = 10.0 * (power(&signal[0]) / power(&noise)).log10();
+        assert!((snr - 20.0).abs() < 0.2);
+        // Атака повторяема
+        assert_eq!(noisy, Attack::Noise { snr_db: 20.0 }.apply(&signal, 44100));
+    }
+
+    #[test]
+    fn test_filters_separate_frequencies() {
+        let low = sine(200.0, 0.5, 8192);
+        let high = sine(10000.0, 0.5, 8192);
+        let mixed = vec![low.iter().zip(&high).map(|(a, b)| a + b).collect::<Vec<f64>>()];
+
+        let lowpassed = Attack::LowPass { cutoff: 2000.0 }.apply(&mixed, 44100);
+        let highpassed = Attack::HighPass { cutoff: 2000.0 }.apply(&mixed, 44100);
+        // Края не учитываются: там фильтр видит только половину отсчетов
+        let inner = FILTER_TAPS..8192 - FILTER_TAPS;
+        let error = |x: &[f64], y: &[f64]| power(&x[inner.clone()].iter().zip(&y[inner.clone()]).map(|(a, b)| a - b).collect::<Vec<_>>());
+        assert!(error(&lowpassed[0], &low) < 1e-4);
+        assert!(error(&highpassed[0], &high) < 1e-4);
+    }
+
+    #[test]
+    fn test_resample_keeps_length_and_passband() {
+        let signal = vec![sine(1000.0, 0.5, 8192)];
+        let resampled = Attack::Resample { sample_rate: 22050 }.apply(&signal, 44100);
+
+        assert_eq!(resampled[0].len(), signal[0].len());
+        let inner = FILTER_TAPS..8192 - FILTER_TAPS;
+        let error: Vec<f64> = signal[0][inner.clone()].iter().zip(&resampled[0][inner]).map(|(a, b)| a - b).collect();
+        assert!(power(&error) < 1e-3 * power(&signal[0]));
+    }
+
+    #[test]
+    fn test_time_domain_attacks() {
+        let signal = vec![vec![0.25, 0.5, -0.75, 1.0]];
+
+        assert_eq!(Attack::Shift { samples: 1 }.apply(&signal, 44100), vec![vec![0.0, 0.25, 0.5, -0.75]]);
+        assert_eq!(Attack::Crop { samples: 3 }.apply(&signal, 44100), vec![vec![0.25]]);
+        assert_eq!(Attack::Scale { factor: 2.0 }.apply(&signal, 44100), vec![vec![0.5, 1.0, -1.0, 1.0]]);
+        assert_eq!(Attack::Requantize { bits: 2 }.apply(&signal, 44100), vec![vec![0.5, 0.5, -1.0, 0.5]]);
+        // Задержка эха 1000 / 44100 мс - один отсчет
+        let echo = Attack::Echo { delay_ms: 1000.0 / 44100.0, decay: 0.5 }.apply(&signal, 44100);
+        assert_eq!(echo, vec![vec![0.25, 0.625, -0.5, 0.625]]);
+    }
+
+    #[test]
+    fn test_lossy_keeps_length_and_band_limits() {
+        let low = sine(1000.0, 0.5, 20_000);
+        let high = sine(18000.0, 0.5, 20_000);
+        let mixed = vec![low.iter().zip(&high).map(|(a, b)| a + b).collect::<Vec<f64>>()];
+
+        let decoded = Attack::Lossy { kbps: 96 }.apply(&mixed, 44100);
+        assert_eq!(decoded[0].len(), mixed[0].len());
+
+        // Тон 18 кГц выше полосы кодека при 96 кбит/с и пропадает, 1 кГц остается
+        // с шумом квантования около 12 дБ ниже сигнала
+        let error: Vec<f64> = decoded[0].iter().zip(&low).map(|(a, b)| a - b).collect();
+        let snr = 10.0 * (power(&low) / power(&error)).log10();
+        assert!(snr > 10.0, "ОСШ {}", snr);
+
+        // Чем выше битрейт, тем меньше искажения
+        let error_at = |kbps: u32| {
+            let decoded = Attack::Lossy { kbps }.apply(std::slice::from_ref(&low), 44100);
+            power(&decoded[0].iter().zip(&low).map(|(a, b)| a - b).collect::<Vec<_>>())
+        };
+        assert!(error_at(256) < error_at(64));
+    }
+}
@@ -0,0 +1,144 @@
+use bitvec::prelude::*;
+use crate::attack::Attack;
+use crate::error::StegoError;
+use crate::method::StegoMethod;
+use crate::stego::{Embedder, Extractor};
+
+// Проверка устойчивости: сообщение встраивается без заголовка с каждой силой встраивания,
+// стегаконтейнер искажается каждой атакой и сообщение извлекается обратно.
+// Сравниваются биты, поэтому видна доля ошибок даже там, где текст уже не читается.
+
+// Зерно ПСП фиксировано, чтобы прогоны можно было сравнивать между собой
+const BENCH_SEED: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchRow {
+    // None - стегаконтейнер без искажений
+    pub attack: Option<Attack>,
+    pub strength: f64,
+    // Доля ошибочных бит при извлечении с исходным контейнером и слепом;
+    // None, если сообщение извлечь не удалось или не удалось встроить с этой силой
+    pub informed_ber: Option<f64>,
+    pub blind_ber: Option<f64>,
+}
+
+// Недопустимая сила или сила, с которой встраивание не удалось (например, эхо перегружает
+// сигнал), дает строки без долей ошибок. Ошибка возвращается, только если сообщение не встроилось ни с одной силой.
+pub fn bench(container: &[Vec<f64>], sample_rate: u32, message: &[u8], method: &dyn StegoMethod, attacks: &[Attack], strengths: &[f64]) -> Result<Vec<BenchRow>, StegoError> {
+    let mut rows = Vec::new();
+    let mut embedded_any = strengths.is_empty();
+    let mut last_error = None;
+    let all_attacks = || std::iter::once(None).chain(attacks.iter().copied().map(Some));
+
+    for &strength in strengths {
+        let embedded = method.with_strength(strength).and_then(|method| {
+            let embedded = Embedder::new()
+                .without_header()
+                .seed(BENCH_SEED)
+                .method(method.clone())
+                .embed(container, message)?;
+            Ok((method, embedded))
+        });
+        let (method, embedded) = match embedded {
+            Ok(embedded) => embedded,
+            Err(e) => {
+                rows.extend(all_attacks().map(|attack| BenchRow { attack, strength, informed_ber: None, blind_ber: None }));
+                last_error = Some(e);
+                continue;
+            }
+        };
+        embedded_any = true;
+        let bits_num = embedded.bits_per_symbol * embedded.message_len;
+        let expected: BitVec<u8, Msb0> = BitVec::<u8, Msb0>::from_slice(message).into_iter()
+            .chain(std::iter::repeat(true))
+            .take(bits_num)
+            .collect();
+
+        let extractor = Extractor::new(embedded.psp.clone())
+            .method(method)
+            .without_header(embedded.bits_per_symbol, embedded.message_len);
+        let ber = |extractor: &Extractor, stego: &[Vec<f64>]| -> Option<f64> {
+            let extracted = extractor.extract(stego).ok()?;
+            let bits = BitVec::<u8, Msb0>::from_slice(&extracted.message);
+            let errors = expected.iter().zip(bits.iter()).filter(|(a, b)| **a != **b).count();
+            Some(errors as f64 / bits_num.max(1) as f64)
+        };
+
+        for attack in all_attacks() {
+            let stego = match attack {
+                Some(attack) => attack.apply(&embedded.amplitudes, sample_rate),
+                None => embedded.amplitudes.clone(),
+            };
+
+            rows.push(BenchRow {
+                attack,
+                strength,
+                informed_ber: ber(&extractor.clone().container(container), &stego),
+                blind_ber: ber(&extractor, &stego),
+            });
+        }
+    }
+
+    match last_error {
+        Some(e) if !embedded_any => Err(e),
+        _ => Ok(rows),
+    }
+}
+
+// Таблица результатов в формате CSV
+pub fn to_csv(rows: &[BenchRow]) -> String {
+    let ber = |value: Option<f64>| value.map_or(String::new(), |v| format!("{}", v));
+    let mut csv = String::from("attack,strength,informed_ber,blind_ber\n");
+    for row in rows {
+        let attack = row.attack.map_or("none".to_string(), |a| a.to_string());
+        csv.push_str(&format!("{},{},{},{}\n", attack, row.strength, ber(row.informed_ber), ber(row.blind_ber)));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsss::SpreadSpectrum;
+    use crate::test_signals::sine;
+
+    fn container(len: usize) -> Vec<Vec<f64>> {
+        vec![sine(440.0, 0.01, len)]
+    }
+
+    #[test]
+    fn test_bench_reports_every_attack_and_strength() {
+        let attacks = [Attack::Scale { factor: 0.5 }, Attack::Noise { snr_db: -30.0 }, Attack::Crop { samples: 30_000 }];
+        let rows = bench(&container(40_000), 44100, b"bench", &SpreadSpectrum::default(), &attacks, &[0.0005, 0.005]).unwrap();
+
+        assert_eq!(rows.len(), 2 * (attacks.len() + 1));
+        for strength_rows in rows.chunks(attacks.len() + 1) {
+            // Без искажений и после масштабирования ошибок нет
+            assert_eq!(strength_rows[0].attack, None);
+            assert_eq!(strength_rows[0].informed_ber, Some(0.0));
+            assert_eq!(strength_rows[1].blind_ber, Some(0.0));
+            // Шум намного громче сигнала дает ошибки или срывает синхронизацию,
+            // а после потери большей части отсчетов сообщение не извлекается вовсе
+            assert!(strength_rows[2].informed_ber.is_none_or(|ber| ber > 0.1));
+            assert_eq!(strength_rows[3].informed_ber, None);
+        }
+
+        let csv = to_csv(&rows);
+        assert_eq!(csv.lines().count(), rows.len() + 1);
+        assert!(csv.contains("\nnoise:-30,0.005,"));
+    }
+
+    #[test]
+    fn test_bench_continues_after_failed_strength() {
+        let attacks = [Attack::Scale { factor: 0.5 }];
+        let rows = bench(&container(40_000), 44100, b"bench", &SpreadSpectrum::default(), &attacks, &[-0.005, 0.005]).unwrap();
+
+        assert_eq!(rows.len(), 2 * (attacks.len() + 1));
+        assert!(rows[..2].iter().all(|row| row.strength == -0.005 && row.informed_ber.is_none() && row.blind_ber.is_none()));
+        assert_eq!(rows[2].informed_ber, Some(0.0));
+
+        // Если не встроилось ни с одной силой, возвращается ошибка
+        let result = bench(&container(40_000), 44100, b"bench", &SpreadSpectrum::default(), &attacks, &[0.0]);
+        assert!(matches!(result, Err(StegoError::InvalidArgument(_))));
+    }
+}
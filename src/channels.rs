@@ -0,0 +1,132 @@
+// Политика встраивания в многоканальный контейнер. Сообщение встраивается в один
+// поток отсчетов, который формируется из каналов и затем переносится обратно.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelMode {
+    // Только первый (левый) канал, остальные не изменяются
+    Left,
+    // Отсчеты всех каналов по очереди, кадр за кадром: емкость растет пропорционально
+    // количеству каналов, а сдвиг во времени одинаково сдвигает весь поток
+    All,
+    // Средний сигнал (L + R) / 2 первых двух каналов, разностный не изменяется
+    MidSide,
+}
+
+impl ChannelMode {
+    pub const MODES: [ChannelMode; 3] = [ChannelMode::Left, ChannelMode::All, ChannelMode::MidSide];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelMode::Left => "left",
+            ChannelMode::All => "all",
+            ChannelMode::MidSide => "mid-side",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        ChannelMode::MODES.into_iter().find(|mode| mode.name() == name)
+    }
+
+    // Поток отсчетов, в который встраивается сообщение.
+    // Для монофонического файла все политики совпадают.
+    pub fn stream(&self, channels: &[Vec<f64>]) -> Vec<f64> {
+        match (self, channels) {
+            (_, []) => Vec::new(),
+            (ChannelMode::All, channels) => (0..channels[0].len())
+                .flat_map(|t| channels.iter().map(move |channel| channel[t]))
+                .collect(),
+            (ChannelMode::MidSide, [left, right, ..]) => left.iter()
+                .zip(right.iter())
+                .map(|(&l, &r)| (l + r) / 2.0)
+                .collect(),
+            (_, [first, ..]) => first.clone(),
+        }
+    }
+
+    // Перенос измененного потока обратно в каналы контейнера
+    pub fn merge(&self, channels: &[Vec<f64>], stream: &[f64]) -> Vec<Vec<f64>> {
+        let mut channels = channels.to_vec();
+
+        match (self, channels.as_mut_slice()) {
+            (_, []) => {}
+            (ChannelMode::All, channels) => {
+                let count = channels.len();
+                for (i, &sample) in stream.iter().enumerate() {
+                    channels[i % count][i / count] = sample;
+                }
+            }
+            // Изменение среднего сигнала добавляется в оба канала, поэтому разностный сигнал сохраняется
+            (ChannelMode::MidSide, [left, right, ..]) => {
+                for ((l, r), &m) in left.iter_mut().zip(right.iter_mut()).zip(stream.iter()) {
+                    let delta = m - (*l + *r) / 2.0;
+                    *l += delta;
+                    *r += delta;
+                }
+            }
+            (_, [first, ..]) => first.copy_from_slice(stream),
+        }
+
+        channels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stereo() -> Vec<Vec<f64>> {
+        vec![vec![0.1, 0.2, 0.3], vec![-0.1, 0.4, 0.5]]
+    }
+
+    #[test]
+    fn test_left_mode_changes_only_first_channel() {
+        let channels = stereo();
+        let stream = ChannelMode::Left.stream(&channels);
+        assert_eq!(stream, channels[0]);
+
+        let merged = ChannelMode::Left.merge(&channels, &[1.0, 2.0, 3.0]);
+        assert_eq!(merged[0], vec![1.0, 2.0, 3.0]);
+        assert_eq!(merged[1], channels[1]);
+    }
+
+    #[test]
+    fn test_all_mode_uses_every_channel() {
+        let channels = stereo();
+        let stream = ChannelMode::All.stream(&channels);
+        assert_eq!(stream, vec![0.1, -0.1, 0.2, 0.4, 0.3, 0.5]);
+
+        let modified: Vec<f64> = stream.iter().map(|x| x + 1.0).collect();
+        let merged = ChannelMode::All.merge(&channels, &modified);
+        assert_eq!(merged[0], vec![1.1, 1.2, 1.3]);
+        assert_eq!(merged[1], vec![0.9, 1.4, 1.5]);
+    }
+
+    #[test]
+    fn test_mid_side_mode_preserves_side_signal() {
+        let channels = stereo();
+        let mid = ChannelMode::MidSide.stream(&channels);
+
+        let modified: Vec<f64> = mid.iter().map(|x| x + 0.01).collect();
+        let merged = ChannelMode::MidSide.merge(&channels, &modified);
+
+        let sides = channels[0].iter().zip(channels[1].iter());
+        let new_sides = merged[0].iter().zip(merged[1].iter());
+        for ((l, r), (new_l, new_r)) in sides.zip(new_sides) {
+            assert!(((l - r) / 2.0 - (new_l - new_r) / 2.0).abs() < 1e-12);
+        }
+
+        let new_mid = ChannelMode::MidSide.stream(&merged);
+        for (a, b) in new_mid.iter().zip(modified.iter()) {
+            assert!((a - b).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_modes_match_on_mono() {
+        let channels = vec![vec![0.1, 0.2, 0.3]];
+
+        for mode in ChannelMode::MODES {
+            assert_eq!(mode.stream(&channels), channels[0]);
+            assert_eq!(mode.merge(&channels, &[0.0, 0.0, 0.0]), vec![vec![0.0, 0.0, 0.0]]);
+        }
+    }
+}
@@ -0,0 +1,7 @@
+// Общий интерфейс источников ПСП. Идентификатор генератора сохраняется в файле ключа,
+// чтобы при извлечении восстановить ту же последовательность.
+pub trait ChipGenerator {
+    fn id(&self) -> &'static str;
+
+    fn generate_prs(&mut self, num: usize) -> Vec<i16>;
+}
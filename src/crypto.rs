@@ -0,0 +1,91 @@
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use pbkdf2::pbkdf2_hmac;
+use sha2::Sha256;
+use crate::error::StegoError;
+
+pub const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const PBKDF2_ROUNDS: u32 = 100_000;
+// На сколько байт зашифрованное сообщение длиннее исходного
+pub const ENVELOPE_OVERHEAD: usize = SALT_LEN + NONCE_LEN + TAG_LEN;
+
+// Вывод 256-битного ключа из пароля
+pub fn derive_key(passphrase: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    key
+}
+
+pub fn random_bytes<const N: usize>() -> std::io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes).map_err(std::io::Error::from)?;
+    Ok(bytes)
+}
+
+// Шифрование сообщения перед встраиванием: ChaCha20-Poly1305 с ключом из пароля.
+// Результат: | соль (16) | nonce (12) | шифртекст | тег (16) |
+pub fn encrypt_payload(plaintext: &[u8], passphrase: &str) -> std::io::Result<Vec<u8>> {
+    let salt: [u8; SALT_LEN] = random_bytes()?;
+    let nonce: [u8; NONCE_LEN] = random_bytes()?;
+
+    let key = derive_key(passphrase, &salt);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| std::io::Error::other("Ошибка шифрования сообщения"))?;
+
+    let mut envelope = Vec::with_capacity(SALT_LEN + NONCE_LEN + ciphertext.len());
+    envelope.extend_from_slice(&salt);
+    envelope.extend_from_slice(&nonce);
+    envelope.extend_from_slice(&ciphertext);
+    Ok(envelope)
+}
+
+pub fn decrypt_payload(envelope: &[u8], passphrase: &str) -> Result<Vec<u8>, StegoError> {
+    if envelope.len() < SALT_LEN + NONCE_LEN + TAG_LEN {
+        return Err(StegoError::Decryption("зашифрованное сообщение обрезано"));
+    }
+
+    let (salt, rest) = envelope.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let key = derive_key(passphrase, salt);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| StegoError::Decryption("не пройдена проверка аутентификации: неверный пароль или сообщение повреждено"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_round_trip() {
+        let plaintext = "Секретное сообщение".as_bytes();
+        let envelope = encrypt_payload(plaintext, "пароль").expect("Шифрование не должно падать");
+
+        assert_eq!(envelope.len(), SALT_LEN + NONCE_LEN + plaintext.len() + TAG_LEN);
+        assert_ne!(&envelope[SALT_LEN + NONCE_LEN..SALT_LEN + NONCE_LEN + plaintext.len()], plaintext);
+
+        let decrypted = decrypt_payload(&envelope, "пароль").expect("Расшифрование не должно падать");
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn test_wrong_passphrase_fails_authentication() {
+        let envelope = encrypt_payload(b"Hi", "right").unwrap();
+
+        let err = decrypt_payload(&envelope, "wrong").unwrap_err();
+        assert!(err.to_string().contains("аутентификации"));
+    }
+
+    #[test]
+    fn test_tampered_payload_fails_authentication() {
+        let mut envelope = encrypt_payload(b"Hi", "right").unwrap();
+        let last = envelope.len() - 1;
+        envelope[last] ^= 1;
+
+        assert!(decrypt_payload(&envelope, "right").is_err());
+    }
+}
@@ -1,77 +1,277 @@
 use bitvec::order::Msb0;
 use bitvec::prelude::BitVec;
-use crate::utils::DecryptData;
+use crate::channels::ChannelMode;
+use crate::error::StegoError;
+use crate::fec::FecConfig;
+use crate::header::{StegoHeader, HEADER_BITS, HEADER_LEN};
+use crate::method::Demodulated;
+use crate::sync::{find_preamble, find_preamble_informed, SyncPoint, PREAMBLE_BITS};
+use crate::transform::Domain;
 
-pub fn dest(data: &DecryptData, samples_per_msg_bit: usize, n: usize, m: usize) -> Vec<u8> {
-    let mut recovered_message_bitvec: BitVec<_, Msb0> = BitVec::new();
+// Отсчеты по каналам и ПСП, по которым извлекается сообщение.
+// Если исходный контейнер не передан, извлечение слепое.
+pub struct DespreadData<'a> {
+    pub container: Option<&'a [Vec<f64>]>,
+    pub stegocontainer: &'a [Vec<f64>],
+    pub key: &'a [i16],
+    pub channel_mode: ChannelMode,
+    pub domain: Domain,
+}
 
-    for i in 0..n * m {
-        let start = i * samples_per_msg_bit;
-        let end = (i + 1) * samples_per_msg_bit;
+pub fn dest(data: &DespreadData, samples_per_msg_bit: usize, n: usize, m: usize) -> Result<Vec<u8>, StegoError> {
+    Ok(bits_from_correlations(&despread(data, samples_per_msg_bit, n, m)?.0))
+}
 
-        let segment_stego = &data.stegocontainer.amplitudes[start..end];
-        let segment_original = &data.container.amplitudes[start..end];
+// Извлечение сообщения со встроенным заголовком. Длина ПСП берется из ключа,
+// а количество бит на символ, длина сообщения и помехоустойчивый код - из заголовка.
+pub fn dest_with_header(data: &DespreadData) -> Result<(StegoHeader, Vec<u8>, SyncPoint), StegoError> {
+    let frame = Frame::locate(data, data.key.len())?;
+    let (header, message) = decode_frame(&frame)?;
+    Ok((header, message, frame.sync))
+}
 
-        let res: Vec<f64> = segment_stego.iter()
-            .zip(segment_original.iter())
-            .map(|(&x, &y)| (x - y) / (y + 2.0))
-            .collect();
+// Заголовок и сообщение из мягких решений по битам кадра, найденного любым алгоритмом
+// встраивания (см. StegoHeader::encode_frame). Нехватка бит после найденного начала кадра -
+// ошибка заголовка, а не малого контейнера: обычно это ложный пик преамбулы или чужой ключ.
+pub fn decode_frame(frame: &dyn Demodulated) -> Result<(StegoHeader, Vec<u8>), StegoError> {
+    if frame.capacity() < HEADER_BITS {
+        return Err(StegoError::Header(format!(
+            "заголовок не помещается после найденного начала кадра (требуется {} бит, доступно {}): неверный ключ или ложный пик преамбулы",
+            HEADER_BITS, frame.capacity(),
+        )));
+    }
+
+    let header_bytes = FecConfig::HEADER.decode(&frame.soft_bits(0, HEADER_BITS)?, HEADER_LEN)?;
+    let header = StegoHeader::from_bytes(&header_bytes)?;
+
+    if header.frame_bits() > frame.capacity() {
+        return Err(StegoError::Header(format!(
+            "кадр обрезан: по заголовку требуется {} бит, доступно {}", header.frame_bits(), frame.capacity(),
+        )));
+    }
+
+    // Мягкое сложение копий: решения по одному и тому же биту складываются, поэтому
+    // надежные решения перевешивают ошибочные (взвешенное голосование)
+    let coded_bits = header.coded_payload_bits();
+    let mut correlations = frame.soft_bits(HEADER_BITS, coded_bits)?;
+    for start in repeated_copies(&header, frame.capacity()) {
+        let available = (frame.capacity() - start - HEADER_BITS).min(coded_bits);
+        for (sum, soft) in correlations.iter_mut().zip(frame.soft_bits(start + HEADER_BITS, available)?) {
+            *sum += soft;
+        }
+    }
+
+    let mut message = header.fec.decode(&correlations, header.padded_payload_len())?;
+    message.truncate(header.payload_len as usize);
+    header.verify(&message)?;
+
+    Ok((header, message))
+}
+
+// Начала копий кадра после первой, в которых есть хотя бы один бит сообщения
+pub fn repeated_copies(header: &StegoHeader, capacity: usize) -> impl Iterator<Item = usize> {
+    copy_starts(header.frame_bits(), if header.is_repeated() { capacity } else { 0 })
+}
+
+// Количество копий кадра с хотя бы одним битом сообщения вместе с первой: столько же
+// копий складывает decode_frame при той же емкости
+pub fn frame_copies(frame_bits: usize, capacity: usize) -> usize {
+    1 + copy_starts(frame_bits, capacity).count()
+}
+
+fn copy_starts(frame_bits: usize, capacity: usize) -> impl Iterator<Item = usize> {
+    (frame_bits..capacity.saturating_sub(HEADER_BITS)).step_by(frame_bits.max(1))
+}
+
+// Значения корреляции сегментов с ПСП для каждого бита сообщения и найденное начало кадра.
+// Знак корреляции определяет бит, а модуль показывает запас решения.
+// Значения нормированы на длину сегмента.
+pub fn despread(data: &DespreadData, samples_per_msg_bit: usize, n: usize, m: usize) -> Result<(Vec<f64>, SyncPoint), StegoError> {
+    if n * m == 0 {
+        return Ok((Vec::new(), SyncPoint { offset: 0, peak: 0.0 }));
+    }
+    let frame = Frame::locate(data, samples_per_msg_bit)?;
+    Ok((frame.soft_bits(0, n * m)?, frame.sync))
+}
+
+pub(crate) fn bits_from_correlations(correlations: &[f64]) -> Vec<u8> {
+    let bitvec: BitVec<u8, Msb0> = correlations.iter()
+        .map(|&correlation| correlation > 0.0)
+        .collect();
 
-        let b = if (res[0] > 0.0 && data.key[0] > 0) || (res[0] < 0.0 && data.key[0] < 0) {
-            true
-        } else {
-            false
+    bitvec.into_vec()
+}
+
+// Поток, по которому принимаются решения, и найденное в нем начало кадра (преамбула)
+pub(crate) struct Frame {
+    decision: Vec<f64>,
+    sync: SyncPoint,
+    key: Vec<i16>,
+    samples_per_msg_bit: usize,
+}
+
+impl Frame {
+    // При наличии исходного контейнера решения принимаются по остатку (x - y) / (y + 2),
+    // в котором остается только вложенная ПСП; контейнер при этом сдвигается на найденную
+    // задержку стегаконтейнера. В областях преобразований поток состоит из коэффициентов
+    // (см. Domain::coefficients).
+    // При слепом извлечении коррелируется сам стегаконтейнер, а контейнер выступает как шум,
+    // который подавляется за счет длины ПСП.
+    pub(crate) fn locate(data: &DespreadData, samples_per_msg_bit: usize) -> Result<Frame, StegoError> {
+        let stego_stream = data.channel_mode.stream(data.stegocontainer);
+        if samples_per_msg_bit == 0 || stego_stream.is_empty() {
+            return Err(StegoError::ContainerTooSmall { required: PREAMBLE_BITS, available: 0 });
+        }
+
+        let (decision, sync) = match (data.container, data.domain) {
+            (Some(container), Domain::Time) => {
+                let stream = data.channel_mode.stream(container);
+                let sync = find_preamble_informed(&stream, &stego_stream, data.key, samples_per_msg_bit);
+                (residual(&stream, &stego_stream, sync.offset), sync)
+            }
+            // В областях преобразований кадры контейнера и стегаконтейнера считаются совпадающими.
+            // Разность коэффициентов s * chip * |c| уже взвешена модулем коэффициента, как в
+            // согласованном фильтре: слабые коэффициенты, которые кодек огрубляет сильнее всего, весят меньше
+            (Some(container), domain) => {
+                let coefficients = domain.coefficients(&data.channel_mode.stream(container), data.key);
+                let decision: Vec<f64> = domain.coefficients(&stego_stream, data.key).iter().zip(&coefficients)
+                    .map(|(x, y)| x - y)
+                    .collect();
+                let sync = find_preamble(&decision, data.key, samples_per_msg_bit);
+                (decision, sync)
+            }
+            (None, domain) => {
+                let decision = domain.coefficients(&stego_stream, data.key);
+                let sync = find_preamble(&decision, data.key, samples_per_msg_bit);
+                (decision, sync)
+            }
         };
-        recovered_message_bitvec.push(b);
+
+        Ok(Frame { decision, sync, key: data.key.to_vec(), samples_per_msg_bit })
     }
+}
 
-    recovered_message_bitvec.into_vec()
+impl Demodulated for Frame {
+    // Количество бит после преамбулы, сегменты которых хотя бы начинаются внутри потока
+    fn capacity(&self) -> usize {
+        let remaining = self.decision.len() as isize - self.sync.offset;
+        if remaining <= 0 {
+            return 0;
+        }
+        (remaining as usize).div_ceil(self.samples_per_msg_bit).saturating_sub(PREAMBLE_BITS)
+    }
+
+    // Отсчеты за пределами потока (обрезанные) считаются нулевыми
+    fn soft_bits(&self, first_bit: usize, bits_num: usize) -> Result<Vec<f64>, StegoError> {
+        if first_bit + bits_num > self.capacity() {
+            return Err(StegoError::ContainerTooSmall { required: first_bit + bits_num, available: self.capacity() });
+        }
+
+        let n = self.samples_per_msg_bit;
+        let correlations = (first_bit..first_bit + bits_num)
+            .map(|i| {
+                let start = self.sync.offset + ((PREAMBLE_BITS + i) * n) as isize;
+                let correlation: f64 = self.key.iter().take(n).enumerate()
+                    .filter_map(|(j, &c)| {
+                        let position = start + j as isize;
+                        (position >= 0).then(|| self.decision.get(position as usize)).flatten().map(|x| x * c as f64)
+                    })
+                    .sum();
+                correlation / n as f64
+            })
+            .collect();
+
+        Ok(correlations)
+    }
+
+    fn sync(&self) -> Option<SyncPoint> {
+        Some(self.sync)
+    }
+}
+
+// Остаток (x - y) / (y + 2) при задержке стегаконтейнера delay относительно контейнера;
+// там, где контейнера нет, остаток нулевой
+fn residual(container: &[f64], stegocontainer: &[f64], delay: isize) -> Vec<f64> {
+    stegocontainer.iter().enumerate()
+        .map(|(j, &x)| {
+            let i = j as isize - delay;
+            if i >= 0 && (i as usize) < container.len() {
+                let y = container[i as usize];
+                (x - y) / (y + 2.0)
+            } else {
+                0.0
+            }
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use bitvec::prelude::*;
-    use crate::utils::{DecryptData, WavFile};
+    use crate::chip_generator::ChipGenerator;
+    use crate::fec::InnerCode;
+    use crate::park_miller_prng::ParkMiller;
+    use crate::st::{st, st_with_header, Spreading};
+    use crate::sync::preamble_template;
+    use crate::test_signals::{requantize_16bit, sine};
 
-    // Вспомогательная функция для создания тестовых данных
-    fn create_test_data() -> DecryptData {
-        let container = WavFile {
-            name: "original.wav".to_string(),
-            amplitudes: vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8],
-            bits_per_sample: 16,
-            channels: 1,
-            sample_rate: 44100,
-            samples_num: 8,
-        };
+    // Отсчеты и ключ, которыми владеет тест; DespreadData их только заимствует
+    struct TestData {
+        container: Option<Vec<Vec<f64>>>,
+        stegocontainer: Vec<Vec<f64>>,
+        key: Vec<i16>,
+        channel_mode: ChannelMode,
+    }
 
-        let stegocontainer = WavFile {
-            name: "stego.wav".to_string(),
-            amplitudes: vec![0.1001, 0.1999, 0.3001, 0.3999, 0.5001, 0.5999, 0.7001, 0.7999],
-            bits_per_sample: 16,
-            channels: 1,
-            sample_rate: 44100,
-            samples_num: 8,
-        };
+    impl TestData {
+        fn view(&self) -> DespreadData<'_> {
+            DespreadData {
+                container: self.container.as_deref(),
+                stegocontainer: &self.stegocontainer,
+                key: &self.key,
+                channel_mode: self.channel_mode,
+                domain: Domain::Time,
+            }
+        }
+    }
+
+    // Вспомогательная функция для создания тестовых данных
+    fn create_test_data() -> TestData {
+        let container = vec![vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8]];
+        let stegocontainer = vec![vec![0.1001, 0.1999, 0.3001, 0.3999, 0.5001, 0.5999, 0.7001, 0.7999]];
 
         let key = vec![1, -1, 1, -1, 1, -1, 1, -1];  // Псевдослучайная последовательность (PSP)
 
-        DecryptData {
-            container,
+        TestData {
+            container: Some(container),
             stegocontainer,
             key,
+            channel_mode: ChannelMode::Left,
         }
     }
 
+    // Дописывает в начало контейнера и стегаконтейнера сегменты с преамбулой,
+    // как это делает st для вручную собранных тестовых данных
+    fn prepend_preamble(data: &mut TestData, samples_per_msg_bit: usize) {
+        let template = preamble_template(&data.key, samples_per_msg_bit);
+        let original: Vec<f64> = (0..template.len()).map(|t| 0.05 * (t % 7) as f64).collect();
+        let embedded: Vec<f64> = original.iter().zip(&template).map(|(&y, &c)| y + c * 0.0005 * (y + 2.0)).collect();
+
+        let container = &mut data.container.as_mut().unwrap()[0];
+        container.splice(0..0, original);
+        data.stegocontainer[0].splice(0..0, embedded);
+    }
+
     #[test]
     fn test_dest_with_non_empty_message() {
-        let data = create_test_data();
+        let mut data = create_test_data();
+        prepend_preamble(&mut data, 1);
         let n = 8;  // 8 бит в сообщении
         let m = 1;  // 1 байт в сообщении
         let samples_per_msg_bit = 1;  // 1 сэмпл на бит
 
         // Вызов функции dest для извлечения сообщения
-        let recovered_message = dest(&data, samples_per_msg_bit, n, m);
+        let recovered_message = dest(&data.view(), samples_per_msg_bit, n, m).unwrap();
 
         // Ожидаемое сообщение (в битах)
         let expected_message = vec![0b10101010];  // Пример сообщения (1 байт)
@@ -82,30 +282,16 @@ mod tests {
 
     #[test]
     fn test_dest_with_empty_message() {
-        let container = WavFile {
-            name: "original.wav".to_string(),
-            amplitudes: vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8],
-            bits_per_sample: 16,
-            channels: 1,
-            sample_rate: 44100,
-            samples_num: 8,
-        };
-
-        let stegocontainer = WavFile {
-            name: "stego.wav".to_string(),
-            amplitudes: vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8],  // Амплитуды не изменены
-            bits_per_sample: 16,
-            channels: 1,
-            sample_rate: 44100,
-            samples_num: 8,
-        };
+        let container = vec![vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8]];
+        let stegocontainer = container.clone();  // Амплитуды не изменены
 
         let key = vec![1, -1, 1, -1, 1, -1, 1, -1];  // Псевдослучайная последовательность (PSP)
 
-        let data = DecryptData {
-            container,
+        let data = TestData {
+            container: Some(container),
             stegocontainer,
             key,
+            channel_mode: ChannelMode::Left,
         };
 
         let n = 8;  // 8 бит в сообщении
@@ -113,7 +299,7 @@ mod tests {
         let samples_per_msg_bit = 1;  // 1 сэмпл на бит
 
         // Вызов функции dest для извлечения сообщения
-        let recovered_message = dest(&data, samples_per_msg_bit, n, m);
+        let recovered_message = dest(&data.view(), samples_per_msg_bit, n, m).unwrap();
 
         // Ожидаемое сообщение (пустое)
         let expected_message: Vec<u8> = vec![];
@@ -121,4 +307,307 @@ mod tests {
         // Проверяем, что извлеченное сообщение пустое
         assert_eq!(recovered_message, expected_message);
     }
-}
\ No newline at end of file
+
+    // Тихий синусоидальный контейнер, на котором слепое извлечение должно работать
+    fn create_quiet_container(samples_num: usize) -> Vec<Vec<f64>> {
+        vec![sine(440.0, 0.001, samples_num)]
+    }
+
+    #[test]
+    fn test_dest_blind_round_trip() {
+        let message = "Hi!".as_bytes().to_vec();
+        let bits_per_char = 8;
+        let samples_per_msg_bit = 2000;
+        let container = create_quiet_container(samples_per_msg_bit * (PREAMBLE_BITS + bits_per_char * message.len()));
+
+        let mut generator = ParkMiller::with_seed(42).unwrap();
+        let psp = generator.generate_prs(samples_per_msg_bit);
+
+        let stego_amplitudes = st(&container, &message, &Spreading::new(&psp, samples_per_msg_bit), bits_per_char, message.len()).unwrap();
+
+        // Исходный контейнер на стороне получателя отсутствует
+        let data = TestData {
+            container: None,
+            stegocontainer: stego_amplitudes,
+            key: psp,
+            channel_mode: ChannelMode::Left,
+        };
+
+        let recovered_message = dest(&data.view(), samples_per_msg_bit, bits_per_char, message.len()).unwrap();
+        assert_eq!(recovered_message, message);
+    }
+
+    #[test]
+    fn test_dest_uses_whole_segment() {
+        let mut data = create_test_data();
+        let samples_per_msg_bit = 4;
+        data.key = vec![1, -1, -1, 1];
+
+        // Бит 1 в первом сегменте и бит 0 во втором, но первый отсчет каждого сегмента искажен
+        let original = data.container.as_ref().unwrap()[0].clone();
+        let embedded = [-1.0, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0, -1.0];
+        data.stegocontainer = vec![original.iter()
+            .zip(embedded.iter())
+            .map(|(&y, &e)| y + e * 0.0005 * (y + 2.0))
+            .collect()];
+        prepend_preamble(&mut data, samples_per_msg_bit);
+
+        let (correlations, sync) = despread(&data.view(), samples_per_msg_bit, 2, 1).unwrap();
+        assert_eq!(sync.offset, 0);
+        assert!(correlations[0] > 0.0);
+        assert!(correlations[1] < 0.0);
+
+        let recovered_message = dest(&data.view(), samples_per_msg_bit, 2, 1).unwrap();
+        assert_eq!(recovered_message, vec![0b10000000]);
+    }
+
+    #[test]
+    fn test_dest_survives_16bit_requantization() {
+        let message = "Привет".as_bytes().to_vec();
+        let bits_per_char = 8;
+        let samples_per_msg_bit = 64;
+        let container = create_quiet_container(samples_per_msg_bit * (PREAMBLE_BITS + bits_per_char * message.len()));
+        let container = requantize_16bit(&container[..1]);
+
+        let mut generator = ParkMiller::with_seed(42).unwrap();
+        let psp = generator.generate_prs(samples_per_msg_bit);
+
+        let stego_amplitudes = st(&container, &message, &Spreading::new(&psp, samples_per_msg_bit), bits_per_char, message.len()).unwrap();
+
+        let data = TestData {
+            stegocontainer: requantize_16bit(&stego_amplitudes[..1]),
+            container: Some(container),
+            key: psp,
+            channel_mode: ChannelMode::Left,
+        };
+
+        let (correlations, _) = despread(&data.view(), samples_per_msg_bit, bits_per_char, message.len()).unwrap();
+        assert!(correlations.iter().all(|c| c.abs() > 0.0002));
+
+        let recovered_message = dest(&data.view(), samples_per_msg_bit, bits_per_char, message.len()).unwrap();
+        assert_eq!(recovered_message, message);
+    }
+
+    #[test]
+    fn test_dest_with_header_round_trip() {
+        let message = "Ключ".as_bytes().to_vec();
+        let samples_per_msg_bit = 500;
+        let header = StegoHeader::new(&message, 16, 0, FecConfig::NONE);
+        let container = create_quiet_container(samples_per_msg_bit * (PREAMBLE_BITS + header.frame_bits()) + 123);
+
+        let mut generator = ParkMiller::with_seed(42).unwrap();
+        let psp = generator.generate_prs(samples_per_msg_bit);
+
+        let stego_amplitudes = st_with_header(&container, &message, &Spreading::new(&psp, samples_per_msg_bit), &header).unwrap();
+
+        // Параметры n и m не передаются: они восстанавливаются из заголовка
+        let data = TestData {
+            container: None,
+            stegocontainer: stego_amplitudes,
+            key: psp,
+            channel_mode: ChannelMode::Left,
+        };
+
+        let (header, recovered_message, sync) = dest_with_header(&data.view()).expect("Заголовок должен быть найден");
+        assert_eq!(sync.offset, 0);
+        assert_eq!(header.bits_per_symbol, 16);
+        assert_eq!(header.payload_len as usize, message.len());
+        assert_eq!(recovered_message, message);
+    }
+
+    #[test]
+    fn test_dest_with_header_rejects_legacy_container() {
+        let message = "Hi".as_bytes().to_vec();
+        let samples_per_msg_bit = 500;
+        let container = create_quiet_container(samples_per_msg_bit * (PREAMBLE_BITS + HEADER_BITS));
+
+        let mut generator = ParkMiller::with_seed(42).unwrap();
+        let psp = generator.generate_prs(samples_per_msg_bit);
+
+        let stego_amplitudes = st(&container, &message, &Spreading::new(&psp, samples_per_msg_bit), 8, message.len()).unwrap();
+
+        let data = TestData {
+            container: None,
+            stegocontainer: stego_amplitudes,
+            key: psp,
+            channel_mode: ChannelMode::Left,
+        };
+
+        assert!(matches!(dest_with_header(&data.view()), Err(StegoError::Header(_))));
+    }
+
+    // Инвертирует вложенную ПСП в указанных сегментах после преамбулы,
+    // что переворачивает соответствующие биты
+    fn flip_segments(data: &mut TestData, samples_per_msg_bit: usize, segments: &[usize]) {
+        let original = &data.container.as_ref().unwrap()[0];
+        for &segment in segments {
+            let segment = PREAMBLE_BITS + segment;
+            let range = segment * samples_per_msg_bit..(segment + 1) * samples_per_msg_bit;
+            for (stego, &y) in data.stegocontainer[0][range.clone()].iter_mut().zip(&original[range]) {
+                *stego = 2.0 * y - *stego;
+            }
+        }
+    }
+
+    fn embed_with_fec(message: &[u8], fec: FecConfig, samples_per_msg_bit: usize) -> TestData {
+        let header = StegoHeader::new(message, 8, 0, fec);
+        let container = create_quiet_container(samples_per_msg_bit * (PREAMBLE_BITS + header.frame_bits()));
+
+        let mut generator = ParkMiller::with_seed(7).unwrap();
+        let psp = generator.generate_prs(samples_per_msg_bit);
+
+        let stego_amplitudes = st_with_header(&container, message, &Spreading::new(&psp, samples_per_msg_bit), &header).unwrap();
+
+        TestData {
+            stegocontainer: stego_amplitudes,
+            container: Some(container),
+            key: psp,
+            channel_mode: ChannelMode::Left,
+        }
+    }
+
+    #[test]
+    fn test_dest_without_fec_is_corrupted_by_flipped_segment() {
+        let message = b"Hello, FEC!";
+        let mut data = embed_with_fec(message, FecConfig::NONE, 8);
+
+        flip_segments(&mut data, 8, &[HEADER_BITS + 3]);
+        assert!(matches!(dest_with_header(&data.view()), Err(StegoError::Checksum)));
+    }
+
+    #[test]
+    fn test_dest_recovers_flipped_segments_with_fec() {
+        let message = b"Hello, FEC!";
+        let samples_per_msg_bit = 8;
+        let payload_bits = 8 * message.len();
+
+        // Для каждого кода: набор сегментов полезной нагрузки, которые будут испорчены
+        let cases: Vec<(FecConfig, Vec<usize>)> = vec![
+            (FecConfig { inner: InnerCode::Repetition(3), rs_parity: 0 }, (0..payload_bits).step_by(5).collect()),
+            (FecConfig { inner: InnerCode::Hamming74, rs_parity: 0 }, (0..payload_bits / 4).map(|b| b * 7 + 2).collect()),
+            (FecConfig { inner: InnerCode::Convolutional, rs_parity: 0 }, (0..2 * payload_bits).step_by(15).collect()),
+            (FecConfig { inner: InnerCode::None, rs_parity: 8 }, (0..24).chain(40..48).collect()),
+        ];
+
+        for (fec, flipped) in cases {
+            let mut data = embed_with_fec(message, fec, samples_per_msg_bit);
+            // В заголовке тоже портится по одному биту в кодовом слове Хэмминга
+            let mut segments: Vec<usize> = (0..HEADER_BITS).step_by(7).collect();
+            segments.extend(flipped.iter().map(|&s| HEADER_BITS + s));
+            flip_segments(&mut data, samples_per_msg_bit, &segments);
+
+            let (header, recovered_message, _) = dest_with_header(&data.view())
+                .unwrap_or_else(|e| panic!("Не удалось извлечь сообщение с кодом {:?}: {}", fec, e));
+            assert_eq!(header.fec, fec);
+            assert_eq!(recovered_message, message);
+        }
+    }
+
+    #[test]
+    fn test_dest_with_header_stereo_channel_modes() {
+        let message = b"Stereo";
+        let samples_per_msg_bit = 200;
+        let header = StegoHeader::new(message, 8, 0, FecConfig::NONE);
+
+        let mut generator = ParkMiller::with_seed(3).unwrap();
+        let psp = generator.generate_prs(samples_per_msg_bit);
+
+        for mode in ChannelMode::MODES {
+            // В режиме all емкость удваивается, поэтому каждый канал вдвое короче
+            let frame_samples = samples_per_msg_bit * (PREAMBLE_BITS + header.frame_bits());
+            let frames = if mode == ChannelMode::All {
+                frame_samples / 2 + 1
+            } else {
+                frame_samples
+            };
+            let left = create_quiet_container(frames).remove(0);
+            let right: Vec<f64> = left.iter().map(|x| -0.5 * x).collect();
+            let container = vec![left, right];
+
+            let stego_amplitudes = st_with_header(&container, message, &Spreading { channel_mode: mode, ..Spreading::new(&psp, samples_per_msg_bit) }, &header).unwrap();
+
+            if mode == ChannelMode::Left {
+                assert_eq!(stego_amplitudes[1], container[1]);
+            }
+
+            let data = TestData {
+                container: None,
+                stegocontainer: stego_amplitudes,
+                key: psp.clone(),
+                channel_mode: mode,
+            };
+
+            let (_, recovered_message, _) = dest_with_header(&data.view())
+                .unwrap_or_else(|e| panic!("Не удалось извлечь сообщение в режиме {}: {}", mode.name(), e));
+            assert_eq!(recovered_message, message);
+        }
+    }
+
+    #[test]
+    fn test_dest_rejects_short_stegocontainer() {
+        let data = create_test_data();
+
+        // Преамбула и n*m сегментов по N отсчетов не помещаются в стегаконтейнер
+        assert!(matches!(
+            dest(&data.view(), 2, 8, 1),
+            Err(StegoError::ContainerTooSmall { required: 8, .. })
+        ));
+    }
+
+    #[test]
+    fn test_dest_with_header_survives_cropping_and_delay() {
+        let message = b"Sync";
+        let samples_per_msg_bit = 64;
+        let header = StegoHeader::new(message, 8, 0, FecConfig::NONE);
+        let frame_samples = samples_per_msg_bit * (PREAMBLE_BITS + header.frame_bits());
+        let container = create_quiet_container(frame_samples + 500);
+
+        let psp = ParkMiller::with_seed(11).unwrap().generate_prs(samples_per_msg_bit);
+        let stego = st_with_header(&container, message, &Spreading::new(&psp, samples_per_msg_bit), &header).unwrap();
+
+        // Обрезка конца и начала (часть преамбулы теряется) и задержка
+        let cropped_end = stego[0][..frame_samples - 5].to_vec();
+        let cropped_start = stego[0][3 * samples_per_msg_bit + 17..].to_vec();
+        let mut delayed = vec![0.0; 1234];
+        delayed.extend_from_slice(&stego[0]);
+
+        let cases = [(cropped_end, 0), (cropped_start, -(3 * samples_per_msg_bit as isize) - 17), (delayed, 1234)];
+        for (stegocontainer, offset) in cases {
+            for informed in [true, false] {
+                let data = TestData {
+                    container: informed.then(|| container.clone()),
+                    stegocontainer: vec![stegocontainer.clone()],
+                    key: psp.clone(),
+                    channel_mode: ChannelMode::Left,
+                };
+
+                let (_, recovered_message, sync) = dest_with_header(&data.view())
+                    .unwrap_or_else(|e| panic!("Смещение {}, с контейнером: {}: {}", offset, informed, e));
+                assert_eq!(sync.offset, offset);
+                assert!(sync.peak > 0.0);
+                assert_eq!(recovered_message, message);
+            }
+        }
+    }
+
+    #[test]
+    fn test_truncated_frame_is_header_error() {
+        let message = b"Sync";
+        let samples_per_msg_bit = 64;
+        let header = StegoHeader::new(message, 8, 0, FecConfig::NONE);
+        let container = create_quiet_container(samples_per_msg_bit * (PREAMBLE_BITS + header.frame_bits()));
+        let psp = ParkMiller::with_seed(11).unwrap().generate_prs(samples_per_msg_bit);
+        let stego = st_with_header(&container, message, &Spreading::new(&psp, samples_per_msg_bit), &header).unwrap();
+
+        // Заголовок цел, но сообщение обрезано; затем не помещается и сам заголовок
+        for bits in [HEADER_BITS + 8, HEADER_BITS / 2] {
+            let data = TestData {
+                container: None,
+                stegocontainer: vec![stego[0][..samples_per_msg_bit * (PREAMBLE_BITS + bits)].to_vec()],
+                key: psp.clone(),
+                channel_mode: ChannelMode::Left,
+            };
+            assert!(matches!(dest_with_header(&data.view()), Err(StegoError::Header(_))), "{} бит", bits);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::sync::Arc;
+use crate::channels::ChannelMode;
+use crate::dest::{DespreadData, Frame};
+use crate::error::StegoError;
+use crate::method::{Demodulated, StegoMethod};
+use crate::st::{st_frame, Gain, Spreading};
+use crate::sync::PREAMBLE_BITS;
+use crate::transform::Domain;
+
+pub const DSSS_ID: &str = "dsss";
+
+// Расширение спектра (см. st и dest): каждый бит кадра умножается на ПСП длиной с ключ,
+// перед кадром встраивается синхронизирующая преамбула. Ключ делит контейнер поровну
+// между битами, поэтому его длина зависит от длины кадра.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpreadSpectrum {
+    pub channel_mode: ChannelMode,
+    pub domain: Domain,
+    // Если не задано, постоянная сила по умолчанию для выбранной области
+    pub gain: Option<Gain>,
+}
+
+impl Default for SpreadSpectrum {
+    fn default() -> Self {
+        SpreadSpectrum { channel_mode: ChannelMode::Left, domain: Domain::Time, gain: None }
+    }
+}
+
+impl SpreadSpectrum {
+    // Варианты для перебора при извлечении: указанные политика каналов и область или все
+    pub fn candidates(channel_mode: Option<ChannelMode>, domain: Option<Domain>) -> Vec<Arc<dyn StegoMethod>> {
+        let modes = match channel_mode {
+            Some(channel_mode) => vec![channel_mode],
+            None => ChannelMode::MODES.to_vec(),
+        };
+        let domains = match domain {
+            Some(domain) => vec![domain],
+            None => Domain::DOMAINS.to_vec(),
+        };
+
+        domains.iter()
+            .flat_map(|&domain| modes.iter().map(move |&channel_mode| {
+                Arc::new(SpreadSpectrum { channel_mode, domain, gain: None }) as Arc<dyn StegoMethod>
+            }))
+            .collect()
+    }
+
+    // Параметры: политика каналов и область, для fft еще границы полосы в долях частоты дискретизации
+    pub fn from_parameters(parameters: &[&str]) -> Result<Self, StegoError> {
+        let invalid = |what: &str| StegoError::MalformedKey(format!("параметры {}: {}", DSSS_ID, what));
+
+        let (mode, domain, band) = match parameters {
+            [mode, domain, band @ ..] => (mode, domain, band),
+            _ => return Err(invalid("ожидаются политика каналов и область встраивания")),
+        };
+        let channel_mode = ChannelMode::from_name(mode).ok_or_else(|| invalid("неизвестная политика каналов"))?;
+        let domain = match (Domain::from_name(domain), band) {
+            (Some(Domain::Fft { .. }), [low, high]) => {
+                let parse = |value: &str| value.parse::<f64>().map_err(|_| invalid("границы полосы должны быть числами"));
+                Domain::Fft { low: parse(low)?, high: parse(high)? }
+            }
+            (Some(Domain::Fft { .. }), _) => return Err(invalid("для fft ожидаются границы полосы")),
+            (Some(domain), []) => domain,
+            _ => return Err(invalid("неизвестная область встраивания")),
+        };
+
+        Ok(SpreadSpectrum { channel_mode, domain, gain: None })
+    }
+
+    fn stream_capacity(&self, container: &[Vec<f64>]) -> usize {
+        self.domain.capacity(self.channel_mode.stream(container).len())
+    }
+}
+
+impl StegoMethod for SpreadSpectrum {
+    fn id(&self) -> &'static str {
+        DSSS_ID
+    }
+
+    fn parameters(&self) -> Vec<String> {
+        let mut parameters = vec![self.channel_mode.name().to_string(), self.domain.name().to_string()];
+        if let Domain::Fft { low, high } = self.domain {
+            parameters.extend([low.to_string(), high.to_string()]);
+        }
+        parameters
+    }
+
+    // По одному элементу ПСП на бит
+    fn capacity(&self, container: &[Vec<f64>]) -> usize {
+        self.stream_capacity(container).saturating_sub(PREAMBLE_BITS)
+    }
+
+    fn key_len(&self, container: &[Vec<f64>], bits_num: usize) -> usize {
+        self.stream_capacity(container) / (PREAMBLE_BITS + bits_num)
+    }
+
+    fn capacity_for_key_len(&self, container: &[Vec<f64>], key_len: usize) -> usize {
+        self.stream_capacity(container).checked_div(key_len).unwrap_or(0).saturating_sub(PREAMBLE_BITS)
+    }
+
+    fn chips_per_bit(&self, key_len: usize) -> Option<usize> {
+        Some(key_len)
+    }
+
+    fn embed(&self, container: &[Vec<f64>], bits: &[bool], key: &[i16]) -> Result<Vec<Vec<f64>>, StegoError> {
+        let spreading = Spreading {
+            channel_mode: self.channel_mode,
+            gain: self.gain.unwrap_or(Gain::Fixed(self.domain.default_strength())),
+            domain: self.domain,
+            ..Spreading::new(key, key.len())
+        };
+        st_frame(container, bits, &spreading)
+    }
+
+    // Длина сегмента на бит равна длине ПСП, поэтому не зависит от обрезки стегаконтейнера
+    fn demodulate(&self, stegocontainer: &[Vec<f64>], container: Option<&[Vec<f64>]>, key: &[i16]) -> Result<Box<dyn Demodulated>, StegoError> {
+        let data = DespreadData { container, stegocontainer, key, channel_mode: self.channel_mode, domain: self.domain };
+        Ok(Box::new(Frame::locate(&data, key.len())?))
+    }
+
+    fn sync_unit(&self) -> &'static str {
+        match self.domain {
+            Domain::Time => "отсчетов",
+            Domain::Mdct => "коэффициентов MDCT",
+            Domain::Fft { .. } => "бинов БПФ",
+        }
+    }
+
+    fn with_strength(&self, strength: f64) -> Result<Arc<dyn StegoMethod>, StegoError> {
+        // Отрицательная сила инвертирует биты, нулевая ничего не встраивает
+        if !(strength > 0.0 && strength.is_finite()) {
+            return Err(StegoError::InvalidArgument(format!("сила встраивания {} должна быть положительной, получено {}", DSSS_ID, strength)));
+        }
+        Ok(Arc::new(SpreadSpectrum { gain: Some(Gain::Fixed(strength)), ..*self }))
+    }
+
+    fn bench_strengths(&self) -> Vec<f64> {
+        match self.domain {
+            Domain::Time => vec![0.0005, 0.002, 0.005],
+            Domain::Mdct => vec![0.05, 0.1, 0.2],
+            Domain::Fft { .. } => vec![0.1, 0.2, 0.4],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_len_splits_container_between_bits() {
+        let container = vec![vec![0.0; 10_000], vec![0.0; 10_000]];
+        let method = SpreadSpectrum::default();
+
+        assert_eq!(method.capacity(&container), 10_000 - PREAMBLE_BITS);
+        assert_eq!(method.key_len(&container, 87), 100);
+        assert_eq!(method.key_len(&container, 10_000), 0);
+
+        // В области MDCT позиций меньше, чем отсчетов
+        let mdct = SpreadSpectrum { domain: Domain::Mdct, ..method };
+        assert!(mdct.capacity(&container) < method.capacity(&container));
+    }
+
+    #[test]
+    fn test_candidates_cover_modes_and_domains() {
+        assert_eq!(SpreadSpectrum::candidates(None, None).len(), Domain::DOMAINS.len() * ChannelMode::MODES.len());
+
+        let candidates = SpreadSpectrum::candidates(Some(ChannelMode::All), Some(Domain::Mdct));
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].parameters(), ["all", "mdct"]);
+    }
+}
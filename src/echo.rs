@@ -0,0 +1,363 @@
+use std::sync::Arc;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use crate::channels::ChannelMode;
+use crate::error::StegoError;
+use crate::method::{limit_peak, Demodulated, StegoMethod};
+use crate::sync::SyncPoint;
+
+pub const ECHO_ID: &str = "echo";
+
+// Задержки эха по умолчанию в отсчетах: около 1.1 и 1.7 мс при 44.1 кГц. Эхо короче
+// нескольких миллисекунд на слух не отделяется от сигнала, а лишь слегка окрашивает тембр.
+pub const DEFAULT_DELAYS: (usize, usize) = (50, 75);
+// Затухание эха по умолчанию
+pub const DEFAULT_DECAY: f64 = 0.3;
+// Сегмент на бит должен быть заметно длиннее задержек, иначе кепстр сегмента слишком шумный
+pub const MIN_SEGMENT: usize = 1024;
+// Модуль спектра берется в шагах квантования 16 бит: логарифм 1 + |X| * QUANTUM_SCALE
+// не отрицателен, и пик автокепстра не меняет знак у тихих сегментов
+const QUANTUM_SCALE: f64 = 32768.0;
+
+// Сокрытие эхом. Каждый бит занимает сегмент из длины ключа отсчетов, и в сегмент
+// добавляется слабое эхо сигнала с задержкой delays.0 для бита 0 или delays.1 для бита 1:
+// y[n] = x[n] + decay * x[n - d]. Между сегментами эхо одной задержки плавно переходит
+// в эхо другой, чтобы на границах не было щелчков.
+//
+// Эхо умножает спектр сегмента на 1 + decay * e^(-jwd), поэтому в кепстре (обратное
+// преобразование логарифма модуля спектра) появляется пик около decay на отсчете d.
+// Решения принимаются по автокепстру - автокорреляции кепстра, то есть обратному
+// преобразованию квадрата логарифма модуля: пик эха в нем умножается на средний логарифм
+// и выделяется над кепстром самого сигнала. Бит определяется тем, какой из двух пиков
+// больше; исходный контейнер для этого не нужен, то есть извлечение слепое по построению.
+// Эхо переживает фильтрацию лучше аддитивной ПСП: фильтр одинаково умножает спектр
+// сигнала и эха и лишь сдвигает кепстр у нуля.
+//
+// Начало кадра не ищется: кадр начинается с первого отсчета, и после обрезки сегменты
+// уже не совпадают с битами.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EchoHiding {
+    // left и mid-side - эхо в одном потоке; all - эхо каждого канала в нем самом (стереокартина
+    // сохраняется), а извлечение ведется по среднему всех каналов
+    pub channel_mode: ChannelMode,
+    // Задержки эха для бит 0 и 1 в отсчетах
+    pub delays: (usize, usize),
+    pub decay: f64,
+}
+
+impl Default for EchoHiding {
+    fn default() -> Self {
+        EchoHiding { channel_mode: ChannelMode::Left, delays: DEFAULT_DELAYS, decay: DEFAULT_DECAY }
+    }
+}
+
+impl EchoHiding {
+    pub fn new(channel_mode: ChannelMode, delays: (usize, usize), decay: f64) -> Result<Self, StegoError> {
+        if delays.0 == 0 || delays.1 == 0 || delays.0 == delays.1 {
+            return Err(StegoError::InvalidArgument(format!("{}: задержки должны быть различными и положительными, получено {:?}", ECHO_ID, delays)));
+        }
+        // Без затухания эха нет, а эхо не тише сигнала уже слышно как повтор
+        if !(decay > 0.0 && decay < 1.0) {
+            return Err(StegoError::InvalidArgument(format!("{}: затухание эха должно быть в (0, 1), получено {}", ECHO_ID, decay)));
+        }
+        Ok(EchoHiding { channel_mode, delays, decay })
+    }
+
+    // Параметры: политика каналов и две задержки в отсчетах
+    pub fn from_parameters(parameters: &[&str]) -> Result<Self, StegoError> {
+        let invalid = |what: &str| StegoError::MalformedKey(format!("параметры {}: {}", ECHO_ID, what));
+
+        match parameters {
+            [mode, zero, one] => {
+                let channel_mode = ChannelMode::from_name(mode).ok_or_else(|| invalid("неизвестная политика каналов"))?;
+                let delay = |value: &str| value.parse::<usize>().map_err(|_| invalid("задержки должны быть целыми числами"));
+                let delays = (delay(zero)?, delay(one)?);
+                Self::new(channel_mode, delays, DEFAULT_DECAY).map_err(|_| invalid("задержки должны быть различными и положительными"))
+            }
+            _ => Err(invalid("ожидаются политика каналов и две задержки")),
+        }
+    }
+
+    fn frames(&self, container: &[Vec<f64>]) -> usize {
+        container.first().map_or(0, |channel| channel.len())
+    }
+
+    // Сегмент не короче MIN_SEGMENT и вмещает кепстр до большей задержки с запасом
+    fn min_segment(&self) -> usize {
+        MIN_SEGMENT.max(8 * self.delays.0.max(self.delays.1))
+    }
+
+    // Ключ короче сегмента не дает различить задержки эха (и короче самих задержек)
+    fn check_segment(&self, segment: usize) -> Result<(), String> {
+        if segment < self.min_segment() {
+            return Err(format!("{}: длина ключа {} меньше наименьшего сегмента {}", ECHO_ID, segment, self.min_segment()));
+        }
+        Ok(())
+    }
+
+    // Поток, по которому принимаются решения
+    fn decision_stream(&self, channels: &[Vec<f64>]) -> Vec<f64> {
+        match self.channel_mode {
+            ChannelMode::All if !channels.is_empty() => (0..self.frames(channels))
+                .map(|t| channels.iter().map(|channel| channel[t]).sum::<f64>() / channels.len() as f64)
+                .collect(),
+            mode => mode.stream(channels),
+        }
+    }
+
+    // Добавляет эхо в один поток: веса эха каждой задержки по отсчетам в mixer
+    fn add_echo(&self, stream: &[f64], mixer: &[(f64, f64)]) -> Vec<f64> {
+        let delayed = |n: usize, d: usize| if n >= d { stream[n - d] } else { 0.0 };
+        stream.iter().zip(mixer).enumerate()
+            .map(|(n, (&x, &(w0, w1)))| x + self.decay * (w0 * delayed(n, self.delays.0) + w1 * delayed(n, self.delays.1)))
+            .collect()
+    }
+}
+
+impl StegoMethod for EchoHiding {
+    fn id(&self) -> &'static str {
+        ECHO_ID
+    }
+
+    fn parameters(&self) -> Vec<String> {
+        vec![self.channel_mode.name().to_string(), self.delays.0.to_string(), self.delays.1.to_string()]
+    }
+
+    fn capacity(&self, container: &[Vec<f64>]) -> usize {
+        self.frames(container) / self.min_segment()
+    }
+
+    // Ключ задает длину сегмента на бит
+    fn key_len(&self, container: &[Vec<f64>], bits_num: usize) -> usize {
+        let segment = self.frames(container).checked_div(bits_num).unwrap_or(0);
+        if segment >= self.min_segment() { segment } else { 0 }
+    }
+
+    fn capacity_for_key_len(&self, container: &[Vec<f64>], key_len: usize) -> usize {
+        if key_len >= self.min_segment() { self.frames(container) / key_len } else { 0 }
+    }
+
+    // Бит определяется по автокепстру всего сегмента
+    fn chips_per_bit(&self, key_len: usize) -> Option<usize> {
+        Some(key_len)
+    }
+
+    fn embed(&self, container: &[Vec<f64>], bits: &[bool], key: &[i16]) -> Result<Vec<Vec<f64>>, StegoError> {
+        self.check_segment(key.len()).map_err(StegoError::InvalidArgument)?;
+        let capacity = self.capacity_for_key_len(container, key.len());
+        if bits.len() > capacity {
+            return Err(StegoError::ContainerTooSmall { required: bits.len(), available: capacity });
+        }
+        let mixer = mixer(self.frames(container), bits, key.len());
+        let mut stego: Vec<Vec<f64>> = match self.channel_mode {
+            ChannelMode::All => container.iter().map(|channel| self.add_echo(channel, &mixer)).collect(),
+            mode => mode.merge(container, &self.add_echo(&mode.stream(container), &mixer)),
+        };
+
+        // Эхо складывается с сигналом и на громком контейнере выходит за полную шкалу
+        limit_peak(&mut stego);
+        Ok(stego)
+    }
+
+    fn demodulate(&self, stegocontainer: &[Vec<f64>], _container: Option<&[Vec<f64>]>, key: &[i16]) -> Result<Box<dyn Demodulated>, StegoError> {
+        let segment = key.len();
+        self.check_segment(segment).map_err(StegoError::MalformedKey)?;
+
+        let stream = self.decision_stream(stegocontainer);
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(segment);
+        let ifft = planner.plan_fft_inverse(segment);
+        // Окно Ханна ослабляет края сегмента, где эхо переходит от одной задержки к другой
+        let window: Vec<f64> = (0..segment).map(|n| 0.5 - 0.5 * (2.0 * std::f64::consts::PI * n as f64 / segment as f64).cos()).collect();
+
+        let decisions = stream.chunks_exact(segment)
+            .map(|chunk| {
+                let mut buffer: Vec<Complex<f64>> = chunk.iter().zip(&window).map(|(x, w)| Complex::new(x * w, 0.0)).collect();
+                fft.process(&mut buffer);
+                // Автокорреляция кепстра - обратное преобразование квадрата логарифма модуля
+                for value in buffer.iter_mut() {
+                    *value = Complex::new((1.0 + value.norm() * QUANTUM_SCALE).ln().powi(2), 0.0);
+                }
+                ifft.process(&mut buffer);
+                // Нормировка на нулевой отсчет убирает зависимость от громкости сегмента
+                (buffer[self.delays.1].re - buffer[self.delays.0].re) / buffer[0].re.max(f64::MIN_POSITIVE)
+            })
+            .collect();
+
+        Ok(Box::new(EchoFrame { decisions }))
+    }
+
+    fn sync_unit(&self) -> &'static str {
+        "отсчетов"
+    }
+
+    // Сила встраивания - затухание эха
+    fn with_strength(&self, strength: f64) -> Result<Arc<dyn StegoMethod>, StegoError> {
+        Ok(Arc::new(Self::new(self.channel_mode, self.delays, strength)?))
+    }
+
+    fn bench_strengths(&self) -> Vec<f64> {
+        vec![0.1, 0.3, 0.5]
+    }
+}
+
+// Веса эха задержек 0 и 1 по отсчетам: по сегменту на бит, вне кадра эха нет. Переходы
+// между сегментами сглажены скользящим средним длиной в восьмую часть сегмента.
+fn mixer(len: usize, bits: &[bool], segment: usize) -> Vec<(f64, f64)> {
+    let target = |n: usize| match bits.get(n / segment.max(1)) {
+        Some(false) => (1.0, 0.0),
+        Some(true) => (0.0, 1.0),
+        None => (0.0, 0.0),
+    };
+
+    let ramp = (segment / 8).max(1);
+    let mut prefix = vec![(0.0, 0.0); len + 1];
+    for n in 0..len {
+        let (w0, w1) = target(n);
+        prefix[n + 1] = (prefix[n].0 + w0, prefix[n].1 + w1);
+    }
+
+    (0..len)
+        .map(|n| {
+            let start = n.saturating_sub(ramp / 2);
+            let end = (start + ramp).min(len);
+            let count = (end - start) as f64;
+            ((prefix[end].0 - prefix[start].0) / count, (prefix[end].1 - prefix[start].1) / count)
+        })
+        .collect()
+}
+
+// Разность автокепстра на задержках бит 1 и 0 для каждого сегмента
+struct EchoFrame {
+    decisions: Vec<f64>,
+}
+
+impl Demodulated for EchoFrame {
+    fn capacity(&self) -> usize {
+        self.decisions.len()
+    }
+
+    fn soft_bits(&self, first_bit: usize, bits_num: usize) -> Result<Vec<f64>, StegoError> {
+        self.decisions.get(first_bit..first_bit + bits_num)
+            .map(|decisions| decisions.to_vec())
+            .ok_or(StegoError::ContainerTooSmall { required: first_bit + bits_num, available: self.capacity() })
+    }
+
+    fn sync(&self) -> Option<SyncPoint> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attack::Attack;
+    use crate::method::MAX_PEAK;
+    use crate::test_signals::{bits, noise, requantize_16bit};
+
+    fn decode(method: &EchoHiding, stego: &[Vec<f64>], segment: usize, bits_num: usize) -> Vec<bool> {
+        let frame = method.demodulate(stego, None, &vec![1; segment]).unwrap();
+        frame.soft_bits(0, bits_num).unwrap().iter().map(|&d| d > 0.0).collect()
+    }
+
+    #[test]
+    fn test_mixer_switches_smoothly() {
+        let mixer = mixer(64, &[false, true], 16);
+        assert_eq!(mixer[4], (1.0, 0.0));
+        assert_eq!(mixer[20], (0.0, 1.0));
+        // На границе сегментов веса складываются в единицу, вне кадра эха нет
+        assert_eq!(mixer[16], (0.5, 0.5));
+        assert_eq!(mixer[40], (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_blind_round_trip() {
+        let container = vec![noise(64 * 1024, 1), noise(64 * 1024, 2)];
+        let bits = bits(60);
+
+        for channel_mode in ChannelMode::MODES {
+            let method = EchoHiding { channel_mode, ..EchoHiding::default() };
+            let segment = method.key_len(&container, bits.len());
+            assert!(segment >= MIN_SEGMENT);
+
+            let stego = method.embed(&container, &bits, &vec![1; segment]).unwrap();
+            assert_eq!(decode(&method, &stego, segment, bits.len()), bits, "{}", channel_mode.name());
+        }
+    }
+
+    #[test]
+    fn test_survives_lowpass_filter() {
+        let container = vec![noise(64 * 1024, 3)];
+        let method = EchoHiding::default();
+        let bits = bits(40);
+        let segment = method.key_len(&container, bits.len());
+
+        let stego = method.embed(&container, &bits, &vec![1; segment]).unwrap();
+        let filtered = Attack::LowPass { cutoff: 8000.0 }.apply(&stego, 44100);
+        assert_eq!(decode(&method, &filtered, segment, bits.len()), bits);
+    }
+
+    #[test]
+    fn test_rejects_short_key() {
+        let container = vec![noise(4096, 4)];
+        let method = EchoHiding::default();
+        assert!(matches!(method.demodulate(&container, None, &[1; 10]), Err(StegoError::MalformedKey(_))));
+        assert!(matches!(method.embed(&container, &bits(2), &[1; 10]), Err(StegoError::InvalidArgument(_))));
+        assert_eq!(method.capacity_for_key_len(&container, 10), 0);
+    }
+
+    #[test]
+    fn test_embed_rejects_extra_bits() {
+        let container = vec![noise(10 * MIN_SEGMENT, 3)];
+        let method = EchoHiding::default();
+        let key = vec![1; MIN_SEGMENT];
+
+        assert!(method.embed(&container, &bits(10), &key).is_ok());
+        assert!(matches!(method.embed(&container, &bits(11), &key), Err(StegoError::ContainerTooSmall { required: 11, available: 10 })));
+    }
+
+    #[test]
+    fn test_loud_cover_is_attenuated() {
+        // Контейнер с пиками у полной шкалы, как после сведения: эхо перегрузило бы его
+        let container = vec![noise(64 * 1024, 5).iter().map(|x| 2.0 * x).collect::<Vec<f64>>()];
+        let bits = bits(16);
+
+        for decay in [0.1, DEFAULT_DECAY] {
+            let method = EchoHiding { decay, ..EchoHiding::default() };
+            let segment = method.key_len(&container, bits.len());
+            let stego = requantize_16bit(&method.embed(&container, &bits, &vec![1; segment]).unwrap());
+            assert!(stego[0].iter().all(|x| x.abs() <= MAX_PEAK));
+            assert_eq!(decode(&method, &stego, segment, bits.len()), bits, "{}", decay);
+        }
+    }
+
+    #[test]
+    fn test_quiet_cover_round_trip() {
+        // Около -50 дБ: логарифм модуля спектра в долях полной шкалы здесь отрицателен
+        let container = vec![noise(64 * 1024, 9).iter().map(|x| 0.005 * x).collect::<Vec<f64>>()];
+        let bits = bits(16);
+        let method = EchoHiding::default();
+        let segment = method.key_len(&container, bits.len());
+
+        let stego = requantize_16bit(&method.embed(&container, &bits, &vec![1; segment]).unwrap());
+        assert_eq!(decode(&method, &stego, segment, bits.len()), bits);
+    }
+
+    #[test]
+    fn test_rejects_invalid_decay() {
+        for decay in [0.0, -0.3, 1.0, f64::NAN] {
+            assert!(matches!(EchoHiding::new(ChannelMode::Left, DEFAULT_DELAYS, decay), Err(StegoError::InvalidArgument(_))));
+            assert!(EchoHiding::default().with_strength(decay).is_err());
+        }
+        assert!(EchoHiding::new(ChannelMode::Left, (40, 40), DEFAULT_DECAY).is_err());
+        assert_eq!(EchoHiding::new(ChannelMode::All, (40, 90), 0.2).unwrap(), EchoHiding { channel_mode: ChannelMode::All, delays: (40, 90), decay: 0.2 });
+    }
+
+    #[test]
+    fn test_parameters() {
+        assert_eq!(EchoHiding::default().parameters(), ["left", "50", "75"]);
+        assert_eq!(EchoHiding::from_parameters(&["all", "40", "90"]).unwrap().delays, (40, 90));
+        assert!(EchoHiding::from_parameters(&["all", "40", "40"]).is_err());
+        assert!(EchoHiding::from_parameters(&["left", "40"]).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt;
+use std::io;
+
+// Ошибки встраивания и извлечения сообщения
+#[derive(Debug)]
+pub enum StegoError {
+    Io(io::Error),
+    // Некорректные или отсутствующие аргументы командной строки
+    InvalidArgument(String),
+    // Не указан пароль, без которого операция невозможна
+    MissingPassphrase(&'static str),
+    // Файл ключа не удалось разобрать
+    MalformedKey(String),
+    // Формат отсчетов WAV-файла не поддерживается
+    UnsupportedFormat(String),
+    // WAV-файл поврежден или обрезан
+    InvalidWav(String),
+    // Сообщение нельзя встроить по символам (например, невалидный UTF-8)
+    InvalidMessage(&'static str),
+    // Контейнер вмещает меньше бит, чем требуется
+    ContainerTooSmall { required: usize, available: usize },
+    // Длины исходного контейнера и стегаконтейнера различаются
+    LengthMismatch { container: usize, stegocontainer: usize },
+    // Заголовок не найден, обрезан или имеет неподдерживаемую версию
+    Header(String),
+    // Контрольная сумма сообщения не совпадает с заголовком
+    Checksum,
+    // Помехоустойчивый код не смог исправить ошибки
+    Fec(&'static str),
+    // Расшифрование сообщения не удалось
+    Decryption(&'static str),
+    // Встроенное сообщение не извлекается обратно (проверка после встраивания) и ошибка извлечения
+    Unrecoverable(Box<StegoError>),
+}
+
+impl StegoError {
+    // Код завершения утилиты, по которому скрипты могут различать виды ошибок.
+    // 2 совпадает с кодом clap для ошибок в аргументах.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            StegoError::InvalidArgument(_) | StegoError::MissingPassphrase(_) => 2,
+            StegoError::Io(_) => 3,
+            StegoError::MalformedKey(_) => 4,
+            StegoError::UnsupportedFormat(_) | StegoError::InvalidWav(_) => 5,
+            StegoError::ContainerTooSmall { .. } => 6,
+            StegoError::LengthMismatch { .. } => 7,
+            StegoError::Header(_) => 8,
+            StegoError::Checksum | StegoError::Fec(_) => 9,
+            StegoError::Decryption(_) => 10,
+            StegoError::InvalidMessage(_) => 11,
+            StegoError::Unrecoverable(_) => 12,
+        }
+    }
+}
+
+impl fmt::Display for StegoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StegoError::Io(e) => write!(f, "Ошибка ввода-вывода: {}", e),
+            StegoError::InvalidArgument(what) => write!(f, "Ошибка: {}", what),
+            StegoError::MissingPassphrase(what) => write!(f, "Ошибка: {}", what),
+            StegoError::MalformedKey(what) => write!(f, "Ошибка: некорректный формат ключа ({})", what),
+            StegoError::UnsupportedFormat(what) => write!(f, "Ошибка: неподдерживаемый формат WAV ({})", what),
+            StegoError::InvalidWav(what) => write!(f, "Ошибка: поврежденный WAV-файл ({})", what),
+            StegoError::InvalidMessage(what) => write!(f, "Ошибка: {}", what),
+            StegoError::ContainerTooSmall { required, available } => write!(
+                f,
+                "Ошибка: недостаточно отсчетов, чтобы спрятать сообщение (требуется {} бит, доступно {})",
+                required, available
+            ),
+            StegoError::LengthMismatch { container, stegocontainer } => write!(
+                f,
+                "Ошибка: длины контейнера ({} отсчетов) и стегаконтейнера ({} отсчетов) не совпадают",
+                container, stegocontainer
+            ),
+            StegoError::Header(what) => write!(f, "Ошибка: {}", what),
+            StegoError::Checksum => write!(f, "Ошибка: контрольная сумма сообщения не совпадает"),
+            StegoError::Fec(what) => write!(f, "Ошибка: {}", what),
+            StegoError::Decryption(what) => write!(f, "Ошибка: сообщение не расшифровано ({})", what),
+            StegoError::Unrecoverable(cause) => {
+                let cause = cause.to_string();
+                write!(
+                    f,
+                    "Ошибка: встроенное сообщение не удалось извлечь обратно ({}); попробуйте другой метод, силу встраивания или контейнер",
+                    cause.strip_prefix("Ошибка: ").unwrap_or(&cause)
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for StegoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StegoError::Io(e) => Some(e),
+            StegoError::Unrecoverable(cause) => Some(cause.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StegoError {
+    fn from(e: io::Error) -> Self {
+        StegoError::Io(e)
+    }
+}
+
+impl From<hound::Error> for StegoError {
+    fn from(e: hound::Error) -> Self {
+        match e {
+            hound::Error::IoError(e) => StegoError::Io(e),
+            hound::Error::Unsupported | hound::Error::TooWide | hound::Error::InvalidSampleFormat => {
+                StegoError::UnsupportedFormat(e.to_string())
+            }
+            hound::Error::FormatError(_) | hound::Error::UnfinishedSample => StegoError::InvalidWav(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_distinguish_failure_kinds() {
+        let errors = [
+            StegoError::Io(io::Error::other("io")),
+            StegoError::MalformedKey("x".to_string()),
+            StegoError::UnsupportedFormat("x".to_string()),
+            StegoError::ContainerTooSmall { required: 2, available: 1 },
+            StegoError::LengthMismatch { container: 2, stegocontainer: 1 },
+            StegoError::Header("x".to_string()),
+            StegoError::Checksum,
+            StegoError::Unrecoverable(Box::new(StegoError::Checksum)),
+        ];
+
+        let mut codes: Vec<u8> = errors.iter().map(|e| e.exit_code()).collect();
+        assert!(codes.iter().all(|&code| code > 1));
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+    }
+
+    #[test]
+    fn test_messages_have_single_prefix() {
+        let errors = [
+            StegoError::InvalidArgument("x".to_string()),
+            StegoError::Decryption("x"),
+            StegoError::Fec("x"),
+            StegoError::Unrecoverable(Box::new(StegoError::Header("заголовок не найден".to_string()))),
+        ];
+        for e in errors {
+            let message = e.to_string();
+            assert!(message.starts_with("Ошибка: "), "{}", message);
+            assert_eq!(message.matches("Ошибка").count(), 1, "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_wav_errors_are_classified() {
+        assert!(matches!(StegoError::from(hound::Error::FormatError("no RIFF tag")), StegoError::InvalidWav(_)));
+        assert!(matches!(StegoError::from(hound::Error::Unsupported), StegoError::UnsupportedFormat(_)));
+        assert!(matches!(StegoError::from(hound::Error::IoError(io::Error::other("io"))), StegoError::Io(_)));
+    }
+}
@@ -0,0 +1,533 @@
+// Помехоустойчивое кодирование встраиваемых бит.
+//
+// Внешний код Рида-Соломона работает над байтами сообщения (GF(2^8)), внутренний код -
+// над битами: повторение, Хэмминг (7,4) или сверточный код (K = 3, R = 1/2) с декодером Витерби.
+// Декодирование внутренних кодов мягкое: на вход подаются значения корреляции сегментов,
+// знак которых соответствует биту, а модуль - надежности решения.
+
+use crate::error::StegoError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InnerCode {
+    None,
+    Repetition(u8),
+    Hamming74,
+    Convolutional,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FecConfig {
+    pub inner: InnerCode,
+    // Количество проверочных байт Рида-Соломона на блок (0 - код не используется)
+    pub rs_parity: u8,
+}
+
+const INNER_NONE_ID: u8 = 0;
+const INNER_REPETITION_ID: u8 = 1;
+const INNER_HAMMING74_ID: u8 = 2;
+const INNER_CONVOLUTIONAL_ID: u8 = 3;
+
+impl FecConfig {
+    pub const NONE: FecConfig = FecConfig { inner: InnerCode::None, rs_parity: 0 };
+
+    // Заголовок всегда защищается кодом Хэмминга, так как о выбранном коде
+    // декодер узнает только из самого заголовка
+    pub const HEADER: FecConfig = FecConfig { inner: InnerCode::Hamming74, rs_parity: 0 };
+
+    pub fn to_bytes(self) -> [u8; 3] {
+        let (id, param) = match self.inner {
+            InnerCode::None => (INNER_NONE_ID, 0),
+            InnerCode::Repetition(k) => (INNER_REPETITION_ID, k),
+            InnerCode::Hamming74 => (INNER_HAMMING74_ID, 0),
+            InnerCode::Convolutional => (INNER_CONVOLUTIONAL_ID, 0),
+        };
+        [id, param, self.rs_parity]
+    }
+
+    pub fn from_bytes(bytes: [u8; 3]) -> Result<Self, StegoError> {
+        let inner = match bytes[0] {
+            INNER_NONE_ID => InnerCode::None,
+            INNER_REPETITION_ID if bytes[1] > 0 => InnerCode::Repetition(bytes[1]),
+            INNER_HAMMING74_ID => InnerCode::Hamming74,
+            INNER_CONVOLUTIONAL_ID => InnerCode::Convolutional,
+            other => return Err(StegoError::Header(format!("неизвестный помехоустойчивый код {}", other))),
+        };
+        if bytes[2] as usize >= RS_BLOCK_LEN {
+            return Err(StegoError::Header("некорректное число проверочных байт Рида-Соломона".to_string()));
+        }
+
+        Ok(FecConfig { inner, rs_parity: bytes[2] })
+    }
+
+    // Количество встраиваемых бит для data_len байт данных
+    pub fn encoded_bits(&self, data_len: usize) -> usize {
+        let bits = rs_encoded_len(data_len, self.rs_parity as usize) * 8;
+        match self.inner {
+            InnerCode::None => bits,
+            InnerCode::Repetition(k) => bits * k as usize,
+            InnerCode::Hamming74 => bits.div_ceil(4) * 7,
+            InnerCode::Convolutional => (bits + CONV_MEMORY) * 2,
+        }
+    }
+
+    pub fn encode(&self, data: &[u8]) -> Vec<bool> {
+        let bytes = rs_encode(data, self.rs_parity as usize);
+        let bits: Vec<bool> = bytes.iter()
+            .flat_map(|&byte| (0..8).rev().map(move |i| (byte >> i) & 1 == 1))
+            .collect();
+
+        match self.inner {
+            InnerCode::None => bits,
+            InnerCode::Repetition(k) => repetition_encode(&bits, k as usize),
+            InnerCode::Hamming74 => hamming74_encode(&bits),
+            InnerCode::Convolutional => convolutional_encode(&bits),
+        }
+    }
+
+    // soft: значения корреляции для encoded_bits(data_len) встроенных бит
+    pub fn decode(&self, soft: &[f64], data_len: usize) -> Result<Vec<u8>, StegoError> {
+        if soft.len() != self.encoded_bits(data_len) {
+            return Err(StegoError::Fec("количество извлеченных бит не соответствует помехоустойчивому коду"));
+        }
+
+        let bits_num = rs_encoded_len(data_len, self.rs_parity as usize) * 8;
+        let bits = match self.inner {
+            InnerCode::None => soft.iter().map(|&s| s > 0.0).collect(),
+            InnerCode::Repetition(k) => repetition_decode(soft, k as usize, bits_num),
+            InnerCode::Hamming74 => hamming74_decode(soft, bits_num),
+            InnerCode::Convolutional => viterbi_decode(soft, bits_num),
+        };
+
+        let bytes: Vec<u8> = bits.chunks(8)
+            .map(|chunk| chunk.iter().fold(0u8, |acc, &bit| (acc << 1) | bit as u8))
+            .collect();
+
+        rs_decode(&bytes, data_len, self.rs_parity as usize)
+    }
+}
+
+// Повторение всей последовательности k раз: локальное повреждение контейнера
+// затрагивает только одну копию каждого бита
+fn repetition_encode(bits: &[bool], k: usize) -> Vec<bool> {
+    let mut encoded = Vec::with_capacity(bits.len() * k);
+    for _ in 0..k {
+        encoded.extend_from_slice(bits);
+    }
+    encoded
+}
+
+fn repetition_decode(soft: &[f64], k: usize, bits_num: usize) -> Vec<bool> {
+    (0..bits_num)
+        .map(|i| (0..k).map(|j| soft[j * bits_num + i]).sum::<f64>() > 0.0)
+        .collect()
+}
+
+// Систематический код Хэмминга (7,4): | d1 d2 d3 d4 | p1 p2 p3 |
+fn hamming74_codeword(data: [bool; 4]) -> [bool; 7] {
+    let [d1, d2, d3, d4] = data;
+    [d1, d2, d3, d4, d1 ^ d2 ^ d4, d1 ^ d3 ^ d4, d2 ^ d3 ^ d4]
+}
+
+fn hamming74_encode(bits: &[bool]) -> Vec<bool> {
+    bits.chunks(4)
+        .flat_map(|chunk| {
+            let mut data = [false; 4];
+            data[..chunk.len()].copy_from_slice(chunk);
+            hamming74_codeword(data)
+        })
+        .collect()
+}
+
+// Декодирование по максимуму правдоподобия: из 16 кодовых слов выбирается
+// наиболее коррелирующее с принятыми значениями
+fn hamming74_decode(soft: &[f64], bits_num: usize) -> Vec<bool> {
+    let mut bits = Vec::with_capacity(bits_num + 3);
+
+    for block in soft.chunks(7) {
+        let best = (0..16u8)
+            .map(|value| [value & 8 != 0, value & 4 != 0, value & 2 != 0, value & 1 != 0])
+            .max_by(|&a, &b| {
+                let score = |data: [bool; 4]| -> f64 {
+                    hamming74_codeword(data).iter()
+                        .zip(block.iter())
+                        .map(|(&bit, &s)| if bit { s } else { -s })
+                        .sum()
+                };
+                score(a).total_cmp(&score(b))
+            })
+            .unwrap();
+        bits.extend_from_slice(&best);
+    }
+
+    bits.truncate(bits_num);
+    bits
+}
+
+// Сверточный код с образующими 7 и 5 (восьмеричные), память 2, с хвостом из нулей
+const CONV_MEMORY: usize = 2;
+const CONV_STATES: usize = 1 << CONV_MEMORY;
+
+fn convolutional_output(state: usize, bit: bool) -> (bool, bool) {
+    let register = ((bit as usize) << 2) | state;
+    ((register & 0b111).count_ones() % 2 == 1, (register & 0b101).count_ones() % 2 == 1)
+}
+
+fn convolutional_encode(bits: &[bool]) -> Vec<bool> {
+    let mut encoded = Vec::with_capacity((bits.len() + CONV_MEMORY) * 2);
+    let mut state = 0;
+
+    for &bit in bits.iter().chain([false; CONV_MEMORY].iter()) {
+        let (out0, out1) = convolutional_output(state, bit);
+        encoded.push(out0);
+        encoded.push(out1);
+        state = (((bit as usize) << 2) | state) >> 1;
+    }
+
+    encoded
+}
+
+fn viterbi_decode(soft: &[f64], bits_num: usize) -> Vec<bool> {
+    let steps = bits_num + CONV_MEMORY;
+    let mut metrics = [f64::NEG_INFINITY; CONV_STATES];
+    metrics[0] = 0.0;
+    // Для каждого шага и состояния: (предыдущее состояние, входной бит)
+    let mut survivors: Vec<[(usize, bool); CONV_STATES]> = Vec::with_capacity(steps);
+
+    for step in 0..steps {
+        let (r0, r1) = (soft[2 * step], soft[2 * step + 1]);
+        let mut next_metrics = [f64::NEG_INFINITY; CONV_STATES];
+        let mut step_survivors = [(0, false); CONV_STATES];
+
+        for (state, &state_metric) in metrics.iter().enumerate() {
+            if state_metric == f64::NEG_INFINITY {
+                continue;
+            }
+            for bit in [false, true] {
+                let (out0, out1) = convolutional_output(state, bit);
+                let branch = (if out0 { r0 } else { -r0 }) + (if out1 { r1 } else { -r1 });
+                let next_state = (((bit as usize) << 2) | state) >> 1;
+                let metric = state_metric + branch;
+                if metric > next_metrics[next_state] {
+                    next_metrics[next_state] = metric;
+                    step_survivors[next_state] = (state, bit);
+                }
+            }
+        }
+
+        metrics = next_metrics;
+        survivors.push(step_survivors);
+    }
+
+    // Хвост из нулей возвращает кодер в нулевое состояние
+    let mut bits = Vec::with_capacity(steps);
+    let mut state = 0;
+    for step_survivors in survivors.iter().rev() {
+        let (previous, bit) = step_survivors[state];
+        bits.push(bit);
+        state = previous;
+    }
+    bits.reverse();
+    bits.truncate(bits_num);
+    bits
+}
+
+// Код Рида-Соломона над GF(2^8) с примитивным многочленом x^8 + x^4 + x^3 + x^2 + 1.
+// Данные делятся на блоки до 255 - rs_parity байт, к каждому добавляются rs_parity
+// проверочных байт, что позволяет исправить до rs_parity / 2 ошибочных байт в блоке.
+const RS_BLOCK_LEN: usize = 255;
+
+struct GaloisField {
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+impl GaloisField {
+    fn new() -> Self {
+        let mut exp = [0u8; 512];
+        let mut log = [0u8; 256];
+        let mut x: u16 = 1;
+        for (i, e) in exp.iter_mut().take(255).enumerate() {
+            *e = x as u8;
+            log[x as usize] = i as u8;
+            x <<= 1;
+            if x & 0x100 != 0 {
+                x ^= 0x11d;
+            }
+        }
+        for i in 255..512 {
+            exp[i] = exp[i - 255];
+        }
+        GaloisField { exp, log }
+    }
+
+    fn mul(&self, x: u8, y: u8) -> u8 {
+        if x == 0 || y == 0 {
+            return 0;
+        }
+        self.exp[self.log[x as usize] as usize + self.log[y as usize] as usize]
+    }
+
+    fn div(&self, x: u8, y: u8) -> u8 {
+        if x == 0 {
+            return 0;
+        }
+        self.exp[(self.log[x as usize] as usize + 255 - self.log[y as usize] as usize) % 255]
+    }
+
+    fn pow(&self, x: u8, power: i32) -> u8 {
+        self.exp[(self.log[x as usize] as i32 * power).rem_euclid(255) as usize]
+    }
+
+    fn inverse(&self, x: u8) -> u8 {
+        self.exp[255 - self.log[x as usize] as usize]
+    }
+
+    // Многочлены хранятся от старшей степени к младшей
+    fn poly_scale(&self, p: &[u8], x: u8) -> Vec<u8> {
+        p.iter().map(|&c| self.mul(c, x)).collect()
+    }
+
+    fn poly_add(&self, p: &[u8], q: &[u8]) -> Vec<u8> {
+        let len = p.len().max(q.len());
+        let mut r = vec![0u8; len];
+        for (i, &c) in p.iter().enumerate() {
+            r[i + len - p.len()] = c;
+        }
+        for (i, &c) in q.iter().enumerate() {
+            r[i + len - q.len()] ^= c;
+        }
+        r
+    }
+
+    fn poly_mul(&self, p: &[u8], q: &[u8]) -> Vec<u8> {
+        let mut r = vec![0u8; p.len() + q.len() - 1];
+        for (j, &qc) in q.iter().enumerate() {
+            for (i, &pc) in p.iter().enumerate() {
+                r[i + j] ^= self.mul(pc, qc);
+            }
+        }
+        r
+    }
+
+    fn poly_eval(&self, p: &[u8], x: u8) -> u8 {
+        p.iter().skip(1).fold(p[0], |y, &c| self.mul(y, x) ^ c)
+    }
+
+    fn generator_poly(&self, nsym: usize) -> Vec<u8> {
+        (0..nsym).fold(vec![1], |g, i| self.poly_mul(&g, &[1, self.pow(2, i as i32)]))
+    }
+}
+
+fn rs_encoded_len(data_len: usize, nsym: usize) -> usize {
+    if nsym == 0 {
+        return data_len;
+    }
+    data_len + data_len.div_ceil(RS_BLOCK_LEN - nsym) * nsym
+}
+
+fn rs_encode(data: &[u8], nsym: usize) -> Vec<u8> {
+    if nsym == 0 {
+        return data.to_vec();
+    }
+
+    let gf = GaloisField::new();
+    let generator = gf.generator_poly(nsym);
+    let mut encoded = Vec::with_capacity(rs_encoded_len(data.len(), nsym));
+
+    for block in data.chunks(RS_BLOCK_LEN - nsym) {
+        let mut remainder = block.to_vec();
+        remainder.resize(block.len() + nsym, 0);
+        for i in 0..block.len() {
+            let coef = remainder[i];
+            if coef != 0 {
+                for (j, &g) in generator.iter().enumerate().skip(1) {
+                    remainder[i + j] ^= gf.mul(g, coef);
+                }
+            }
+        }
+        encoded.extend_from_slice(block);
+        encoded.extend_from_slice(&remainder[block.len()..]);
+    }
+
+    encoded
+}
+
+fn rs_decode(encoded: &[u8], data_len: usize, nsym: usize) -> Result<Vec<u8>, StegoError> {
+    if nsym == 0 {
+        return Ok(encoded[..data_len].to_vec());
+    }
+
+    let gf = GaloisField::new();
+    let mut data = Vec::with_capacity(data_len);
+    let mut remaining = data_len;
+
+    for block in encoded.chunks(RS_BLOCK_LEN) {
+        let block_data_len = remaining.min(RS_BLOCK_LEN - nsym);
+        let corrected = rs_correct_block(&gf, &block[..block_data_len + nsym], nsym)?;
+        data.extend_from_slice(&corrected[..block_data_len]);
+        remaining -= block_data_len;
+    }
+
+    Ok(data)
+}
+
+fn rs_syndromes(gf: &GaloisField, block: &[u8], nsym: usize) -> Vec<u8> {
+    (0..nsym).map(|i| gf.poly_eval(block, gf.pow(2, i as i32))).collect()
+}
+
+// Декодирование блока: синдромы, алгоритм Берлекэмпа-Месси, поиск Ченя и алгоритм Форни
+fn rs_correct_block(gf: &GaloisField, block: &[u8], nsym: usize) -> Result<Vec<u8>, StegoError> {
+    let too_many_errors = || StegoError::Fec("слишком много ошибок для кода Рида-Соломона");
+
+    let syndromes = rs_syndromes(gf, block, nsym);
+    if syndromes.iter().all(|&s| s == 0) {
+        return Ok(block.to_vec());
+    }
+
+    // Многочлен локаторов ошибок (алгоритм Берлекэмпа-Месси)
+    let mut err_loc = vec![1u8];
+    let mut old_loc = vec![1u8];
+    for i in 0..nsym {
+        let mut delta = syndromes[i];
+        for j in 1..err_loc.len().min(i + 1) {
+            delta ^= gf.mul(err_loc[err_loc.len() - 1 - j], syndromes[i - j]);
+        }
+        old_loc.push(0);
+        if delta != 0 {
+            if old_loc.len() > err_loc.len() {
+                let new_loc = gf.poly_scale(&old_loc, delta);
+                old_loc = gf.poly_scale(&err_loc, gf.inverse(delta));
+                err_loc = new_loc;
+            }
+            err_loc = gf.poly_add(&err_loc, &gf.poly_scale(&old_loc, delta));
+        }
+    }
+    while err_loc.len() > 1 && err_loc[0] == 0 {
+        err_loc.remove(0);
+    }
+    let errors = err_loc.len() - 1;
+    if errors * 2 > nsym {
+        return Err(too_many_errors());
+    }
+
+    // Поиск корней (процедура Ченя)
+    let reversed_loc: Vec<u8> = err_loc.iter().rev().cloned().collect();
+    let err_pos: Vec<usize> = (0..block.len())
+        .filter(|&i| gf.poly_eval(&reversed_loc, gf.pow(2, i as i32)) == 0)
+        .map(|i| block.len() - 1 - i)
+        .collect();
+    if err_pos.len() != errors {
+        return Err(too_many_errors());
+    }
+
+    // Величины ошибок (алгоритм Форни)
+    let coef_pos: Vec<usize> = err_pos.iter().map(|&p| block.len() - 1 - p).collect();
+    let errata_loc = coef_pos.iter().fold(vec![1u8], |loc, &p| {
+        gf.poly_mul(&loc, &gf.poly_add(&[1], &[gf.pow(2, p as i32), 0]))
+    });
+    let mut synd_rev: Vec<u8> = syndromes.iter().rev().cloned().collect();
+    synd_rev.push(0);
+    let product = gf.poly_mul(&synd_rev, &errata_loc);
+    let evaluator_len = errata_loc.len();
+    let err_eval: Vec<u8> = product[product.len() - evaluator_len..].to_vec();
+
+    let x: Vec<u8> = coef_pos.iter().map(|&p| gf.pow(2, p as i32)).collect();
+    let mut corrected = block.to_vec();
+    for (i, &xi) in x.iter().enumerate() {
+        let xi_inv = gf.inverse(xi);
+        let err_loc_prime = x.iter().enumerate()
+            .filter(|&(j, _)| j != i)
+            .fold(1u8, |acc, (_, &xj)| gf.mul(acc, 1 ^ gf.mul(xi_inv, xj)));
+        if err_loc_prime == 0 {
+            return Err(too_many_errors());
+        }
+        let y = gf.mul(xi, gf.poly_eval(&err_eval, xi_inv));
+        corrected[err_pos[i]] ^= gf.div(y, err_loc_prime);
+    }
+
+    if rs_syndromes(gf, &corrected, nsym).iter().any(|&s| s != 0) {
+        return Err(too_many_errors());
+    }
+
+    Ok(corrected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_soft(bits: &[bool]) -> Vec<f64> {
+        bits.iter().map(|&bit| if bit { 1.0 } else { -1.0 }).collect()
+    }
+
+    fn round_trip_with_flips(config: FecConfig, data: &[u8], flips: &[usize]) -> Vec<u8> {
+        let encoded = config.encode(data);
+        assert_eq!(encoded.len(), config.encoded_bits(data.len()));
+
+        let mut soft = to_soft(&encoded);
+        for &i in flips {
+            soft[i] = -soft[i];
+        }
+        config.decode(&soft, data.len()).expect("Декодирование не должно падать")
+    }
+
+    #[test]
+    fn test_repetition_corrects_minority_flips() {
+        let data = b"FEC test";
+        let config = FecConfig { inner: InnerCode::Repetition(3), rs_parity: 0 };
+        let bits_num = data.len() * 8;
+
+        // В каждой позиции искажено не более одной копии из трех
+        let flips: Vec<usize> = (0..bits_num).map(|i| i + (i % 3) * bits_num).collect();
+        assert_eq!(round_trip_with_flips(config, data, &flips), data);
+    }
+
+    #[test]
+    fn test_hamming_corrects_one_error_per_block() {
+        let data = b"Hamming";
+        let config = FecConfig { inner: InnerCode::Hamming74, rs_parity: 0 };
+
+        let blocks = config.encoded_bits(data.len()) / 7;
+        let flips: Vec<usize> = (0..blocks).map(|b| b * 7 + b % 7).collect();
+        assert_eq!(round_trip_with_flips(config, data, &flips), data);
+    }
+
+    #[test]
+    fn test_viterbi_corrects_spaced_errors() {
+        let data = b"Viterbi decoding";
+        let config = FecConfig { inner: InnerCode::Convolutional, rs_parity: 0 };
+
+        let flips: Vec<usize> = (0..config.encoded_bits(data.len())).step_by(12).collect();
+        assert_eq!(round_trip_with_flips(config, data, &flips), data);
+    }
+
+    #[test]
+    fn test_reed_solomon_corrects_byte_errors() {
+        let data: Vec<u8> = (0..300).map(|i| (i * 7 % 256) as u8).collect();
+        let config = FecConfig { inner: InnerCode::None, rs_parity: 8 };
+
+        // По 4 испорченных байта в каждом из двух блоков, включая проверочные байты
+        let byte_errors = [0usize, 10, 100, 250, 260, 270, 300, 315];
+        let flips: Vec<usize> = byte_errors.iter().flat_map(|&b| [b * 8, b * 8 + 5]).collect();
+        assert_eq!(round_trip_with_flips(config, &data, &flips), data);
+    }
+
+    #[test]
+    fn test_reed_solomon_reports_uncorrectable_block() {
+        let data = b"Too many errors";
+        let config = FecConfig { inner: InnerCode::None, rs_parity: 2 };
+
+        let mut soft = to_soft(&config.encode(data));
+        for byte in 0..3 {
+            soft[byte * 8] = -soft[byte * 8];
+        }
+        assert!(config.decode(&soft, data.len()).is_err());
+    }
+
+    #[test]
+    fn test_config_bytes_round_trip() {
+        let config = FecConfig { inner: InnerCode::Repetition(5), rs_parity: 16 };
+        assert_eq!(FecConfig::from_bytes(config.to_bytes()).unwrap(), config);
+        assert!(FecConfig::from_bytes([9, 0, 0]).is_err());
+    }
+}
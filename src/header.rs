@@ -0,0 +1,178 @@
+use bitvec::prelude::*;
+use crc32fast::Hasher;
+use crate::error::StegoError;
+use crate::fec::FecConfig;
+
+// Заголовок, который встраивается перед сообщением, чтобы при извлечении не нужно было
+// передавать количество бит на символ и длину сообщения вручную.
+//
+// Формат (big-endian):
+// | магия "SG" (2 байта) | версия (1) | флаги (1) | бит на символ (1) | код (3) | длина сообщения (4) | CRC-32 (4) |
+//
+// CRC-32 считается по всем предыдущим полям заголовка и по самому сообщению.
+// Сам заголовок встраивается закодированным кодом Хэмминга (7,4), см. FecConfig::HEADER.
+// С версии 4 перед заголовком встраивается синхронизирующая преамбула (см. sync).
+pub const MAGIC: [u8; 2] = *b"SG";
+pub const VERSION: u8 = 4;
+pub const HEADER_LEN: usize = 16;
+pub const HEADER_BITS: usize = HEADER_LEN * 8 / 4 * 7;
+
+// Сообщение зашифровано паролем (см. crypto::encrypt_payload)
+pub const FLAG_ENCRYPTED: u8 = 0x01;
+// Кадр повторяется до конца контейнера, последняя копия может быть неполной
+pub const FLAG_REPEATED: u8 = 0x02;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StegoHeader {
+    pub version: u8,
+    pub flags: u8,
+    pub bits_per_symbol: u8,
+    pub fec: FecConfig,
+    pub payload_len: u32,
+    pub checksum: u32,
+}
+
+impl StegoHeader {
+    pub fn new(payload: &[u8], bits_per_symbol: usize, flags: u8, fec: FecConfig) -> Self {
+        let mut header = StegoHeader {
+            version: VERSION,
+            flags,
+            bits_per_symbol: bits_per_symbol as u8,
+            fec,
+            payload_len: payload.len() as u32,
+            checksum: 0,
+        };
+        header.checksum = header.compute_checksum(payload);
+        header
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & FLAG_ENCRYPTED != 0
+    }
+
+    pub fn is_repeated(&self) -> bool {
+        self.flags & FLAG_REPEATED != 0
+    }
+
+    // Количество бит сообщения, которые встраиваются после заголовка
+    pub fn payload_bits(&self) -> usize {
+        self.bits_per_symbol as usize * self.payload_len as usize
+    }
+
+    // Сообщение, дополненное до payload_bits() бит, в байтах
+    pub fn padded_payload_len(&self) -> usize {
+        self.payload_bits().div_ceil(8)
+    }
+
+    // Количество бит сообщения после помехоустойчивого кодирования
+    pub fn coded_payload_bits(&self) -> usize {
+        self.fec.encoded_bits(self.padded_payload_len())
+    }
+
+    // Общее количество встраиваемых бит вместе с заголовком
+    pub fn frame_bits(&self) -> usize {
+        HEADER_BITS + self.coded_payload_bits()
+    }
+
+    // Биты кадра: заголовок, закодированный FecConfig::HEADER, затем сообщение, дополненное
+    // единицами до payload_bits() бит и закодированное выбранным в заголовке кодом
+    pub fn encode_frame(&self, message: &[u8]) -> Vec<bool> {
+        let payload: BitVec<u8, Msb0> = BitVec::<u8, Msb0>::from_slice(message).into_iter()
+            .chain(std::iter::repeat(true))
+            .take(self.payload_bits())
+            .collect();
+
+        let mut bits = FecConfig::HEADER.encode(&self.to_bytes());
+        bits.extend(self.fec.encode(&payload.into_vec()));
+        bits
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.fields_to_bytes();
+        bytes.extend_from_slice(&self.checksum.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StegoError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(StegoError::Header("заголовок стегаконтейнера обрезан".to_string()));
+        }
+        if bytes[0..2] != MAGIC {
+            return Err(StegoError::Header("заголовок стегаконтейнера не найден (неверный ключ или контейнер без заголовка)".to_string()));
+        }
+        if bytes[2] != VERSION {
+            return Err(StegoError::Header(format!("неподдерживаемая версия заголовка {}", bytes[2])));
+        }
+
+        Ok(StegoHeader {
+            version: bytes[2],
+            flags: bytes[3],
+            bits_per_symbol: bytes[4],
+            fec: FecConfig::from_bytes([bytes[5], bytes[6], bytes[7]])?,
+            payload_len: u32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            checksum: u32::from_be_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+        })
+    }
+
+    pub fn verify(&self, payload: &[u8]) -> Result<(), StegoError> {
+        if self.compute_checksum(payload) != self.checksum {
+            return Err(StegoError::Checksum);
+        }
+        Ok(())
+    }
+
+    fn fields_to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(self.version);
+        bytes.push(self.flags);
+        bytes.push(self.bits_per_symbol);
+        bytes.extend_from_slice(&self.fec.to_bytes());
+        bytes.extend_from_slice(&self.payload_len.to_be_bytes());
+        bytes
+    }
+
+    fn compute_checksum(&self, payload: &[u8]) -> u32 {
+        let mut hasher = Hasher::new();
+        hasher.update(&self.fields_to_bytes());
+        hasher.update(payload);
+        hasher.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fec::InnerCode;
+
+    #[test]
+    fn test_header_round_trip() {
+        let payload = "Привет".as_bytes();
+        let fec = FecConfig { inner: InnerCode::Repetition(3), rs_parity: 4 };
+        let header = StegoHeader::new(payload, 16, FLAG_ENCRYPTED, fec);
+
+        let bytes = header.to_bytes();
+        assert_eq!(bytes.len(), HEADER_LEN);
+
+        let parsed = StegoHeader::from_bytes(&bytes).expect("Заголовок должен разбираться");
+        assert_eq!(parsed, header);
+        assert!(parsed.is_encrypted());
+        assert_eq!(parsed.payload_bits(), 16 * payload.len());
+        assert!(parsed.verify(payload).is_ok());
+        assert_eq!(parsed.fec, fec);
+        assert_eq!(parsed.coded_payload_bits(), fec.encoded_bits(2 * payload.len()));
+        assert_eq!(FecConfig::HEADER.encoded_bits(HEADER_LEN), HEADER_BITS);
+    }
+
+    #[test]
+    fn test_header_rejects_bad_magic_and_checksum() {
+        let payload = b"Hi";
+        let mut bytes = StegoHeader::new(payload, 8, 0, FecConfig::NONE).to_bytes();
+
+        let header = StegoHeader::from_bytes(&bytes).unwrap();
+        assert!(header.verify(b"Ho").is_err());
+
+        bytes[0] = b'X';
+        assert!(StegoHeader::from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use crate::chip_generator::ChipGenerator;
+use crate::crypto::{derive_key, random_bytes};
+
+pub use crate::crypto::SALT_LEN;
+
+pub const HMAC_SHA256_ID: &str = "hmac-sha256";
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Криптостойкий генератор ПСП: ключ выводится из пароля через PBKDF2-HMAC-SHA256,
+// а элементы ПСП берутся из битов HMAC-SHA256(ключ, счетчик). Без ключа по наблюдаемым
+// отсчетам последовательность не восстанавливается, в отличие от генератора Парка-Миллера.
+pub struct HmacSha256Generator {
+    key: [u8; 32],
+    counter: u64,
+    block: [u8; 32],
+    bit_index: usize,
+}
+
+impl HmacSha256Generator {
+    pub fn from_passphrase(passphrase: &str, salt: &[u8]) -> Self {
+        HmacSha256Generator {
+            key: derive_key(passphrase, salt),
+            counter: 0,
+            block: [0u8; 32],
+            bit_index: 256,
+        }
+    }
+
+    pub fn generate_salt() -> std::io::Result<[u8; SALT_LEN]> {
+        random_bytes()
+    }
+
+    fn next_block(&mut self) {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC принимает ключ любой длины");
+        mac.update(&self.counter.to_be_bytes());
+        self.block.copy_from_slice(&mac.finalize().into_bytes());
+        self.counter += 1;
+        self.bit_index = 0;
+    }
+
+    fn next(&mut self) -> i16 {
+        if self.bit_index == 256 {
+            self.next_block();
+        }
+
+        let bit = (self.block[self.bit_index / 8] >> (7 - self.bit_index % 8)) & 1;
+        self.bit_index += 1;
+
+        if bit == 1 {
+            1
+        } else {
+            -1
+        }
+    }
+}
+
+impl ChipGenerator for HmacSha256Generator {
+    fn id(&self) -> &'static str {
+        HMAC_SHA256_ID
+    }
+
+    fn generate_prs(&mut self, num: usize) -> Vec<i16> {
+        (0..num).map(|_| self.next()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_passphrase_and_salt_give_same_prs() {
+        let salt = [7u8; SALT_LEN];
+        let first = HmacSha256Generator::from_passphrase("секрет", &salt).generate_prs(1000);
+        let second = HmacSha256Generator::from_passphrase("секрет", &salt).generate_prs(1000);
+        assert_eq!(first, second);
+
+        let ones = first.iter().filter(|&&c| c == 1).count();
+        assert!(ones > 400 && ones < 600);
+    }
+
+    #[test]
+    fn test_prs_depends_on_passphrase_and_salt() {
+        let salt = [7u8; SALT_LEN];
+        let reference = HmacSha256Generator::from_passphrase("секрет", &salt).generate_prs(256);
+
+        let other_passphrase = HmacSha256Generator::from_passphrase("секрет!", &salt).generate_prs(256);
+        assert_ne!(reference, other_passphrase);
+
+        let other_salt = HmacSha256Generator::from_passphrase("секрет", &[8u8; SALT_LEN]).generate_prs(256);
+        assert_ne!(reference, other_salt);
+    }
+
+    #[test]
+    fn test_stream_continues_between_calls() {
+        let salt = [1u8; SALT_LEN];
+        let whole = HmacSha256Generator::from_passphrase("pass", &salt).generate_prs(600);
+
+        let mut generator = HmacSha256Generator::from_passphrase("pass", &salt);
+        let mut parts = generator.generate_prs(100);
+        parts.extend(generator.generate_prs(500));
+        assert_eq!(whole, parts);
+    }
+}
@@ -0,0 +1,44 @@
+use crate::chip_generator::ChipGenerator;
+use crate::error::StegoError;
+use crate::hmac_prng::{HmacSha256Generator, HMAC_SHA256_ID, SALT_LEN};
+use crate::park_miller_prng::{ParkMiller, PARK_MILLER_ID};
+
+// Компактный ключ: вместо самой ПСП хранится генератор, его параметры и длина ПСП,
+// по которым последовательность восстанавливается при извлечении.
+// Для hmac-sha256 в ключе хранится только соль, пароль передается отдельно.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeySpec {
+    ParkMiller { seed: u32, chips: usize },
+    HmacSha256 { salt: [u8; SALT_LEN], chips: usize },
+}
+
+impl KeySpec {
+    pub fn id(&self) -> &'static str {
+        match self {
+            KeySpec::ParkMiller { .. } => PARK_MILLER_ID,
+            KeySpec::HmacSha256 { .. } => HMAC_SHA256_ID,
+        }
+    }
+
+    pub fn chips(&self) -> usize {
+        match self {
+            KeySpec::ParkMiller { chips, .. } | KeySpec::HmacSha256 { chips, .. } => *chips,
+        }
+    }
+
+    pub fn generator(&self, passphrase: Option<&str>) -> Result<Box<dyn ChipGenerator>, StegoError> {
+        match self {
+            KeySpec::ParkMiller { seed, .. } => Ok(Box::new(ParkMiller::with_seed(*seed)?)),
+            KeySpec::HmacSha256 { salt, .. } => {
+                let passphrase = passphrase.ok_or(StegoError::MissingPassphrase(
+                    "для генератора hmac-sha256 необходим пароль (--passphrase)",
+                ))?;
+                Ok(Box::new(HmacSha256Generator::from_passphrase(passphrase, salt)))
+            }
+        }
+    }
+
+    pub fn generate_prs(&self, passphrase: Option<&str>) -> Result<Vec<i16>, StegoError> {
+        Ok(self.generator(passphrase)?.generate_prs(self.chips()))
+    }
+}
@@ -0,0 +1,41 @@
+// Генератор, порожденный ПСП ключа: алгоритмы встраивания берут из него собственные
+// случайные величины (перестановки отсчетов, сдвиги решеток), которые без ключа неизвестны.
+// Один ключ дает несколько независимых потоков, различающихся номером потока.
+
+// Элементы ПСП, из знаков которых собирается зерно
+pub(crate) const SEED_CHIPS: usize = 64;
+
+// xorshift64 с зерном из первых SEED_CHIPS элементов ПСП и номера потока
+pub(crate) struct KeyedRng(u64);
+
+impl KeyedRng {
+    pub(crate) fn new(key: &[i16], stream: u64) -> Self {
+        let key_seed = key.iter().take(SEED_CHIPS).fold(0u64, |acc, &c| acc << 1 | (c > 0) as u64);
+        KeyedRng((key_seed ^ stream.wrapping_mul(0x9e37_79b9_7f4a_7c15)) | 1)
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streams_depend_on_key_and_stream() {
+        let values = |key: &[i16], stream: u64| -> Vec<u64> {
+            let mut rng = KeyedRng::new(key, stream);
+            (0..8).map(|_| rng.next()).collect()
+        };
+        let key = [1, -1, -1, 1, 1];
+
+        assert_eq!(values(&key, 1), values(&key, 1));
+        assert_ne!(values(&key, 1), values(&key, 2));
+        assert_ne!(values(&key, 1), values(&[-1, -1, -1, 1, 1], 1));
+    }
+}
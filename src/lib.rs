@@ -0,0 +1,53 @@
+// Встраивание сообщений в звуковые сигналы. Алгоритмы встраивания реализуют StegoMethod,
+// по умолчанию используется расширение спектра (SpreadSpectrum).
+//
+// Библиотека работает с отсчетами, разделенными по каналам, и ничего не знает о файлах:
+// чтение и запись WAV-файлов и ключей остаются в утилите командной строки.
+//
+//     let embedded = Embedder::new().seed(42).embed(&channels, b"Hi")?;
+//     let extracted = Extractor::from_key_spec(&embedded.key, None)?.extract(&embedded.amplitudes)?;
+
+pub mod attack;
+pub mod bench;
+pub mod channels;
+pub mod chip_generator;
+pub mod crypto;
+pub mod dest;
+pub mod dsss;
+pub mod echo;
+pub mod error;
+pub mod fec;
+pub mod header;
+pub mod hmac_prng;
+pub mod key;
+mod keyed_rng;
+pub mod lsb;
+pub mod method;
+pub mod park_miller_prng;
+pub mod phase;
+pub mod psychoacoustic;
+pub mod qim;
+pub mod quality;
+pub mod spectrum;
+pub mod st;
+pub mod sync;
+pub mod transform;
+mod stego;
+#[cfg(test)]
+mod test_signals;
+
+pub use channels::ChannelMode;
+pub use dsss::SpreadSpectrum;
+pub use echo::EchoHiding;
+pub use error::StegoError;
+pub use fec::{FecConfig, InnerCode};
+pub use header::StegoHeader;
+pub use key::KeySpec;
+pub use lsb::LeastSignificantBits;
+pub use method::StegoMethod;
+pub use phase::PhaseCoding;
+pub use qim::QuantizationIndexModulation;
+pub use quality::QualityReport;
+pub use st::Gain;
+pub use stego::{Capacity, Embedded, Embedder, EmbeddingPlan, Extracted, Extractor};
+pub use transform::Domain;
@@ -0,0 +1,299 @@
+use std::sync::Arc;
+use crate::channels::ChannelMode;
+use crate::error::StegoError;
+use crate::keyed_rng::{KeyedRng, SEED_CHIPS};
+use crate::method::{Demodulated, StegoMethod};
+use crate::sync::SyncPoint;
+
+pub const LSB_ID: &str = "lsb";
+
+// Наибольшее количество младших бит на отсчет
+pub const MAX_DEPTH: u8 = 8;
+// Ключ нужен только как зерно перестановки отсчетов (см. KeyedRng)
+const KEY_CHIPS: usize = SEED_CHIPS;
+
+// Встраивание в младшие биты целочисленных отсчетов. Емкость в сотни раз больше, чем
+// у расширения спектра, но любое изменение отсчетов (даже повторное квантование) разрушает
+// сообщение, поэтому метод годится только для передачи файла без изменений.
+//
+// Биты кадра записываются по depth бит в отсчеты всех каналов в порядке, который задает
+// перестановка, порожденная ключом: без ключа неизвестно, какие отсчеты несут сообщение.
+// Вместо замены младших бит отсчет сдвигается к ближайшему значению с нужными младшими
+// битами (±1 при depth = 1, направление при равном расстоянии выбирается случайно).
+// Простая замена делает пары значений 2k и 2k + 1 равновероятными, что видно по гистограмме
+// отсчетов; при сдвиге на ±1 гистограмма только слегка сглаживается.
+//
+// Начало кадра не ищется: после обрезки или сдвига отсчетов сообщение не извлекается.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LeastSignificantBits {
+    // Разрядность целочисленных отсчетов файла: отсчет x хранится как round(x * 2^(bits - 1))
+    pub sample_bits: u16,
+    // Количество младших бит на отсчет
+    pub depth: u8,
+}
+
+impl LeastSignificantBits {
+    pub fn new(sample_bits: u16, depth: u8) -> Result<Self, StegoError> {
+        if !(2..=32).contains(&sample_bits) {
+            return Err(StegoError::InvalidArgument(format!("LSB: неподдерживаемая разрядность отсчетов {}", sample_bits)));
+        }
+        if depth == 0 || depth > MAX_DEPTH || depth as u16 >= sample_bits {
+            return Err(StegoError::InvalidArgument(format!(
+                "LSB: количество младших бит должно быть от 1 до {} и меньше разрядности отсчетов", MAX_DEPTH,
+            )));
+        }
+        Ok(LeastSignificantBits { sample_bits, depth })
+    }
+
+    // Параметры: разрядность отсчетов и количество младших бит на отсчет
+    pub fn from_parameters(parameters: &[&str]) -> Result<Self, StegoError> {
+        let invalid = || StegoError::MalformedKey(format!("параметры {}: ожидаются разрядность отсчетов и количество младших бит", LSB_ID));
+
+        match parameters {
+            [sample_bits, depth] => {
+                let sample_bits = sample_bits.parse().map_err(|_| invalid())?;
+                let depth = depth.parse().map_err(|_| invalid())?;
+                Self::new(sample_bits, depth).map_err(|_| invalid())
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    fn full_scale(&self) -> i64 {
+        1i64 << (self.sample_bits - 1)
+    }
+
+    // Целочисленный отсчет, как его запишет save_amplitudes_to_wav
+    fn quantize(&self, amplitude: f64) -> i64 {
+        ((amplitude * self.full_scale() as f64).round() as i64).clamp(-self.full_scale(), self.full_scale() - 1)
+    }
+
+    // Ближайшее к sample значение с младшими битами target в пределах шкалы
+    fn matched(&self, sample: i64, target: i64, rng: &mut KeyedRng) -> i64 {
+        let modulus = 1i64 << self.depth;
+        let up = (target - sample).rem_euclid(modulus);
+        if up == 0 {
+            return sample;
+        }
+        let down = up - modulus;
+
+        let (near, far) = if up < -down || (up == -down && rng.next() & 1 == 0) {
+            (sample + up, sample + down)
+        } else {
+            (sample + down, sample + up)
+        };
+        if (-self.full_scale()..self.full_scale()).contains(&near) { near } else { far }
+    }
+}
+
+impl StegoMethod for LeastSignificantBits {
+    fn id(&self) -> &'static str {
+        LSB_ID
+    }
+
+    fn parameters(&self) -> Vec<String> {
+        vec![self.sample_bits.to_string(), self.depth.to_string()]
+    }
+
+    fn capacity(&self, container: &[Vec<f64>]) -> usize {
+        ChannelMode::All.stream(container).len() * self.depth as usize
+    }
+
+    // Ключ нужен только как зерно перестановки, поэтому его длина не зависит от кадра
+    fn key_len(&self, container: &[Vec<f64>], bits_num: usize) -> usize {
+        if bits_num <= self.capacity(container) { KEY_CHIPS } else { 0 }
+    }
+
+    fn capacity_for_key_len(&self, container: &[Vec<f64>], key_len: usize) -> usize {
+        if key_len >= KEY_CHIPS { self.capacity(container) } else { 0 }
+    }
+
+    fn chips_per_bit(&self, _key_len: usize) -> Option<usize> {
+        None
+    }
+
+    fn embed(&self, container: &[Vec<f64>], bits: &[bool], key: &[i16]) -> Result<Vec<Vec<f64>>, StegoError> {
+        if key.len() < KEY_CHIPS {
+            return Err(StegoError::InvalidArgument(format!("{}: длина ключа {} меньше {}", LSB_ID, key.len(), KEY_CHIPS)));
+        }
+        let capacity = self.capacity(container);
+        if bits.len() > capacity {
+            return Err(StegoError::ContainerTooSmall { required: bits.len(), available: capacity });
+        }
+
+        let mut stream = ChannelMode::All.stream(container);
+        let positions = keyed_positions(stream.len(), key);
+        let mut rng = KeyedRng::new(key, MATCHING_STREAM);
+        let depth = self.depth as usize;
+
+        for (group, &position) in bits.chunks(depth).zip(&positions) {
+            let sample = self.quantize(stream[position]);
+            // Последний отсчет может нести меньше depth бит: остальные младшие биты не меняются
+            let target = (0..depth).fold(0, |acc, j| {
+                let bit = group.get(j).copied().unwrap_or(sample >> (depth - 1 - j) & 1 == 1);
+                acc << 1 | bit as i64
+            });
+            stream[position] = self.matched(sample, target, &mut rng) as f64 / self.full_scale() as f64;
+        }
+
+        Ok(ChannelMode::All.merge(container, &stream))
+    }
+
+    // Младшие биты читаются из самого стегаконтейнера, исходный контейнер не нужен
+    fn demodulate(&self, stegocontainer: &[Vec<f64>], _container: Option<&[Vec<f64>]>, key: &[i16]) -> Result<Box<dyn Demodulated>, StegoError> {
+        let stream = ChannelMode::All.stream(stegocontainer);
+        Ok(Box::new(LsbFrame {
+            positions: keyed_positions(stream.len(), key),
+            samples: stream.iter().map(|&x| self.quantize(x)).collect(),
+            depth: self.depth as usize,
+        }))
+    }
+
+    fn sync_unit(&self) -> &'static str {
+        "отсчетов"
+    }
+
+    // Сила встраивания для LSB - количество младших бит на отсчет
+    fn with_strength(&self, strength: f64) -> Result<Arc<dyn StegoMethod>, StegoError> {
+        let depth = (strength.round() as u8).clamp(1, MAX_DEPTH.min(self.sample_bits as u8 - 1));
+        Ok(Arc::new(LeastSignificantBits { depth, ..*self }))
+    }
+
+    fn bench_strengths(&self) -> Vec<f64> {
+        vec![1.0, 2.0, 4.0]
+    }
+}
+
+struct LsbFrame {
+    positions: Vec<usize>,
+    samples: Vec<i64>,
+    depth: usize,
+}
+
+impl Demodulated for LsbFrame {
+    fn capacity(&self) -> usize {
+        self.samples.len() * self.depth
+    }
+
+    fn soft_bits(&self, first_bit: usize, bits_num: usize) -> Result<Vec<f64>, StegoError> {
+        if first_bit + bits_num > self.capacity() {
+            return Err(StegoError::ContainerTooSmall { required: first_bit + bits_num, available: self.capacity() });
+        }
+
+        Ok((first_bit..first_bit + bits_num)
+            .map(|i| {
+                let sample = self.samples[self.positions[i / self.depth]];
+                if sample >> (self.depth - 1 - i % self.depth) & 1 == 1 { 1.0 } else { -1.0 }
+            })
+            .collect())
+    }
+
+    fn sync(&self) -> Option<SyncPoint> {
+        None
+    }
+}
+
+// Потоки генератора для перестановки отсчетов и для выбора направления сдвига
+const POSITIONS_STREAM: u64 = 1;
+const MATCHING_STREAM: u64 = 2;
+
+// Перестановка индексов отсчетов (Фишер-Йейтс), порожденная ключом
+fn keyed_positions(len: usize, key: &[i16]) -> Vec<usize> {
+    let mut rng = KeyedRng::new(key, POSITIONS_STREAM);
+    let mut positions: Vec<usize> = (0..len).collect();
+    for i in (1..len).rev() {
+        positions.swap(i, (rng.next() % (i as u64 + 1)) as usize);
+    }
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip_generator::ChipGenerator;
+    use crate::park_miller_prng::ParkMiller;
+    use crate::test_signals::{bits, noise, requantize_16bit};
+
+    // Целочисленный 16-битный контейнер со случайными отсчетами
+    fn container(frames: usize) -> Vec<Vec<f64>> {
+        let channel = |seed: u32| -> Vec<f64> { noise(frames, seed).iter().map(|x| 0.5 * x).collect() };
+        requantize_16bit(&[channel(99), channel(100)])
+    }
+
+    #[test]
+    fn test_round_trip_with_keyed_positions() {
+        let container = container(1000);
+        let key = ParkMiller::with_seed(11).unwrap().generate_prs(KEY_CHIPS);
+
+        for depth in [1, 2, 3] {
+            let method = LeastSignificantBits::new(16, depth).unwrap();
+            assert_eq!(method.capacity(&container), 2000 * depth as usize);
+            let bits = bits(1001);
+            let stego = method.embed(&container, &bits, &key).unwrap();
+
+            let frame = method.demodulate(&stego, None, &key).unwrap();
+            let recovered: Vec<bool> = frame.soft_bits(0, bits.len()).unwrap().iter().map(|&b| b > 0.0).collect();
+            assert_eq!(recovered, bits);
+
+            // Отсчеты меняются не больше чем на половину шага младших бит
+            let max_change = container.iter().flatten().zip(stego.iter().flatten())
+                .map(|(x, y)| ((x - y) * 32768.0).abs().round() as i64)
+                .max()
+                .unwrap();
+            assert!(max_change <= 1 << (depth - 1));
+        }
+    }
+
+    #[test]
+    fn test_positions_depend_on_key() {
+        let container = container(1000);
+        let method = LeastSignificantBits::new(16, 1).unwrap();
+        let key = ParkMiller::with_seed(1).unwrap().generate_prs(KEY_CHIPS);
+        let stego = method.embed(&container, &bits(200), &key).unwrap();
+
+        // Изменения разбросаны по всему контейнеру, а не идут подряд с начала
+        let changed: Vec<usize> = container[0].iter().zip(&stego[0]).enumerate()
+            .filter(|(_, (x, y))| x != y)
+            .map(|(i, _)| i)
+            .collect();
+        assert!(changed.iter().any(|&i| i > 500));
+
+        let other_key = ParkMiller::with_seed(2).unwrap().generate_prs(KEY_CHIPS);
+        let frame = method.demodulate(&stego, None, &other_key).unwrap();
+        let recovered: Vec<bool> = frame.soft_bits(0, 200).unwrap().iter().map(|&b| b > 0.0).collect();
+        assert_ne!(recovered, bits(200));
+    }
+
+    #[test]
+    fn test_matching_keeps_samples_in_range() {
+        let method = LeastSignificantBits::new(8, 2).unwrap();
+        let mut rng = KeyedRng::new(&[1, -1], 0);
+        assert_eq!(method.matched(127, 0, &mut rng), 124);
+        assert_eq!(method.matched(-127, 3, &mut rng), -125);
+        assert_eq!(method.matched(5, 1, &mut rng), 5);
+
+        // Равное расстояние вверх и вниз: направление случайное
+        let directions: Vec<i64> = (0..32).map(|_| method.matched(4, 2, &mut rng)).collect();
+        assert!(directions.contains(&2) && directions.contains(&6));
+    }
+
+    #[test]
+    fn test_embed_rejects_extra_bits_and_short_key() {
+        let container = container(100);
+        let method = LeastSignificantBits::new(16, 2).unwrap();
+        let key = ParkMiller::with_seed(11).unwrap().generate_prs(KEY_CHIPS);
+
+        assert!(method.embed(&container, &bits(400), &key).is_ok());
+        assert!(matches!(method.embed(&container, &bits(401), &key), Err(StegoError::ContainerTooSmall { required: 401, available: 400 })));
+        assert!(matches!(method.embed(&container, &bits(10), &key[..KEY_CHIPS - 1]), Err(StegoError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_rejects_invalid_parameters() {
+        assert!(LeastSignificantBits::new(16, 0).is_err());
+        assert!(LeastSignificantBits::new(8, 8).is_err());
+        assert!(LeastSignificantBits::new(1, 1).is_err());
+        assert!(matches!(LeastSignificantBits::from_parameters(&["16"]), Err(StegoError::MalformedKey(_))));
+        assert_eq!(LeastSignificantBits::from_parameters(&["24", "3"]).unwrap(), LeastSignificantBits { sample_bits: 24, depth: 3 });
+    }
+}
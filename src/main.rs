@@ -1,59 +1,123 @@
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
-use crate::park_miller_prng::ParkMiller;
-use crate::st::st;
+use std::process::ExitCode;
+use std::sync::Arc;
+use clap::ArgMatches;
+use lab_3::hmac_prng::HMAC_SHA256_ID;
+use lab_3::bench::bench;
+use lab_3::quality::quality_report;
+use lab_3::{Embedder, EmbeddingPlan, Extractor, FecConfig, InnerCode, StegoError, StegoMethod};
 use crate::utils::{init_cli, process_files, 
-    ProcessResult, generate_wav, plot_wav_amplitudes, 
-    count_bits_per_char, write_key_to_file, 
+    ProcessResult, generate_wav, get_wav_file_data, plot_wav_amplitudes, 
+    required_arg, write_key_to_file, write_key_spec_to_file, write_quality_report,
+    write_bench_csv,
     save_amplitudes_to_wav, WavFile};
-use crate::dest::dest;
 
 
 mod utils;
-mod park_miller_prng;
-mod st;
-mod dest;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> ExitCode {
     let matches = init_cli().unwrap_or_else(|e| e.exit());
 
-    if matches.get_flag("generate-wav") {
-        generate_wav(&matches)?;
-        println!("WAV-файл был сгенерирован");
-    } else {
-        let data = process_files(&matches)?;
+    match run(&matches) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
 
-        match data {
-            ProcessResult::Encrypt(data) => {
-                let bits_per_char = count_bits_per_char(&*data.message)?;
+fn run(matches: &ArgMatches) -> Result<(), StegoError> {
+    let Some((command, matches)) = matches.subcommand() else {
+        return Err(StegoError::InvalidArgument("не указана подкоманда".to_string()));
+    };
 
-                let samples_per_msg_bit: usize = (data.container.samples_num as f64 / (bits_per_char * data.message.len()) as f64).floor() as usize;
+    match command {
+        "generate" => {
+            generate_wav(matches)?;
+            println!("WAV-файл был сгенерирован");
+        }
+        "info" => {
+            let wav = get_wav_file_data(required_arg::<String>(matches, "file")?)?;
+            println!("Файл: {}", wav.name);
+            println!("Формат отсчетов: {:?}, {} бит", wav.sample_format, wav.bits_per_sample);
+            println!("Каналов: {}", wav.channels);
+            println!("Частота дискретизации: {} Гц", wav.sample_rate);
+            println!("Отсчетов в канале: {}", wav.samples_num);
+            println!("Длительность: {:.2} с", wav.samples_num as f64 / wav.sample_rate as f64);
+        }
+        "plot" => {
+            let wav = get_wav_file_data(required_arg::<String>(matches, "file")?)?;
+            let plotname = match matches.get_one::<String>("output") {
+                Some(output) => output.clone(),
+                None => Path::new(&wav.name).with_extension("png").to_string_lossy().into_owned(),
+            };
+            plot_wav_amplitudes(&wav, &plotname)?;
+            println!("График амплитуд сохранен в {}", plotname);
+        }
+        command => match process_files(command, matches)? {
+            ProcessResult::Encrypt(data) => {
+                let mut embedder = configure_embedder(matches, data.method.clone())?;
 
-                if samples_per_msg_bit == 0 {
-                    return Err(Box::new(std::io::Error::new(
-                        std::io::ErrorKind::InvalidInput,
-                        "Недостаточно отсчетов, чтобы спрятать сообщение",
-                    )));
+                // Адаптивное усиление допускается только для dsss (см. build_method)
+                if required_arg::<String>(matches, "gain")? == "adaptive" {
+                    match matches.get_one::<String>("domain").map(String::as_str) {
+                        None | Some("time") => println!("Амплитуда ПСП подстроена под порог маскирования"),
+                        Some(_) => println!("Сила встраивания ослаблена на {} дБ относительно силы по умолчанию", required_arg::<f64>(matches, "mask-margin")?),
+                    }
+                }
+                if required_arg::<String>(matches, "generator")? == HMAC_SHA256_ID {
+                    // ПСП hmac-sha256 выводится из пароля и случайной соли: зерно ей не нужно,
+                    // а ключ в формате chips раскрыл бы ее целиком и сделал пароль бесполезным
+                    if matches.contains_id("seed") {
+                        return Err(StegoError::InvalidArgument("--seed не применяется к генератору hmac-sha256".to_string()));
+                    }
+                    if required_arg::<String>(matches, "key-format")? == "chips" {
+                        return Err(StegoError::InvalidArgument(
+                            "ключ в формате chips раскрывает ПСП hmac-sha256 без пароля, используйте --key-format seed".to_string(),
+                        ));
+                    }
+                    let passphrase = matches.get_one::<String>("passphrase").ok_or(StegoError::MissingPassphrase(
+                        "для генератора hmac-sha256 необходим пароль (--passphrase)",
+                    ))?;
+                    embedder = embedder.passphrase(passphrase);
+                } else if let Some(&seed) = matches.get_one::<u32>("seed") {
+                    embedder = embedder.seed(seed);
+                }
+                if matches.contains_id("payload-passphrase") {
+                    println!("Сообщение зашифровано ChaCha20-Poly1305");
                 }
 
+                // Если сообщение не помещается, ошибка до записи каких-либо файлов
+                let capacity = embedder.capacity(&data.container.amplitudes)?;
+                println!("Емкость контейнера: {} бит вместе с заголовком, до {} байт сообщения", capacity.frame_bits, capacity.payload_bytes);
+                print_plan(&embedder.plan(&data.container.amplitudes, &data.message)?);
+                let embedded = embedder.embed(&data.container.amplitudes, &data.message)?;
 
-                let mut generator = ParkMiller::new();
-                let psp = generator.generate_prs(samples_per_msg_bit);
-                let key_filename = matches.get_one::<String>("key").unwrap().clone();
-                write_key_to_file(&psp, key_filename.clone().as_str())?;
+                let key_filename = required_arg::<String>(matches, "key")?.clone();
+                println!("Генератор ПСП: {}", embedded.key.id());
+                print_method(embedded.method.as_ref());
+                if required_arg::<String>(matches, "key-format")? == "chips" {
+                    write_key_to_file(&embedded.psp, embedded.method.as_ref(), key_filename.as_str())?;
+                } else {
+                    write_key_spec_to_file(&embedded.key, embedded.method.as_ref(), key_filename.as_str())?;
+                }
 
-                println!("___ДАННЫЕ ДЛЯ ДЕКОДИРОВАНИЯ___");
-                println!("n: {}\nm: {}\nN: {}", bits_per_char, data.message.len(), samples_per_msg_bit);
+                if embedded.header.is_none() {
+                    println!("___ДАННЫЕ ДЛЯ ДЕКОДИРОВАНИЯ___");
+                    println!("n: {}\nm: {}\nN: {}", embedded.bits_per_symbol, embedded.message_len, embedded.psp.len());
+                }
                 println!("Ключ для декодирования был сохранен в {}", key_filename);
                 println!("График исходного сигнала был сохранен в container.png");
                 
                 plot_wav_amplitudes(&data.container, "container.png")?;
-                let result_amplitudes = st(&data, samples_per_msg_bit, bits_per_char, data.message.len(), psp);
                 let new_wav = WavFile {
-                    name: matches.get_one::<String>("stegacontainer").unwrap().clone(),
-                    amplitudes: result_amplitudes,
+                    name: required_arg::<String>(matches, "stegacontainer")?.clone(),
+                    amplitudes: embedded.amplitudes,
                     bits_per_sample: data.container.bits_per_sample,
+                    sample_format: data.container.sample_format,
                     channels: data.container.channels,
                     sample_rate: data.container.sample_rate,
                     samples_num: data.container.samples_num,
@@ -62,18 +126,149 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 println!("График измененного сигнала сохранен в stegacontainer.png");
                 plot_wav_amplitudes(&new_wav, "stegacontainer.png")?;
                 save_amplitudes_to_wav(&new_wav)?;
+
+                // Сравнивается записанный файл, то есть с учетом квантования отсчетов
+                let stegocontainer = get_wav_file_data(&new_wav.name)?;
+                let report = quality_report(&data.container.amplitudes, &stegocontainer.amplitudes, data.container.sample_rate)?;
+                println!("___ОЦЕНКА ИСКАЖЕНИЙ___");
+                println!("{}", report);
+                if let Some(report_path) = matches.get_one::<String>("quality-report") {
+                    write_quality_report(&report, report_path)?;
+                    println!("Оценка искажений сохранена в {}", report_path);
+                }
             }
             ProcessResult::Decrypt(data) => {
-                let bits_per_char = *matches.get_one::<usize>("bits-per-char").unwrap();
-                let message_len = *matches.get_one::<usize>("message-len").unwrap();
-                let samples_per_msg_bit: usize = (data.container.samples_num as f64 / (bits_per_char * message_len) as f64).floor() as usize;
-                let recovered_message = dest(&data, samples_per_msg_bit, bits_per_char, message_len);
-                let mut message_file = File::create(Path::new(matches.get_one::<String>("message").unwrap()))?;
+                let mut extractor = Extractor::new(data.key);
+                if let Some(container) = &data.container {
+                    extractor = extractor.container(&container.amplitudes);
+                }
+                if let Some(method) = data.method {
+                    extractor = extractor.method(method);
+                }
+                if let Some(channel_mode) = data.channel_mode {
+                    extractor = extractor.channel_mode(channel_mode);
+                }
+                if let Some(domain) = data.domain {
+                    extractor = extractor.domain(domain);
+                }
+                if let Some(passphrase) = matches.get_one::<String>("payload-passphrase") {
+                    extractor = extractor.payload_passphrase(passphrase);
+                }
+                if let (Some(&bits_per_char), Some(&message_len)) = (matches.get_one::<usize>("bits-per-char"), matches.get_one::<usize>("message-len")) {
+                    extractor = extractor.without_header(bits_per_char, message_len);
+                }
+
+                let extracted = extractor.extract(&data.stegocontainer.amplitudes)?;
+                if let Some(sync) = extracted.sync {
+                    println!("Начало кадра: смещение {} {}, пик корреляции с преамбулой {:.3}", sync.offset, extracted.method.sync_unit(), sync.peak);
+                }
+                if let Some(min_margin) = extracted.min_margin {
+                    println!("Минимальный запас решения по корреляции: {:e}", min_margin);
+                }
+                if let Some(header) = &extracted.header {
+                    print_method(extracted.method.as_ref());
+                    println!("Найден заголовок: n: {}, m: {}", header.bits_per_symbol, header.payload_len);
+                }
+                if extracted.copies > 1 {
+                    println!("Сложено копий кадра: {}", extracted.copies);
+                }
+                let recovered_message = extracted.message;
+                let message_path = required_arg::<String>(matches, "message")?;
+                let mut message_file = File::create(Path::new(message_path))?;
                 message_file.write_all(&recovered_message)?;
-                println!("Сообщение получено и сохранено в {}", matches.get_one::<String>("message").unwrap());
+                println!("Сообщение получено и сохранено в {}", message_path);
             }
-        }
+            ProcessResult::Capacity(data) => {
+                let embedder = configure_embedder(matches, data.method.clone())?;
+                let capacity = embedder.capacity(&data.container.amplitudes)?;
+                print_method(data.method.as_ref());
+                println!("Емкость контейнера: {} бит вместе с заголовком", capacity.frame_bits);
+                println!("Наибольшая длина сообщения: {} байт", capacity.payload_bytes);
+                if let Some(message) = &data.message {
+                    print_plan(&embedder.plan(&data.container.amplitudes, message)?);
+                }
+            }
+            ProcessResult::Attack(data) => {
+                let amplitudes = data.attack.apply(&data.stegocontainer.amplitudes, data.stegocontainer.sample_rate);
+                let attacked = WavFile {
+                    name: required_arg::<String>(matches, "output")?.clone(),
+                    samples_num: amplitudes.first().map_or(0, |c| c.len()) as u32,
+                    amplitudes,
+                    ..data.stegocontainer
+                };
+                save_amplitudes_to_wav(&attacked)?;
+                println!("Атака {} применена, результат сохранен в {}", data.attack, attacked.name);
+            }
+            ProcessResult::Bench(data) => {
+                let strengths: Vec<f64> = match matches.get_many::<f64>("bench-strength") {
+                    Some(strengths) => strengths.copied().collect(),
+                    None => data.method.bench_strengths(),
+                };
+                print_method(data.method.as_ref());
+                let rows = bench(&data.container.amplitudes, data.container.sample_rate, &data.message, data.method.as_ref(), &data.attacks, &strengths)?;
+
+                let ber = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{:.4}", v));
+                println!("{:<22} {:>10} {:>12} {:>12}", "Атака", "Сила", "BER", "BER (слеп.)");
+                for row in &rows {
+                    let attack = row.attack.map_or("none".to_string(), |a| a.to_string());
+                    println!("{:<22} {:>10} {:>12} {:>12}", attack, row.strength, ber(row.informed_ber), ber(row.blind_ber));
+                }
+                if let Some(csv_path) = matches.get_one::<String>("bench-csv") {
+                    write_bench_csv(&rows, csv_path)?;
+                    println!("Результаты сохранены в {}", csv_path);
+                }
+            }
+        },
     }
 
     Ok(())
 }
+
+// Настройки встраивания, общие для embed и capacity: алгоритм, заголовок, код, шифрование
+// сообщения, количество позиций на бит и повторение кадра
+fn configure_embedder(matches: &ArgMatches, method: Arc<dyn StegoMethod>) -> Result<Embedder, StegoError> {
+    let mut embedder = Embedder::new()
+        .method(method)
+        .min_chips_per_bit(*required_arg::<usize>(matches, "min-chips")?);
+
+    if matches.get_flag("no-header") {
+        embedder = embedder.without_header();
+    } else {
+        embedder = embedder.fec(FecConfig {
+            inner: match required_arg::<String>(matches, "fec")?.as_str() {
+                "repetition" => InnerCode::Repetition(*required_arg::<u8>(matches, "fec-repeat")?),
+                "hamming" => InnerCode::Hamming74,
+                "convolutional" => InnerCode::Convolutional,
+                _ => InnerCode::None,
+            },
+            rs_parity: *required_arg::<u8>(matches, "rs-parity")?,
+        });
+    }
+    if let Some(passphrase) = matches.get_one::<String>("payload-passphrase") {
+        embedder = embedder.payload_passphrase(passphrase);
+    }
+    if let Some(&chips) = matches.get_one::<usize>("chips-per-bit") {
+        embedder = embedder.chips_per_bit(chips);
+    }
+    if matches.get_flag("repeat") {
+        embedder = embedder.repeat();
+    }
+    Ok(embedder)
+}
+
+fn print_plan(plan: &EmbeddingPlan) {
+    match (plan.chips_per_bit, plan.processing_gain_db()) {
+        (Some(chips), Some(gain)) => println!("Кадр: {} бит, позиций контейнера на бит: {} (выигрыш от расширения {:.1} дБ)", plan.frame_bits, chips, gain),
+        _ => println!("Кадр: {} бит", plan.frame_bits),
+    }
+    if plan.copies > 1 {
+        println!("Копий кадра в контейнере: {}", plan.copies);
+    }
+}
+
+fn print_method(method: &dyn StegoMethod) {
+    match method.parameters().as_slice() {
+        [] => println!("Метод встраивания: {}", method.id()),
+        parameters => println!("Метод встраивания: {} ({})", method.id(), parameters.join(", ")),
+    }
+}
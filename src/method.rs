@@ -0,0 +1,134 @@
+use std::fmt;
+use std::sync::Arc;
+use crate::dsss::{SpreadSpectrum, DSSS_ID};
+use crate::echo::{EchoHiding, ECHO_ID};
+use crate::error::StegoError;
+use crate::lsb::{LeastSignificantBits, LSB_ID};
+use crate::phase::{PhaseCoding, PHASE_ID};
+use crate::qim::{QuantizationIndexModulation, QIM_ID};
+use crate::sync::SyncPoint;
+
+// Алгоритм встраивания бит кадра в отсчеты контейнера. Кадр (заголовок и сообщение после
+// помехоустойчивого кода, см. StegoHeader::encode_frame) собирается снаружи, поэтому
+// алгоритм отвечает только за то, как биты попадают в сигнал и как потом находятся.
+// Ключ - ПСП, порожденная генератором из файла ключа; ее длину выбирает алгоритм.
+//
+// Параметры алгоритма (политика каналов, область и т.п.) записываются в ключ рядом с его
+// идентификатором, чтобы при извлечении их не нужно было перебирать (см. from_parameters).
+pub trait StegoMethod: fmt::Debug + Send + Sync {
+    fn id(&self) -> &'static str;
+
+    // Параметры, из которых алгоритм восстанавливается функцией from_parameters
+    fn parameters(&self) -> Vec<String>;
+
+    // Наибольшее количество бит кадра, которое помещается в контейнер
+    fn capacity(&self, container: &[Vec<f64>]) -> usize;
+
+    // Длина ПСП для встраивания bits_num бит кадра; 0 - кадр не помещается
+    fn key_len(&self, container: &[Vec<f64>], bits_num: usize) -> usize;
+
+    // Количество бит, которое помещается в контейнер при заданной длине ПСП (например, при
+    // фиксированном количестве позиций на бит); 0 - такая длина алгоритму не подходит
+    fn capacity_for_key_len(&self, container: &[Vec<f64>], key_len: usize) -> usize;
+
+    // Количество отсчетов (коэффициентов) контейнера, по которым распределен один бит при
+    // длине ключа key_len; None, если бит занимает одну позицию и выигрыша от расширения нет
+    fn chips_per_bit(&self, key_len: usize) -> Option<usize>;
+
+    // Ошибка, если биты не помещаются или встраивание испортило бы сигнал (например, перегрузкой)
+    fn embed(&self, container: &[Vec<f64>], bits: &[bool], key: &[i16]) -> Result<Vec<Vec<f64>>, StegoError>;
+
+    // Поиск начала кадра в стегаконтейнере. Если исходный контейнер не передан, извлечение слепое.
+    fn demodulate(&self, stegocontainer: &[Vec<f64>], container: Option<&[Vec<f64>]>, key: &[i16]) -> Result<Box<dyn Demodulated>, StegoError>;
+
+    // Единица, в которой выражено смещение начала кадра (SyncPoint::offset)
+    fn sync_unit(&self) -> &'static str;
+
+    // Тот же алгоритм с постоянной силой встраивания strength (для проверки устойчивости).
+    // Ошибка, если такая сила недопустима для алгоритма
+    fn with_strength(&self, strength: f64) -> Result<Arc<dyn StegoMethod>, StegoError>;
+
+    // Силы встраивания по умолчанию для проверки устойчивости: от незаметной до заведомо слышимой
+    fn bench_strengths(&self) -> Vec<f64>;
+}
+
+// Найденный в стегаконтейнере кадр, из которого читаются мягкие решения по битам:
+// знак определяет бит, а модуль показывает запас решения
+pub trait Demodulated {
+    // Количество бит кадра, которые можно прочитать
+    fn capacity(&self) -> usize;
+
+    fn soft_bits(&self, first_bit: usize, bits_num: usize) -> Result<Vec<f64>, StegoError>;
+
+    // Найденное начало кадра; None, если алгоритм не ищет его (кадр с первого отсчета)
+    fn sync(&self) -> Option<SyncPoint>;
+}
+
+pub const METHOD_IDS: [&str; 5] = [DSSS_ID, LSB_ID, ECHO_ID, PHASE_ID, QIM_ID];
+
+// Алгоритм по идентификатору и параметрам из файла ключа
+pub fn from_parameters(id: &str, parameters: &[&str]) -> Result<Arc<dyn StegoMethod>, StegoError> {
+    match id {
+        DSSS_ID => Ok(Arc::new(SpreadSpectrum::from_parameters(parameters)?)),
+        LSB_ID => Ok(Arc::new(LeastSignificantBits::from_parameters(parameters)?)),
+        ECHO_ID => Ok(Arc::new(EchoHiding::from_parameters(parameters)?)),
+        PHASE_ID => Ok(Arc::new(PhaseCoding::from_parameters(parameters)?)),
+        QIM_ID => Ok(Arc::new(QuantizationIndexModulation::from_parameters(parameters)?)),
+        other => Err(StegoError::MalformedKey(format!("неизвестный метод встраивания {}", other))),
+    }
+}
+
+// Идентификатор и параметры через запятую, как они записываются в ключ
+pub fn to_parameters_line(method: &dyn StegoMethod) -> String {
+    std::iter::once(method.id().to_string()).chain(method.parameters()).collect::<Vec<_>>().join(",")
+}
+
+// Наибольший пик стегаконтейнера: с запасом на округление до 16 бит
+pub const MAX_PEAK: f64 = 0.999;
+
+// Алгоритмы, которые поднимают пики сигнала (эхо, поворот фаз), одинаково уменьшают все
+// каналы, если пик выше MAX_PEAK: иначе при записи WAV отсчеты обрезались бы вместе со
+// встроенными битами. Общий множитель не меняет ни кепстра, ни фаз, ни баланса каналов.
+pub(crate) fn limit_peak(channels: &mut [Vec<f64>]) {
+    let peak = channels.iter().flatten().fold(0.0f64, |peak, x| peak.max(x.abs()));
+    if peak > MAX_PEAK {
+        channels.iter_mut().flatten().for_each(|x| *x *= MAX_PEAK / peak);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::ChannelMode;
+    use crate::transform::Domain;
+
+    #[test]
+    fn test_parameters_round_trip() {
+        let methods = [
+            SpreadSpectrum::default(),
+            SpreadSpectrum { channel_mode: ChannelMode::MidSide, domain: Domain::Mdct, gain: None },
+            SpreadSpectrum { channel_mode: ChannelMode::All, domain: Domain::Fft { low: 300.0 / 44100.0, high: 0.1 }, gain: None },
+        ];
+
+        let lsb = LeastSignificantBits { sample_bits: 16, depth: 2 };
+        let echo = EchoHiding { channel_mode: ChannelMode::All, ..EchoHiding::default() };
+        let qim = QuantizationIndexModulation { step: 0.01, ..QuantizationIndexModulation::default() };
+
+        for method in methods.iter().map(|m| m as &dyn StegoMethod).chain([&lsb as &dyn StegoMethod, &echo, &PhaseCoding, &qim]) {
+            let line = to_parameters_line(method);
+            let tokens: Vec<&str> = line.split(',').collect();
+            let restored = from_parameters(tokens[0], &tokens[1..]).unwrap();
+            assert_eq!(restored.id(), method.id());
+            assert_eq!(restored.parameters(), method.parameters());
+        }
+        assert_eq!(to_parameters_line(&SpreadSpectrum::default()), "dsss,left,time");
+        assert_eq!(to_parameters_line(&lsb), "lsb,16,2");
+        assert_eq!(to_parameters_line(&echo), "echo,all,50,75");
+        assert_eq!(to_parameters_line(&PhaseCoding), "phase");
+        assert_eq!(to_parameters_line(&qim), "qim,left,0.01");
+
+        assert!(matches!(from_parameters("wavelet", &[]), Err(StegoError::MalformedKey(_))));
+        assert!(matches!(from_parameters(DSSS_ID, &["left"]), Err(StegoError::MalformedKey(_))));
+        assert!(matches!(from_parameters(DSSS_ID, &["left", "fft", "x", "0.1"]), Err(StegoError::MalformedKey(_))));
+    }
+}
@@ -1,6 +1,16 @@
 use std::time::{SystemTime, UNIX_EPOCH};
+use crate::chip_generator::ChipGenerator;
+use crate::error::StegoError;
+
+pub const PARK_MILLER_ID: &str = "park-miller";
+
+const MODULUS: i64 = 2147483647;
+// Наибольшее зерно: зерна 0..=MAX_SEED взаимно однозначно отображаются в ненулевые
+// состояния [1, 2^31 - 2], поэтому разные зерна дают разные ПСП
+pub const MAX_SEED: u32 = (MODULUS - 2) as u32;
 
 pub struct ParkMiller {
+    seed: u32,
     state: i64,
 }
 
@@ -9,13 +19,30 @@ impl ParkMiller {
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
-            .as_micros() as i64;
+            .as_micros();
+
+        ParkMiller::from_valid_seed((now % (MAX_SEED as u128 + 1)) as u32)
+    }
 
-        ParkMiller { state: now % 100 }
+    // Нулевое состояние у генератора Парка-Миллера вырождено, поэтому зерно
+    // сдвигается на единицу. Зерна больше MAX_SEED совпали бы с меньшими и отклоняются
+    pub fn with_seed(seed: u32) -> Result<Self, StegoError> {
+        if seed > MAX_SEED {
+            return Err(StegoError::InvalidArgument(format!("зерно {} {} больше {}", PARK_MILLER_ID, seed, MAX_SEED)));
+        }
+        Ok(ParkMiller::from_valid_seed(seed))
+    }
+
+    fn from_valid_seed(seed: u32) -> Self {
+        ParkMiller { seed, state: seed as i64 + 1 }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
     }
 
     fn next(&mut self) -> i16 {
-        self.state = (self.state * 16807) % 2147483647;
+        self.state = (self.state * 16807) % MODULUS;
         let value = (self.state as f64) / 2147483647.0 + 0.000000000233;
 
         if value > 0.5 {
@@ -24,12 +51,51 @@ impl ParkMiller {
             -1
         }
     }
+}
+
+impl ChipGenerator for ParkMiller {
+    fn id(&self) -> &'static str {
+        PARK_MILLER_ID
+    }
 
-    pub fn generate_prs(&mut self, num: usize) -> Vec<i16> {
+    fn generate_prs(&mut self, num: usize) -> Vec<i16> {
         let mut prs: Vec<i16> = Vec::new();
         for _ in 0..num {
             prs.push(self.next());
         }
         prs
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_seed_is_reproducible() {
+        let first = ParkMiller::with_seed(12345).unwrap().generate_prs(1000);
+        let second = ParkMiller::with_seed(12345).unwrap().generate_prs(1000);
+        assert_eq!(first, second);
+
+        let other = ParkMiller::with_seed(12346).unwrap().generate_prs(1000);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn test_seeds_do_not_alias() {
+        // Раньше зерна s и s + 2^31 - 2 давали одно и то же состояние
+        let last = ParkMiller::with_seed(MAX_SEED).unwrap().generate_prs(1000);
+        assert_ne!(last, ParkMiller::with_seed(0).unwrap().generate_prs(1000));
+        assert_ne!(last, ParkMiller::with_seed(MAX_SEED - 1).unwrap().generate_prs(1000));
+        assert!(matches!(ParkMiller::with_seed(MAX_SEED + 1), Err(StegoError::InvalidArgument(_))));
+        assert!(ParkMiller::with_seed(u32::MAX).is_err());
+        assert!(ParkMiller::new().seed() <= MAX_SEED);
+    }
+
+    #[test]
+    fn test_zero_seed_is_not_degenerate() {
+        let prs = ParkMiller::with_seed(0).unwrap().generate_prs(1000);
+        assert!(prs.contains(&1));
+        assert!(prs.contains(&-1));
+    }
+}
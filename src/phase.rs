@@ -0,0 +1,355 @@
+use std::f64::consts::{FRAC_PI_2, PI};
+use std::sync::Arc;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use crate::error::StegoError;
+use crate::method::{limit_peak, Demodulated, StegoMethod};
+use crate::sync::SyncPoint;
+
+pub const PHASE_ID: &str = "phase";
+
+// Границы длины кадра: все биты кадра должны поместиться в бины одного кадра
+pub const MIN_FRAME: usize = 256;
+pub const MAX_FRAME: usize = 4096;
+// Нулевая частота не несет фазы
+const FIRST_BIN: usize = 1;
+
+// Фазовое кодирование. Первый канал делится на кадры длиной с ключ, и биты записываются
+// в фазы бинов первого кадра: +pi/2 для бита 0 и -pi/2 для бита 1. Ухо почти не слышит
+// абсолютную фазу, но слышит изменение разности фаз между соседними кадрами, поэтому
+// весь остальной сигнал поворачивается на те же углы, что и первый кадр, и разности фаз
+// сохраняются. Поворот делается в кадрах с перекрытием (см. rotate_phases), иначе на
+// границах кадров появляются щелчки.
+//
+// Емкость - не больше половины кадра бит (см. MAX_FRAME), поэтому метод подходит для
+// коротких секретов вроде ключей или идентификаторов. Извлечение слепое: достаточно
+// фаз первого кадра стегаконтейнера.
+//
+// Фаза бина с почти нулевым модулем теряется при квантовании отсчетов, поэтому
+// модули бинов с битами в первом кадре поднимаются до MIN_MAGNITUDE.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PhaseCoding;
+
+// Наименьший модуль бина с битом на отсчет кадра (синусоида с амплитудой около 0.002)
+const MIN_MAGNITUDE: f64 = 1e-3;
+
+impl PhaseCoding {
+    pub fn from_parameters(parameters: &[&str]) -> Result<Self, StegoError> {
+        match parameters {
+            [] => Ok(PhaseCoding),
+            _ => Err(StegoError::MalformedKey(format!("параметры {}: параметров не ожидается", PHASE_ID))),
+        }
+    }
+
+    fn frame_capacity(frame_len: usize) -> usize {
+        (frame_len / 2).saturating_sub(FIRST_BIN)
+    }
+
+    // Длина кадра из ключа: степень двойки от MIN_FRAME до MAX_FRAME
+    fn check_frame(frame_len: usize) -> Result<(), String> {
+        if !frame_len.is_power_of_two() || !(MIN_FRAME..=MAX_FRAME).contains(&frame_len) {
+            return Err(format!("{}: длина ключа {} не степень двойки от {} до {}", PHASE_ID, frame_len, MIN_FRAME, MAX_FRAME));
+        }
+        Ok(())
+    }
+}
+
+impl StegoMethod for PhaseCoding {
+    fn id(&self) -> &'static str {
+        PHASE_ID
+    }
+
+    fn parameters(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    // Емкость самого длинного кадра, который помещается в первый канал
+    fn capacity(&self, container: &[Vec<f64>]) -> usize {
+        let len = container.first().map_or(0, |channel| channel.len()).min(MAX_FRAME);
+        if len < MIN_FRAME {
+            return 0;
+        }
+        Self::frame_capacity(1 << len.ilog2())
+    }
+
+    // Ключ задает длину кадра: наименьшая степень двойки, в первый кадр которой помещаются все биты
+    fn key_len(&self, container: &[Vec<f64>], bits_num: usize) -> usize {
+        let frame_len = (2 * (bits_num + FIRST_BIN)).next_power_of_two().max(MIN_FRAME);
+        let len = container.first().map_or(0, |channel| channel.len());
+        if frame_len <= MAX_FRAME && frame_len <= len { frame_len } else { 0 }
+    }
+
+    // Длина ключа - длина кадра: степень двойки от MIN_FRAME до MAX_FRAME
+    fn capacity_for_key_len(&self, container: &[Vec<f64>], key_len: usize) -> usize {
+        let len = container.first().map_or(0, |channel| channel.len());
+        if Self::check_frame(key_len).is_ok() && key_len <= len {
+            Self::frame_capacity(key_len)
+        } else {
+            0
+        }
+    }
+
+    // Каждый бит - фаза одного бина первого кадра
+    fn chips_per_bit(&self, _key_len: usize) -> Option<usize> {
+        None
+    }
+
+    fn embed(&self, container: &[Vec<f64>], bits: &[bool], key: &[i16]) -> Result<Vec<Vec<f64>>, StegoError> {
+        let frame_len = key.len();
+        Self::check_frame(frame_len).map_err(StegoError::InvalidArgument)?;
+        let len = container.first().map_or(0, |channel| channel.len());
+        let capacity = self.capacity_for_key_len(container, frame_len);
+        if len < frame_len || bits.len() > capacity {
+            return Err(StegoError::ContainerTooSmall { required: bits.len(), available: capacity });
+        }
+        let channel = &container[0];
+
+        let fft = Fft::new(frame_len);
+        let mut first = fft.forward(&channel[..frame_len]);
+
+        // Поворот фазы каждого бина с битом, который переводит первый кадр в ±pi/2
+        let rotations: Vec<Complex<f64>> = first[FIRST_BIN..].iter().zip(bits)
+            .map(|(c, &bit)| Complex::from_polar(1.0, if bit { -FRAC_PI_2 } else { FRAC_PI_2 } - c.arg()))
+            .collect();
+        for (k, rotation) in (FIRST_BIN..).zip(&rotations) {
+            let magnitude = first[k].norm().max(MIN_MAGNITUDE * frame_len as f64);
+            first[k] = Complex::from_polar(magnitude, first[k].arg()) * rotation;
+        }
+
+        // Остальной сигнал поворачивается теми же углами в кадрах с перекрытием, поэтому
+        // между ними нет разрывов. Первый кадр берется точно таким, как задано, а следующие
+        // полкадра плавно переходят от его периодического продолжения к повернутому сигналу:
+        // иначе на стыке кадра с остальным сигналом был бы щелчок
+        let mut stego = rotate_phases(channel, &rotations, &fft);
+        let frame = fft.inverse(first);
+        let hop = frame_len / 2;
+        for (n, y) in stego[frame_len..].iter_mut().take(hop).enumerate() {
+            let fade = 0.5 - 0.5 * (PI * n as f64 / hop as f64).cos();
+            *y = (1.0 - fade) * frame[n] + fade * *y;
+        }
+        stego[..frame_len].copy_from_slice(&frame);
+
+        let mut result = container.to_vec();
+        result[0] = stego;
+        // Поворот фаз может поднять пики выше полной шкалы
+        limit_peak(&mut result);
+
+        Ok(result)
+    }
+
+    fn demodulate(&self, stegocontainer: &[Vec<f64>], _container: Option<&[Vec<f64>]>, key: &[i16]) -> Result<Box<dyn Demodulated>, StegoError> {
+        let frame_len = key.len();
+        Self::check_frame(frame_len).map_err(StegoError::MalformedKey)?;
+        let channel = stegocontainer.first().map_or(&[][..], |channel| channel.as_slice());
+        if channel.len() < frame_len {
+            return Err(StegoError::ContainerTooSmall { required: frame_len.max(1), available: channel.len() });
+        }
+
+        let spectrum = Fft::new(frame_len).forward(&channel[..frame_len]);
+
+        // -sin(фазы): 1 для -pi/2 (бит 1), -1 для pi/2 (бит 0)
+        let decisions = spectrum[FIRST_BIN..frame_len / 2].iter().map(|c| -c.arg().sin()).collect();
+        Ok(Box::new(PhaseFrame { decisions }))
+    }
+
+    fn sync_unit(&self) -> &'static str {
+        "отсчетов"
+    }
+
+    // У фазового кодирования нет силы встраивания: фаза всегда ±pi/2
+    fn with_strength(&self, _strength: f64) -> Result<Arc<dyn StegoMethod>, StegoError> {
+        Ok(Arc::new(*self))
+    }
+
+    fn bench_strengths(&self) -> Vec<f64> {
+        vec![1.0]
+    }
+}
+
+// Поворот бинов FIRST_BIN.. на rotations в кадрах с окном корень из Ханна и перекрытием 50%.
+// Сигнал дополняется нулями по краям, чтобы каждый отсчет покрывали два окна.
+fn rotate_phases(signal: &[f64], rotations: &[Complex<f64>], fft: &Fft) -> Vec<f64> {
+    let frame_len = fft.len;
+    let hop = frame_len / 2;
+    let window: Vec<f64> = (0..frame_len).map(|n| (PI * n as f64 / frame_len as f64).sin()).collect();
+
+    let mut padded = vec![0.0; hop];
+    padded.extend_from_slice(signal);
+    padded.resize(hop + signal.len() + frame_len, 0.0);
+    let mut output = vec![0.0; padded.len()];
+
+    for start in (0..=padded.len() - frame_len).step_by(hop) {
+        let frame: Vec<f64> = padded[start..start + frame_len].iter().zip(&window).map(|(x, w)| x * w).collect();
+        let mut spectrum = fft.forward(&frame);
+        for (k, rotation) in (FIRST_BIN..).zip(rotations) {
+            spectrum[k] *= rotation;
+        }
+        for ((y, x), w) in output[start..].iter_mut().zip(fft.inverse(spectrum)).zip(&window) {
+            *y += x * w;
+        }
+    }
+
+    output[hop..hop + signal.len()].to_vec()
+}
+
+// Прямое и обратное БПФ вещественного кадра
+struct Fft {
+    len: usize,
+    forward: Arc<dyn rustfft::Fft<f64>>,
+    inverse: Arc<dyn rustfft::Fft<f64>>,
+}
+
+impl Fft {
+    fn new(len: usize) -> Self {
+        let mut planner = FftPlanner::new();
+        Fft { len, forward: planner.plan_fft_forward(len), inverse: planner.plan_fft_inverse(len) }
+    }
+
+    fn forward(&self, frame: &[f64]) -> Vec<Complex<f64>> {
+        let mut spectrum: Vec<Complex<f64>> = frame.iter().map(|&x| Complex::new(x, 0.0)).collect();
+        self.forward.process(&mut spectrum);
+        spectrum
+    }
+
+    // Сопряженно-симметричные бины восстанавливаются по первой половине, чтобы сигнал остался вещественным
+    fn inverse(&self, mut spectrum: Vec<Complex<f64>>) -> Vec<f64> {
+        for k in 1..self.len / 2 {
+            spectrum[self.len - k] = spectrum[k].conj();
+        }
+        self.inverse.process(&mut spectrum);
+        spectrum.iter().map(|s| s.re / self.len as f64).collect()
+    }
+}
+
+// Мягкие решения по фазам бинов первого кадра
+struct PhaseFrame {
+    decisions: Vec<f64>,
+}
+
+impl Demodulated for PhaseFrame {
+    fn capacity(&self) -> usize {
+        self.decisions.len()
+    }
+
+    fn soft_bits(&self, first_bit: usize, bits_num: usize) -> Result<Vec<f64>, StegoError> {
+        self.decisions.get(first_bit..first_bit + bits_num)
+            .map(|decisions| decisions.to_vec())
+            .ok_or(StegoError::ContainerTooSmall { required: first_bit + bits_num, available: self.capacity() })
+    }
+
+    fn sync(&self) -> Option<SyncPoint> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signals::{bits, music, noise, requantize_16bit, sine};
+
+    #[test]
+    fn test_frame_length_fits_bits() {
+        let container = vec![music(10_000)];
+        assert_eq!(PhaseCoding.capacity(&container), MAX_FRAME / 2 - FIRST_BIN);
+        assert_eq!(PhaseCoding.key_len(&container, 10), MIN_FRAME);
+        assert_eq!(PhaseCoding.key_len(&container, 300), 1024);
+        assert_eq!(PhaseCoding.key_len(&container, 3000), 0);
+        assert_eq!(PhaseCoding.capacity(&[music(1000)]), 512 / 2 - FIRST_BIN);
+        assert_eq!(PhaseCoding.capacity(&[music(200)]), 0);
+    }
+
+    #[test]
+    fn test_rejects_malformed_key() {
+        let container = vec![music(10_000)];
+        for key_len in [1, 100, 300, 2 * MAX_FRAME] {
+            assert_eq!(PhaseCoding.capacity_for_key_len(&container, key_len), 0);
+            assert!(matches!(PhaseCoding.demodulate(&container, None, &vec![1; key_len]), Err(StegoError::MalformedKey(_))));
+        }
+        assert!(matches!(PhaseCoding.demodulate(&[music(100)], None, &[1; MIN_FRAME]), Err(StegoError::ContainerTooSmall { .. })));
+    }
+
+    #[test]
+    fn test_embed_rejects_extra_bits_and_malformed_key() {
+        let container = vec![music(10_000)];
+        let key = vec![1; 512];
+
+        assert!(PhaseCoding.embed(&container, &bits(255), &key).is_ok());
+        assert!(matches!(PhaseCoding.embed(&container, &bits(256), &key), Err(StegoError::ContainerTooSmall { required: 256, available: 255 })));
+        for key_len in [0, 100, 300, 2 * MAX_FRAME] {
+            assert!(matches!(PhaseCoding.embed(&container, &bits(10), &vec![1; key_len]), Err(StegoError::InvalidArgument(_))));
+        }
+        assert!(matches!(PhaseCoding.embed(&[music(300)], &bits(10), &key), Err(StegoError::ContainerTooSmall { .. })));
+        assert!(matches!(PhaseCoding.embed(&[], &bits(10), &key), Err(StegoError::ContainerTooSmall { .. })));
+    }
+
+    #[test]
+    fn test_full_scale_first_frame_fits() {
+        // Шум на всю шкалу: после поворота фаз пики вышли бы за ±1
+        let container = vec![noise(20_000, 3).iter().map(|x| 2.0 * x).collect::<Vec<f64>>()];
+        let bits = bits(300);
+        let key = vec![1; PhaseCoding.key_len(&container, bits.len())];
+
+        let embedded = PhaseCoding.embed(&container, &bits, &key).unwrap();
+        assert!(embedded.iter().flatten().all(|x| x.abs() < 1.0));
+
+        let frame = PhaseCoding.demodulate(&requantize_16bit(&embedded), None, &key).unwrap();
+        let recovered: Vec<bool> = frame.soft_bits(0, bits.len()).unwrap().iter().map(|&d| d > 0.0).collect();
+        assert_eq!(recovered, bits);
+    }
+
+    #[test]
+    fn test_blind_round_trip_after_requantization() {
+        let container = vec![music(20_000), music(20_000)];
+        let bits = bits(300);
+        let key = vec![1; PhaseCoding.key_len(&container, bits.len())];
+
+        let stego = requantize_16bit(&PhaseCoding.embed(&container, &bits, &key).unwrap());
+        // Второй канал не изменяется
+        assert_eq!(stego[1], requantize_16bit(&container)[1]);
+
+        let frame = PhaseCoding.demodulate(&stego, None, &key).unwrap();
+        let recovered: Vec<bool> = frame.soft_bits(0, bits.len()).unwrap().iter().map(|&d| d > 0.0).collect();
+        assert_eq!(recovered, bits);
+    }
+
+    #[test]
+    fn test_no_click_after_first_frame() {
+        let len = 20_000;
+        let cover: Vec<f64> = sine(220.0, 0.3, len).iter().zip(sine(1375.0, 0.2, len)).zip(sine(3000.0, 0.1, len))
+            .map(|((a, b), c)| a + b + c)
+            .collect();
+        let container = vec![cover];
+        let bits = bits(300);
+        let key = vec![1; PhaseCoding.key_len(&container, bits.len())];
+        let frame_len = key.len();
+        let stego = PhaseCoding.embed(&container, &bits, &key).unwrap();
+
+        // Скачки на стыке первого кадра с остальным сигналом и в переходе после него
+        // не больше скачков в остальном сигнале (при вставке кадра без перехода - вдвое больше)
+        let step = |range: std::ops::Range<usize>| range.map(|n| (stego[0][n] - stego[0][n - 1]).abs()).fold(0.0f64, f64::max);
+        let seam = step(frame_len..frame_len + frame_len / 2);
+        let rest = step(2 * frame_len..len);
+        assert!(seam <= rest, "скачок на стыке {} больше {}", seam, rest);
+    }
+
+    #[test]
+    fn test_phase_differences_are_preserved() {
+        // Тоны точно на бинах 10 и 37 кадра из 256 отсчетов
+        let tone = |k: f64| (0..16 * 256).map(move |t| (2.0 * std::f64::consts::PI * k * t as f64 / 256.0).sin());
+        let container = vec![tone(10.0).zip(tone(37.0)).map(|(a, b)| 0.2 * a + 0.1 * b).collect::<Vec<f64>>()];
+        let bits = bits(100);
+        let key = vec![1; PhaseCoding.key_len(&container, bits.len())];
+        let stego = PhaseCoding.embed(&container, &bits, &key).unwrap();
+
+        let fft = Fft::new(256);
+        let phase = |channel: &[f64], f: usize, k: usize| fft.forward(&channel[f * 256..(f + 1) * 256])[k].arg();
+
+        // Разность фаз между соседними кадрами та же, что в контейнере (с точностью до 2pi)
+        for k in [10, 37] {
+            for f in 4..8 {
+                let difference = (phase(&container[0], f + 1, k) - phase(&container[0], f, k)) - (phase(&stego[0], f + 1, k) - phase(&stego[0], f, k));
+                assert!(difference.sin().abs() < 0.05 && difference.cos() > 0.0, "бин {} кадр {}: {}", k, f, difference);
+            }
+        }
+    }
+}
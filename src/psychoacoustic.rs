@@ -0,0 +1,259 @@
+use std::f64::consts::PI;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+
+// Психоакустическая модель для адаптивного встраивания.
+//
+// Сигнал разбивается на кадры по FRAME_LEN отсчетов с перекрытием 50% и окном Ханна.
+// Для каждого кадра считается порог маскирования: энергия спектра группируется по
+// критическим полосам (барк), размывается функцией распространения Шредера и уменьшается
+// на смещение "тон маскирует шум". Снизу порог ограничен абсолютным порогом слышимости.
+// ПСП в кадре умножается в частотной области так, чтобы ее мощность в каждом бине была
+// ниже порога на заданный запас, и кадры складываются обратно (overlap-add).
+
+pub const FRAME_LEN: usize = 512;
+const HOP: usize = FRAME_LEN / 2;
+const BANDS: usize = 25;
+
+// Синусоида полной амплитуды соответствует 96 дБ SPL, как в психоакустической модели MPEG-1
+const FULL_SCALE_SPL: f64 = 96.0;
+// Выше ~15 кГц формула Терхардта быстро растет: без ограничения почти вся энергия ПСП
+// уходила бы в верхние частоты, которые срезаются фильтрами и сжатием
+const MAX_ABSOLUTE_THRESHOLD: f64 = 20.0;
+
+// Периодическое окно Ханна: при перекрытии 50% сумма окон равна единице
+fn hann() -> Vec<f64> {
+    (0..FRAME_LEN)
+        .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f64 / FRAME_LEN as f64).cos())
+        .collect()
+}
+
+pub fn bark(freq: f64) -> f64 {
+    13.0 * (0.00076 * freq).atan() + 3.5 * (freq / 7500.0).powi(2).atan()
+}
+
+// Абсолютный порог слышимости (аппроксимация Терхардта), дБ SPL
+pub fn absolute_threshold(freq: f64) -> f64 {
+    let f = (freq / 1000.0).max(0.02);
+    let threshold = 3.64 * f.powf(-0.8) - 6.5 * (-0.6 * (f - 3.3).powi(2)).exp() + 1e-3 * f.powi(4);
+    threshold.min(MAX_ABSOLUTE_THRESHOLD)
+}
+
+// Функция распространения маскирования между полосами (Шредер), дБ
+fn spreading(dz: f64) -> f64 {
+    15.81 + 7.5 * (dz + 0.474) - 17.5 * (1.0 + (dz + 0.474).powi(2)).sqrt()
+}
+
+// Порог маскирования для бинов 0..=FRAME_LEN/2 спектра кадра.
+// power - квадраты модулей спектра кадра, взвешенного окном Ханна; порог в тех же единицах.
+pub fn masking_threshold(power: &[f64], sample_rate: u32) -> Vec<f64> {
+    // Мощность пика спектра синусоиды полной амплитуды с окном Ханна
+    let full_scale_power = (FRAME_LEN as f64 / 4.0).powi(2);
+    let spl_scale = 10f64.powf(FULL_SCALE_SPL / 10.0) / full_scale_power;
+    let bin_freq = |k: usize| k as f64 * sample_rate as f64 / FRAME_LEN as f64;
+    let band_of = |k: usize| (bark(bin_freq(k)) as usize).min(BANDS - 1);
+
+    let mut energy = [0.0; BANDS];
+    let mut bins_in_band = [0usize; BANDS];
+    for (k, &p) in power.iter().enumerate() {
+        energy[band_of(k)] += p * spl_scale;
+        bins_in_band[band_of(k)] += 1;
+    }
+
+    let band_threshold: Vec<f64> = (0..BANDS)
+        .map(|band| {
+            let spread: f64 = energy.iter().enumerate()
+                .map(|(masker, &e)| e * 10f64.powf(spreading(band as f64 - masker as f64) / 10.0))
+                .sum();
+            let offset = 14.5 + band as f64;
+            spread * 10f64.powf(-offset / 10.0) / bins_in_band[band].max(1) as f64
+        })
+        .collect();
+
+    (0..power.len())
+        .map(|k| {
+            let quiet = 10f64.powf(absolute_threshold(bin_freq(k)) / 10.0);
+            band_threshold[band_of(k)].max(quiet) / spl_scale
+        })
+        .collect()
+}
+
+// Формирует водяной знак из последовательности spread (±1 в сегментах бит, 0 вне сообщения)
+// так, чтобы в каждом кадре он был на margin_db ниже порога маскирования контейнера
+pub fn shape_watermark(container: &[f64], spread: &[f64], sample_rate: u32, margin_db: f64) -> Vec<f64> {
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(FRAME_LEN);
+    let ifft = planner.plan_fft_inverse(FRAME_LEN);
+
+    let window = hann();
+    // ПСП взвешивается корнем из окна Ханна до и после фильтрации: произведение окон
+    // при перекрытии 50% в сумме дает единицу, а края кадров не дают разрывов
+    let root_window: Vec<f64> = window.iter().map(|w| w.sqrt()).collect();
+    // Ожидаемая мощность бина спектра взвешенной окном Ханна последовательности из ±1
+    let chip_power: f64 = window.iter().map(|w| w * w).sum();
+    let margin = 10f64.powf(-margin_db / 10.0);
+
+    let mut watermark = vec![0.0; container.len()];
+    let sample = |signal: &[f64], i: isize| if i >= 0 && (i as usize) < signal.len() { signal[i as usize] } else { 0.0 };
+
+    // Первый кадр начинается раньше сигнала, чтобы каждый отсчет покрывали два окна
+    let mut start = -(HOP as isize);
+    while start < container.len() as isize {
+        let frame = |signal: &[f64], window: &[f64]| -> Vec<Complex<f64>> {
+            window.iter().enumerate()
+                .map(|(n, &w)| Complex::new(sample(signal, start + n as isize) * w, 0.0))
+                .collect()
+        };
+
+        let mut chips = frame(spread, &root_window);
+        if chips.iter().any(|c| c.re != 0.0) {
+            let mut spectrum = frame(container, &window);
+            fft.process(&mut spectrum);
+            let power: Vec<f64> = spectrum[..=FRAME_LEN / 2].iter().map(|c| c.norm_sqr()).collect();
+            // Главный лепесток окна Ханна занимает ±2 бина, поэтому ПСП в бине не должна
+            // превышать порог соседних бинов - иначе она просочится в тихую полосу
+            let threshold = masking_threshold(&power, sample_rate);
+            let threshold: Vec<f64> = (0..threshold.len())
+                .map(|k| threshold[k.saturating_sub(2)..(k + 3).min(threshold.len())].iter().cloned().fold(f64::INFINITY, f64::min))
+                .collect();
+
+            fft.process(&mut chips);
+            for (k, chip) in chips.iter_mut().enumerate() {
+                let bin = k.min(FRAME_LEN - k);
+                *chip *= (threshold[bin] * margin / chip_power).sqrt();
+            }
+            ifft.process(&mut chips);
+
+            for (n, chip) in chips.iter().enumerate() {
+                let i = start + n as isize;
+                if i >= 0 && (i as usize) < watermark.len() {
+                    watermark[i as usize] += chip.re * root_window[n] / FRAME_LEN as f64;
+                }
+            }
+        }
+
+        start += HOP as isize;
+    }
+
+    watermark
+}
+
+// Отношение шум/маска в дБ: средняя по кадрам мощность noise относительно порога
+// маскирования container. Отрицательные значения - искажения замаскированы.
+// None, если сигнал короче кадра.
+pub fn noise_to_mask_ratio(container: &[f64], noise: &[f64], sample_rate: u32) -> Option<f64> {
+    let fft = FftPlanner::new().plan_fft_forward(FRAME_LEN);
+    let window = hann();
+    let frame_power = |signal: &[f64], start: usize| -> Vec<f64> {
+        let mut spectrum: Vec<Complex<f64>> = window.iter().enumerate()
+            .map(|(n, &w)| Complex::new(signal[start + n] * w, 0.0))
+            .collect();
+        fft.process(&mut spectrum);
+        spectrum[..=FRAME_LEN / 2].iter().map(|c| c.norm_sqr()).collect()
+    };
+
+    let len = container.len().min(noise.len());
+    let ratios: Vec<f64> = (0..len.checked_sub(FRAME_LEN)? + 1)
+        .step_by(HOP)
+        .map(|start| {
+            let threshold = masking_threshold(&frame_power(container, start), sample_rate);
+            let power = frame_power(noise, start);
+            power.iter().zip(threshold.iter()).map(|(p, t)| p / t).sum::<f64>() / power.len() as f64
+        })
+        .collect();
+
+    let mean = ratios.iter().sum::<f64>() / ratios.len() as f64;
+    Some(10.0 * mean.max(1e-12).log10())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signals::{chips, sine};
+
+    fn frame_power(signal: &[f64], start: usize) -> Vec<f64> {
+        let mut spectrum: Vec<Complex<f64>> = hann().iter().enumerate()
+            .map(|(n, &w)| Complex::new(signal[start + n] * w, 0.0))
+            .collect();
+        FftPlanner::new().plan_fft_forward(FRAME_LEN).process(&mut spectrum);
+        spectrum[..=FRAME_LEN / 2].iter().map(|c| c.norm_sqr()).collect()
+    }
+
+    #[test]
+    fn test_threshold_follows_masker() {
+        let power = frame_power(&sine(1000.0, 0.5, FRAME_LEN), 0);
+        let threshold = masking_threshold(&power, 44100);
+
+        // Около маскирующего тона порог намного выше, чем вдали от него
+        let bin_1k = (1000.0 * FRAME_LEN as f64 / 44100.0).round() as usize;
+        let bin_8k = (8000.0 * FRAME_LEN as f64 / 44100.0).round() as usize;
+        assert!(threshold[bin_1k] > 1000.0 * threshold[bin_8k]);
+        // Но остается ниже самого тона
+        assert!(threshold[bin_1k] < power[bin_1k]);
+    }
+
+    #[test]
+    fn test_watermark_stays_under_masking_threshold() {
+        let len = 32 * FRAME_LEN;
+        // Тон на фоне шума, как в реальной записи: без шума порог вдали от тона
+        // определяется только утечкой окна
+        let container: Vec<f64> = sine(440.0, 0.3, len).iter()
+            .zip(chips(len, 777))
+            .map(|(a, b)| a + 0.01 * b)
+            .collect();
+        let spread = chips(len, 12345);
+        let margin_db = 3.0;
+
+        let watermark = shape_watermark(&container, &spread, 44100, margin_db);
+
+        // Мощность ±1 в отдельном кадре случайна, поэтому сравниваются средние по кадрам
+        // мощность водяного знака и порог в каждой полосе
+        let mut band_power = [0.0; BANDS];
+        let mut band_threshold = [0.0; BANDS];
+        for start in (FRAME_LEN..len - 2 * FRAME_LEN).step_by(HOP) {
+            let threshold = masking_threshold(&frame_power(&container, start), 44100);
+            let power = frame_power(&watermark, start);
+            for k in 0..power.len() {
+                let band = (bark(k as f64 * 44100.0 / FRAME_LEN as f64) as usize).min(BANDS - 1);
+                band_power[band] += power[k];
+                band_threshold[band] += threshold[k];
+            }
+        }
+        for band in 0..BANDS {
+            assert!(band_power[band] <= band_threshold[band], "Полоса {} выше порога", band);
+        }
+
+        // Водяной знак коррелирует с исходной последовательностью
+        let correlation: f64 = watermark.iter().zip(spread.iter()).map(|(w, c)| w * c).sum();
+        assert!(correlation > 0.0);
+    }
+
+    #[test]
+    fn test_watermark_is_louder_on_loud_passages() {
+        let len = 4 * FRAME_LEN;
+        let spread = chips(len, 12345);
+        let energy = |signal: &[f64]| signal.iter().map(|x| x * x).sum::<f64>();
+
+        let noise = |amplitude: f64| -> Vec<f64> { chips(len, 777).iter().map(|x| amplitude * x).collect() };
+        let quiet = shape_watermark(&noise(0.001), &spread, 44100, 0.0);
+        let loud = shape_watermark(&noise(0.3), &spread, 44100, 0.0);
+        assert!(energy(&loud) > 10.0 * energy(&quiet));
+
+        // Без сообщения контейнер не изменяется
+        assert!(shape_watermark(&sine(440.0, 0.5, len), &vec![0.0; len], 44100, 0.0).iter().all(|&w| w == 0.0));
+    }
+
+    #[test]
+    fn test_noise_to_mask_ratio_grows_with_noise() {
+        let len = 16 * FRAME_LEN;
+        let container = sine(440.0, 0.3, len);
+        let noise = |amplitude: f64| -> Vec<f64> { chips(len, 777).iter().map(|x| amplitude * x).collect() };
+
+        let quiet = noise_to_mask_ratio(&container, &noise(1e-5), 44100).unwrap();
+        let loud = noise_to_mask_ratio(&container, &noise(1e-2), 44100).unwrap();
+        assert!(quiet < 0.0);
+        assert!((loud - quiet - 60.0).abs() < 1.0);
+
+        assert_eq!(noise_to_mask_ratio(&container[..100], &noise(1e-2), 44100), None);
+    }
+}
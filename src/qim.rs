@@ -0,0 +1,280 @@
+use std::sync::Arc;
+use crate::channels::ChannelMode;
+use crate::error::StegoError;
+use crate::keyed_rng::KeyedRng;
+use crate::method::{Demodulated, StegoMethod};
+use crate::sync::SyncPoint;
+
+pub const QIM_ID: &str = "qim";
+
+// Шаг квантователя по умолчанию на отсчет: искажение около step / sqrt(12) на отсчет
+pub const DEFAULT_STEP: f64 = 0.005;
+// Коэффициент компенсации искажений по умолчанию
+pub const DEFAULT_COMPENSATION: f64 = 0.75;
+// Наименьший блок на бит: на коротких блоках проекция почти не усредняет сигнал
+pub const MIN_BLOCK: usize = 32;
+// Поток генератора для сдвигов решеток
+const DITHER_STREAM: u64 = 3;
+
+// Квантование проекций с подмешиванием (dither modulation, spread-transform QIM). Каждый бит
+// занимает блок из длины ключа отсчетов, и проекция блока на ПСП p = <x, c> / sqrt(L)
+// квантуется одной из двух решеток с шагом step * sqrt(L), сдвинутых друг относительно друга
+// на половину шага: решетка бита 0 проходит через d, решетка бита 1 - через d + шаг / 2.
+// Сдвиг d свой для каждого блока и порождается ключом, поэтому без ключа решетки неизвестны.
+//
+// В отличие от аддитивной ПСП (см. st) сигнал контейнера не мешает извлечению: решение
+// принимается по тому, к какой решетке ближе проекция, и исходный контейнер не нужен.
+// Шаг растет как sqrt(L), а искажение на отсчет от длины блока не зависит, поэтому
+// устойчивость к шуму растет с длиной блока так же, как у расширения спектра.
+//
+// Компенсация искажений: проекция сдвигается не в узел решетки q, а в p + alpha * (q - p).
+// Искажение уменьшается в alpha раз, а остаток (1 - alpha) * (q - p) работает как шум;
+// при alpha > 0.5 без атак биты извлекаются без ошибок.
+//
+// Изменение громкости масштабирует проекции, но не решетки, поэтому уже при усилении
+// на несколько процентов решения на дальних от нуля узлах перепутываются. Начало кадра
+// не ищется: кадр начинается с первого отсчета.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantizationIndexModulation {
+    pub channel_mode: ChannelMode,
+    // Шаг квантователя в единицах амплитуды на отсчет
+    pub step: f64,
+    // Коэффициент компенсации искажений alpha из (0, 1]; 1 - квантование без компенсации
+    pub compensation: f64,
+}
+
+impl Default for QuantizationIndexModulation {
+    fn default() -> Self {
+        QuantizationIndexModulation { channel_mode: ChannelMode::Left, step: DEFAULT_STEP, compensation: DEFAULT_COMPENSATION }
+    }
+}
+
+impl QuantizationIndexModulation {
+    pub fn new(channel_mode: ChannelMode, step: f64, compensation: f64) -> Result<Self, StegoError> {
+        if !(step > 0.0 && step.is_finite()) {
+            return Err(StegoError::InvalidArgument(format!("QIM: шаг квантователя должен быть положительным, получено {}", step)));
+        }
+        if !(compensation > 0.0 && compensation <= 1.0) {
+            return Err(StegoError::InvalidArgument(format!("QIM: коэффициент компенсации должен быть в (0, 1], получено {}", compensation)));
+        }
+        Ok(QuantizationIndexModulation { channel_mode, step, compensation })
+    }
+
+    // Параметры: политика каналов и шаг квантователя. Компенсация при извлечении не нужна.
+    pub fn from_parameters(parameters: &[&str]) -> Result<Self, StegoError> {
+        let invalid = |what: &str| StegoError::MalformedKey(format!("параметры {}: {}", QIM_ID, what));
+
+        match parameters {
+            [mode, step] => {
+                let channel_mode = ChannelMode::from_name(mode).ok_or_else(|| invalid("неизвестная политика каналов"))?;
+                let step = step.parse::<f64>().map_err(|_| invalid("шаг квантователя должен быть числом"))?;
+                Self::new(channel_mode, step, DEFAULT_COMPENSATION).map_err(|_| invalid("шаг квантователя должен быть положительным"))
+            }
+            _ => Err(invalid("ожидаются политика каналов и шаг квантователя")),
+        }
+    }
+
+    // Шаг решетки проекций для блока длины block
+    fn lattice_step(&self, block: usize) -> f64 {
+        self.step * (block as f64).sqrt()
+    }
+}
+
+impl StegoMethod for QuantizationIndexModulation {
+    fn id(&self) -> &'static str {
+        QIM_ID
+    }
+
+    fn parameters(&self) -> Vec<String> {
+        vec![self.channel_mode.name().to_string(), self.step.to_string()]
+    }
+
+    fn capacity(&self, container: &[Vec<f64>]) -> usize {
+        self.channel_mode.stream(container).len() / MIN_BLOCK
+    }
+
+    // Ключ задает длину блока на бит: контейнер делится поровну между битами
+    fn key_len(&self, container: &[Vec<f64>], bits_num: usize) -> usize {
+        let block = self.channel_mode.stream(container).len().checked_div(bits_num).unwrap_or(0);
+        if block >= MIN_BLOCK { block } else { 0 }
+    }
+
+    fn capacity_for_key_len(&self, container: &[Vec<f64>], key_len: usize) -> usize {
+        if key_len >= MIN_BLOCK { self.channel_mode.stream(container).len() / key_len } else { 0 }
+    }
+
+    fn chips_per_bit(&self, key_len: usize) -> Option<usize> {
+        Some(key_len)
+    }
+
+    fn embed(&self, container: &[Vec<f64>], bits: &[bool], key: &[i16]) -> Result<Vec<Vec<f64>>, StegoError> {
+        let block = key.len();
+        if block < MIN_BLOCK {
+            return Err(StegoError::InvalidArgument(format!("{}: длина ключа {} меньше наименьшего блока {}", QIM_ID, block, MIN_BLOCK)));
+        }
+        let capacity = self.capacity_for_key_len(container, block);
+        if bits.len() > capacity {
+            return Err(StegoError::ContainerTooSmall { required: bits.len(), available: capacity });
+        }
+
+        let mut stream = self.channel_mode.stream(container);
+        let lattice_step = self.lattice_step(block);
+        let norm = (block as f64).sqrt();
+        let mut rng = KeyedRng::new(key, DITHER_STREAM);
+
+        for (chunk, &bit) in stream.chunks_exact_mut(block).zip(bits) {
+            let projection = project(chunk, key);
+            let offset = dither(&mut rng, lattice_step) + if bit { lattice_step / 2.0 } else { 0.0 };
+            let quantized = ((projection - offset) / lattice_step).round() * lattice_step + offset;
+
+            // Сдвиг вдоль ПСП меняет проекцию ровно на shift
+            let shift = self.compensation * (quantized - projection);
+            for (x, &c) in chunk.iter_mut().zip(key) {
+                *x += shift * c as f64 / norm;
+            }
+        }
+
+        Ok(self.channel_mode.merge(container, &stream))
+    }
+
+    fn demodulate(&self, stegocontainer: &[Vec<f64>], _container: Option<&[Vec<f64>]>, key: &[i16]) -> Result<Box<dyn Demodulated>, StegoError> {
+        let block = key.len();
+        if block == 0 {
+            return Err(StegoError::ContainerTooSmall { required: 1, available: 0 });
+        }
+        let lattice_step = self.lattice_step(block);
+        let mut rng = KeyedRng::new(key, DITHER_STREAM);
+
+        let decisions = self.channel_mode.stream(stegocontainer).chunks_exact(block)
+            .map(|chunk| {
+                // Расстояние до решетки бита 0 от 0 до шаг / 2; ровно шаг / 4 - решения нет
+                let residual = (project(chunk, key) - dither(&mut rng, lattice_step)).rem_euclid(lattice_step);
+                let distance = residual.min(lattice_step - residual);
+                (distance - lattice_step / 4.0) / (lattice_step / 4.0)
+            })
+            .collect();
+        Ok(Box::new(QimFrame { decisions }))
+    }
+
+    fn sync_unit(&self) -> &'static str {
+        "отсчетов"
+    }
+
+    // Сила встраивания для QIM - шаг квантователя
+    fn with_strength(&self, strength: f64) -> Result<Arc<dyn StegoMethod>, StegoError> {
+        Ok(Arc::new(Self::new(self.channel_mode, strength, self.compensation)?))
+    }
+
+    fn bench_strengths(&self) -> Vec<f64> {
+        vec![0.002, 0.005, 0.01]
+    }
+}
+
+// Проекция блока на ПСП с единичной нормой
+fn project(chunk: &[f64], key: &[i16]) -> f64 {
+    chunk.iter().zip(key).map(|(x, &c)| x * c as f64).sum::<f64>() / (chunk.len() as f64).sqrt()
+}
+
+// Сдвиг решетки очередного блока, равномерный на [0, step)
+fn dither(rng: &mut KeyedRng, step: f64) -> f64 {
+    (rng.next() >> 11) as f64 / (1u64 << 53) as f64 * step
+}
+
+struct QimFrame {
+    decisions: Vec<f64>,
+}
+
+impl Demodulated for QimFrame {
+    fn capacity(&self) -> usize {
+        self.decisions.len()
+    }
+
+    fn soft_bits(&self, first_bit: usize, bits_num: usize) -> Result<Vec<f64>, StegoError> {
+        self.decisions.get(first_bit..first_bit + bits_num)
+            .map(|decisions| decisions.to_vec())
+            .ok_or(StegoError::ContainerTooSmall { required: first_bit + bits_num, available: self.capacity() })
+    }
+
+    fn sync(&self) -> Option<SyncPoint> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip_generator::ChipGenerator;
+    use crate::park_miller_prng::ParkMiller;
+    use crate::test_signals::{bits, music, noise};
+
+    fn recover(method: &QuantizationIndexModulation, stego: &[Vec<f64>], key: &[i16], bits_num: usize) -> Vec<bool> {
+        let frame = method.demodulate(stego, None, key).unwrap();
+        frame.soft_bits(0, bits_num).unwrap().iter().map(|&d| d > 0.0).collect()
+    }
+
+    #[test]
+    fn test_blind_round_trip_with_compensation() {
+        let container = vec![music(20_000), music(20_000)];
+        let bits = bits(100);
+
+        for compensation in [1.0, 0.75, 0.55] {
+            let method = QuantizationIndexModulation { compensation, ..Default::default() };
+            let key = ParkMiller::with_seed(5).unwrap().generate_prs(method.key_len(&container, bits.len()));
+            assert_eq!(key.len(), 200);
+
+            let stego = method.embed(&container, &bits, &key).unwrap();
+            assert_eq!(stego[1], container[1]);
+            assert_eq!(recover(&method, &stego, &key, bits.len()), bits);
+
+            // Искажение на отсчет около alpha * step / sqrt(12)
+            let rms = (container[0].iter().zip(&stego[0]).map(|(x, y)| (x - y).powi(2)).sum::<f64>() / 20_000.0).sqrt();
+            assert!(rms < compensation * DEFAULT_STEP / 12f64.sqrt() * 1.5, "{}", rms);
+        }
+    }
+
+    #[test]
+    fn test_survives_noise_but_not_wrong_key() {
+        let container = vec![music(40_000)];
+        let method = QuantizationIndexModulation::default();
+        let bits = bits(200);
+        let key = ParkMiller::with_seed(8).unwrap().generate_prs(method.key_len(&container, bits.len()));
+        let stego = method.embed(&container, &bits, &key).unwrap();
+
+        // Равномерный шум мощнее самого встраивания (амплитуда в половину шага на отсчет)
+        let noisy: Vec<Vec<f64>> = stego.iter()
+            .map(|channel| channel.iter().zip(noise(channel.len(), 7)).map(|(x, n)| x + DEFAULT_STEP * n).collect())
+            .collect();
+        assert_eq!(recover(&method, &noisy, &key, bits.len()), bits);
+
+        let other_key = ParkMiller::with_seed(9).unwrap().generate_prs(key.len());
+        assert_ne!(recover(&method, &stego, &other_key, bits.len()), bits);
+    }
+
+    #[test]
+    fn test_embed_rejects_extra_bits_and_short_key() {
+        let container = vec![music(1000)];
+        let method = QuantizationIndexModulation::default();
+        let key = ParkMiller::with_seed(5).unwrap().generate_prs(100);
+
+        assert!(method.embed(&container, &bits(10), &key).is_ok());
+        assert!(matches!(method.embed(&container, &bits(11), &key), Err(StegoError::ContainerTooSmall { required: 11, available: 10 })));
+        assert!(matches!(method.embed(&container, &bits(10), &key[..MIN_BLOCK - 1]), Err(StegoError::InvalidArgument(_))));
+        assert!(matches!(method.embed(&container, &bits(10), &[]), Err(StegoError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_rejects_invalid_parameters() {
+        assert!(QuantizationIndexModulation::new(ChannelMode::Left, 0.0, 0.75).is_err());
+        assert!(QuantizationIndexModulation::new(ChannelMode::Left, 0.01, 0.0).is_err());
+        assert!(QuantizationIndexModulation::new(ChannelMode::Left, 0.01, 1.5).is_err());
+        assert!(matches!(QuantizationIndexModulation::from_parameters(&["left"]), Err(StegoError::MalformedKey(_))));
+        assert!(matches!(QuantizationIndexModulation::from_parameters(&["left", "-1"]), Err(StegoError::MalformedKey(_))));
+        // Нулевой шаг дал бы деление на ноль при извлечении
+        assert!(matches!(QuantizationIndexModulation::default().with_strength(0.0), Err(StegoError::InvalidArgument(_))));
+        assert!(QuantizationIndexModulation::default().with_strength(0.01).is_ok());
+        assert_eq!(
+            QuantizationIndexModulation::from_parameters(&["mid-side", "0.01"]).unwrap(),
+            QuantizationIndexModulation { channel_mode: ChannelMode::MidSide, step: 0.01, compensation: DEFAULT_COMPENSATION },
+        );
+    }
+}
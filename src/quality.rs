@@ -0,0 +1,150 @@
+use std::fmt;
+use crate::error::StegoError;
+use crate::psychoacoustic::noise_to_mask_ratio;
+
+// Объективная оценка искажений, внесенных встраиванием: сравниваются отсчеты
+// контейнера и стегаконтейнера по всем каналам (значения в диапазоне [-1.0, 1.0]).
+
+// Длина кадра сегментного ОСШ, отсчетов
+const SEGMENT_LEN: usize = 1024;
+// Общепринятые пределы ОСШ кадра: тишина не уводит среднее в минус бесконечность,
+// а кадры без искажений - в плюс бесконечность
+const MIN_SEGMENT_SNR: f64 = -10.0;
+const MAX_SEGMENT_SNR: f64 = 35.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityReport {
+    // Отношение сигнал/шум, дБ
+    pub snr: f64,
+    // Среднее ОСШ по кадрам, дБ
+    pub segmental_snr: f64,
+    // Пиковое ОСШ относительно полной шкалы, дБ
+    pub psnr: f64,
+    pub max_abs_deviation: f64,
+    // Отношение шум/маска по психоакустической модели в худшем канале, дБ (None для коротких сигналов)
+    pub noise_to_mask: Option<f64>,
+    // Приближение ODG из PEAQ: 0 - искажения неразличимы, -4 - очень раздражают
+    pub odg: Option<f64>,
+}
+
+pub fn quality_report(container: &[Vec<f64>], stegocontainer: &[Vec<f64>], sample_rate: u32) -> Result<QualityReport, StegoError> {
+    let samples = |channels: &[Vec<f64>]| channels.iter().map(|c| c.len()).sum::<usize>();
+    if container.len() != stegocontainer.len() || container.iter().zip(stegocontainer).any(|(a, b)| a.len() != b.len()) {
+        return Err(StegoError::LengthMismatch { container: samples(container), stegocontainer: samples(stegocontainer) });
+    }
+
+    let noise: Vec<Vec<f64>> = container.iter().zip(stegocontainer)
+        .map(|(x, y)| x.iter().zip(y).map(|(a, b)| b - a).collect())
+        .collect();
+    let energy = |signal: &[f64]| signal.iter().map(|x| x * x).sum::<f64>();
+
+    let signal_energy: f64 = container.iter().map(|c| energy(c)).sum();
+    let noise_energy: f64 = noise.iter().map(|c| energy(c)).sum();
+    let total = samples(container).max(1) as f64;
+
+    let segments: Vec<f64> = container.iter().zip(&noise)
+        .flat_map(|(x, e)| x.chunks(SEGMENT_LEN).zip(e.chunks(SEGMENT_LEN)))
+        .map(|(x, e)| snr_db(energy(x), energy(e)).clamp(MIN_SEGMENT_SNR, MAX_SEGMENT_SNR))
+        .collect();
+    let segmental_snr = if segments.is_empty() {
+        MAX_SEGMENT_SNR
+    } else {
+        segments.iter().sum::<f64>() / segments.len() as f64
+    };
+
+    // Слышимость определяется самым искаженным каналом
+    let noise_to_mask = container.iter().zip(&noise)
+        .filter_map(|(x, e)| noise_to_mask_ratio(x, e, sample_rate))
+        .reduce(f64::max);
+
+    Ok(QualityReport {
+        snr: snr_db(signal_energy, noise_energy),
+        segmental_snr,
+        psnr: snr_db(total, noise_energy),
+        max_abs_deviation: noise.iter().flatten().fold(0.0, |acc, e| acc.max(e.abs())),
+        noise_to_mask,
+        odg: noise_to_mask.map(odg_from_nmr),
+    })
+}
+
+fn snr_db(signal: f64, noise: f64) -> f64 {
+    if noise == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (signal / noise).log10()
+    }
+}
+
+// Логистическое отображение отношения шум/маска на шкалу ODG [-4, 0]:
+// замаскированные искажения (NMR < -10 дБ) почти не снижают оценку,
+// NMR около 0 дБ соответствует "заметно, но не раздражает"
+fn odg_from_nmr(nmr: f64) -> f64 {
+    -4.0 / (1.0 + (-(nmr - 2.0) / 3.0).exp())
+}
+
+impl QualityReport {
+    // Бесконечные значения (искажений нет) записываются как null
+    pub fn to_json(&self) -> String {
+        let number = |value: f64| if value.is_finite() { format!("{}", value) } else { "null".to_string() };
+        let optional = |value: Option<f64>| value.map_or("null".to_string(), number);
+        format!(
+            "{{\n  \"snr_db\": {},\n  \"segmental_snr_db\": {},\n  \"psnr_db\": {},\n  \"max_abs_deviation\": {},\n  \"noise_to_mask_db\": {},\n  \"odg\": {}\n}}\n",
+            number(self.snr),
+            number(self.segmental_snr),
+            number(self.psnr),
+            number(self.max_abs_deviation),
+            optional(self.noise_to_mask),
+            optional(self.odg),
+        )
+    }
+}
+
+impl fmt::Display for QualityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let optional = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{:.2}", v));
+        writeln!(f, "ОСШ: {:.2} дБ", self.snr)?;
+        writeln!(f, "Сегментное ОСШ: {:.2} дБ", self.segmental_snr)?;
+        writeln!(f, "Пиковое ОСШ: {:.2} дБ", self.psnr)?;
+        writeln!(f, "Максимальное отклонение: {:e}", self.max_abs_deviation)?;
+        writeln!(f, "Шум/маска: {} дБ", optional(self.noise_to_mask))?;
+        write!(f, "ODG (приближенно): {}", optional(self.odg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_signals::sine;
+    use super::*;
+
+    #[test]
+    fn test_report_for_known_distortion() {
+        let container = vec![sine(440.0, 1.0, 44100)];
+        let stego = vec![container[0].iter().map(|x| x + 0.01).collect()];
+
+        let report = quality_report(&container, &stego, 44100).unwrap();
+        // Мощность синусоиды 0.5, мощность шума 1e-4
+        assert!((report.snr - 36.99).abs() < 0.01);
+        assert!((report.psnr - 40.0).abs() < 1e-6);
+        assert!((report.max_abs_deviation - 0.01).abs() < 1e-12);
+        assert!(report.segmental_snr <= MAX_SEGMENT_SNR);
+        assert!(report.odg.unwrap() <= 0.0);
+    }
+
+    #[test]
+    fn test_report_without_distortion() {
+        let container = vec![sine(440.0, 1.0, 4096), sine(440.0, 1.0, 4096)];
+
+        let report = quality_report(&container, &container, 44100).unwrap();
+        assert_eq!(report.snr, f64::INFINITY);
+        assert_eq!(report.segmental_snr, MAX_SEGMENT_SNR);
+        assert_eq!(report.max_abs_deviation, 0.0);
+        assert!(report.odg.unwrap() > -0.01);
+        assert!(report.to_json().contains("\"snr_db\": null"));
+    }
+
+    #[test]
+    fn test_report_rejects_mismatched_lengths() {
+        let result = quality_report(&[sine(440.0, 1.0, 100)], &[sine(440.0, 1.0, 99)], 44100);
+        assert!(matches!(result, Err(StegoError::LengthMismatch { container: 100, stegocontainer: 99 })));
+    }
+}
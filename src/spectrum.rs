@@ -0,0 +1,188 @@
+use std::f64::consts::PI;
+use std::ops::Range;
+use std::sync::Arc;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+// Расширение спектра по модулям бинов БПФ. Сигнал разбивается на кадры по FRAME_LEN отсчетов
+// с перекрытием 50% и окном корень из Ханна при анализе и при синтезе (в сумме окна Ханна
+// дают единицу). В каждом кадре берутся бины выбранной полосы частот в порядке, который
+// задает ключ, и модуль бина умножается на 1 + strength * chip, а фаза сохраняется.
+// Изменение возвращается в сигнал обратным БПФ и сложением кадров с перекрытием, поэтому
+// энергия водяного знака остается в полосе, например в телефонной 300-3400 Гц.
+//
+// Кадры перекрываются, и при повторном анализе в бине остается около половины его изменения
+// вместе с долей изменений соседних бинов и кадров; для расширения спектра это лишь шум.
+
+pub const FRAME_LEN: usize = 1024;
+const HOP: usize = FRAME_LEN / 2;
+
+// Полоса по умолчанию в долях частоты дискретизации: около 440-4400 Гц при 44.1 кГц
+pub const DEFAULT_BAND: (f64, f64) = (0.01, 0.1);
+// Сила встраивания по умолчанию: доля модуля бина
+pub const DEFAULT_FFT_STRENGTH: f64 = 0.2;
+
+// Бины полосы low..=high (доли частоты дискретизации) без нулевой частоты и частоты Найквиста
+pub fn band_bins(low: f64, high: f64) -> Range<usize> {
+    let first = ((low * FRAME_LEN as f64).ceil() as usize).max(1);
+    let end = ((high * FRAME_LEN as f64).floor() as usize + 1).min(FRAME_LEN / 2);
+    first..end.max(first)
+}
+
+// Кадры, целиком лежащие внутри сигнала: кадр f занимает отсчеты f * HOP..f * HOP + FRAME_LEN
+fn frame_count(len: usize) -> usize {
+    (len / HOP).saturating_sub(1)
+}
+
+pub fn capacity(len: usize, low: f64, high: f64) -> usize {
+    frame_count(len) * band_bins(low, high).len()
+}
+
+// Модули бинов полосы всех кадров подряд, внутри кадра - в порядке, заданном ключом
+pub fn magnitudes(stream: &[f64], low: f64, high: f64, key: &[i16]) -> Vec<f64> {
+    let stft = Stft::new();
+    let bins = band_bins(low, high);
+    (0..frame_count(stream.len()))
+        .flat_map(|f| {
+            let spectrum = stft.analyze(stream, f);
+            keyed_order(bins.clone(), key, f).into_iter().map(move |k| spectrum[k].norm())
+        })
+        .collect()
+}
+
+// Умножает модули бинов на 1 + strength * spread (spread: ±1 на позициях бит, 0 вне сообщения)
+pub fn embed_watermark(stream: &[f64], spread: &[f64], strength: f64, low: f64, high: f64, key: &[i16]) -> Vec<f64> {
+    let stft = Stft::new();
+    let bins = band_bins(low, high);
+    let mut result = stream.to_vec();
+
+    for (f, chips) in spread.chunks(bins.len().max(1)).take(frame_count(stream.len())).enumerate() {
+        if chips.iter().all(|&c| c == 0.0) {
+            continue;
+        }
+        let spectrum = stft.analyze(stream, f);
+        let mut delta = vec![Complex::new(0.0, 0.0); FRAME_LEN];
+        for (k, &chip) in keyed_order(bins.clone(), key, f).into_iter().zip(chips) {
+            delta[k] = spectrum[k] * strength * chip;
+            // Сопряженно-симметричный бин, чтобы сигнал остался вещественным
+            delta[FRAME_LEN - k] = delta[k].conj();
+        }
+        stft.synthesize(&mut result, f, delta);
+    }
+
+    result
+}
+
+// Перестановка бинов полосы для кадра (Фишер-Йейтс поверх xorshift64). Зерно собирается
+// из первых элементов ПСП и номера кадра, поэтому без ключа неизвестно, какой бин
+// несет какой элемент ПСП.
+fn keyed_order(bins: Range<usize>, key: &[i16], frame: usize) -> Vec<usize> {
+    let key_seed = key.iter().take(64).fold(0u64, |acc, &c| acc << 1 | (c > 0) as u64);
+    let mut state = (key_seed ^ (frame as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15)) | 1;
+    let mut order: Vec<usize> = bins.collect();
+    for i in (1..order.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        order.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+    order
+}
+
+struct Stft {
+    window: Vec<f64>,
+    fft: Arc<dyn Fft<f64>>,
+    ifft: Arc<dyn Fft<f64>>,
+}
+
+impl Stft {
+    fn new() -> Self {
+        let mut planner = FftPlanner::new();
+        Stft {
+            // Корень из периодического окна Ханна
+            window: (0..FRAME_LEN).map(|n| (PI * n as f64 / FRAME_LEN as f64).sin()).collect(),
+            fft: planner.plan_fft_forward(FRAME_LEN),
+            ifft: planner.plan_fft_inverse(FRAME_LEN),
+        }
+    }
+
+    fn analyze(&self, stream: &[f64], frame: usize) -> Vec<Complex<f64>> {
+        let mut spectrum: Vec<Complex<f64>> = stream[frame * HOP..frame * HOP + FRAME_LEN].iter().zip(&self.window)
+            .map(|(x, w)| Complex::new(x * w, 0.0))
+            .collect();
+        self.fft.process(&mut spectrum);
+        spectrum
+    }
+
+    // Обратное БПФ спектра кадра, окно и сложение с перекрытием
+    fn synthesize(&self, stream: &mut [f64], frame: usize, mut spectrum: Vec<Complex<f64>>) {
+        self.ifft.process(&mut spectrum);
+        for ((x, s), w) in stream[frame * HOP..].iter_mut().zip(&spectrum).zip(&self.window) {
+            *x += s.re * w / FRAME_LEN as f64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signals::{chips, noise};
+
+    #[test]
+    fn test_keyed_order_is_permutation_of_band() {
+        let bins = band_bins(300.0 / 8000.0, 3400.0 / 8000.0);
+        assert_eq!(bins, 39..436);
+
+        let key = [1, -1, -1, 1, 1, 1, -1, 1];
+        let mut order = keyed_order(bins.clone(), &key, 0);
+        assert_ne!(order, bins.clone().collect::<Vec<_>>());
+        // Порядок зависит от ключа и кадра
+        assert_ne!(order, keyed_order(bins.clone(), &[-1, 1, 1, -1], 0));
+        assert_ne!(order, keyed_order(bins.clone(), &key, 1));
+
+        order.sort();
+        assert_eq!(order, bins.collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_watermark_stays_in_band() {
+        let stream = noise(32 * FRAME_LEN, 1);
+        let (low, high) = (0.05, 0.15);
+        let spread = chips(capacity(stream.len(), low, high), 2);
+
+        let stego = embed_watermark(&stream, &spread, DEFAULT_FFT_STRENGTH, low, high, &[1, -1, 1]);
+        let watermark: Vec<f64> = stego.iter().zip(&stream).map(|(y, x)| y - x).collect();
+
+        // Спектр водяного знака в кадрах: окно синтеза размывает полосу на пару бинов
+        let stft = Stft::new();
+        let bins = band_bins(low, high);
+        let (mut inside, mut outside) = (0.0, 0.0);
+        for f in 1..frame_count(watermark.len()) - 1 {
+            for (k, c) in stft.analyze(&watermark, f)[..=FRAME_LEN / 2].iter().enumerate() {
+                if k + 2 >= bins.start && k < bins.end + 2 {
+                    inside += c.norm_sqr();
+                } else {
+                    outside += c.norm_sqr();
+                }
+            }
+        }
+        assert!(outside < 0.01 * inside);
+    }
+
+    #[test]
+    fn test_magnitude_changes_follow_spread() {
+        let stream = noise(16 * FRAME_LEN, 3);
+        let key = [1, 1, -1, 1, -1];
+        let spread = chips(capacity(stream.len(), DEFAULT_BAND.0, DEFAULT_BAND.1), 4);
+
+        let stego = embed_watermark(&stream, &spread, DEFAULT_FFT_STRENGTH, DEFAULT_BAND.0, DEFAULT_BAND.1, &key);
+        let original = magnitudes(&stream, DEFAULT_BAND.0, DEFAULT_BAND.1, &key);
+        let embedded = magnitudes(&stego, DEFAULT_BAND.0, DEFAULT_BAND.1, &key);
+        assert_eq!(embedded.len(), spread.len());
+
+        // Изменение модулей коррелирует с ПСП, даже если половина его уходит в соседние кадры
+        let correlation: f64 = original.iter().zip(&embedded).zip(&spread).map(|((x, y), c)| (y - x) * c).sum();
+        let expected: f64 = original.iter().map(|x| DEFAULT_FFT_STRENGTH * x).sum();
+        assert!(correlation > 0.3 * expected, "{} / {}", correlation, expected);
+    }
+}
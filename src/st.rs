@@ -1,18 +1,193 @@
 use bitvec::prelude::*;
-use crate::utils::EncryptData;
+use crate::channels::ChannelMode;
+use crate::error::StegoError;
+use crate::header::StegoHeader;
+use crate::psychoacoustic::shape_watermark;
+use crate::sync::{PREAMBLE, PREAMBLE_BITS};
+use crate::spectrum;
+use crate::transform::{embed_watermark, Domain};
 
 
-pub fn st(data: &EncryptData, samples_per_msg_bit: usize, n: usize, m: usize, psp: Vec<i16>) -> Vec<f64> {
-    let mut result_amplitudes = data.container.amplitudes.clone();
+// Учитывается, что у нас в сообщении не смешиваются латиница и кириллица. (НЕ ФАКТ ЧТО РАБОТАЕТ)
+pub fn count_bits_per_char(bytes: &[u8]) -> Result<usize, StegoError> {
+    let mut max_bits_per_char: usize = 0;
+    match std::str::from_utf8(bytes) {
+        Ok(s) => {
+            for c in s.chars() {
+                let bytes = c.len_utf8();
+                let bits = bytes * 8;
+                if bits > max_bits_per_char {
+                    max_bits_per_char = bits;
+                }
+                // print_debug_information(format!("Символ: {}, Байты: {}, Биты: {}", c, bytes, bits));
+            }
+        }
+        Err(_) => {
+            return Err(StegoError::InvalidMessage("массив байтов содержит невалидный UTF-8"))
+        }
+    }
+    Ok(max_bits_per_char)
+}
+
+// Сила встраивания по умолчанию для постоянной амплитуды
+pub const DEFAULT_STRENGTH: f64 = 0.0005;
+
+// Как ПСП добавляется к отсчетам контейнера (в области MDCT - к коэффициентам)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gain {
+    // Постоянная амплитуда: x + strength * c * (x + 2)
+    Fixed(f64),
+    // Амплитуда и спектр ПСП подстраиваются под порог маскирования контейнера
+    // (см. psychoacoustic), margin_db - запас ниже порога
+    Adaptive { margin_db: f64, sample_rate: u32 },
+}
+
+// Параметры расширения спектра: ПСП, длина сегмента на один бит, политика каналов,
+// усиление и область встраивания (в области MDCT сегмент состоит из коэффициентов)
+#[derive(Debug, Clone, Copy)]
+pub struct Spreading<'a> {
+    pub psp: &'a [i16],
+    pub samples_per_msg_bit: usize,
+    pub channel_mode: ChannelMode,
+    pub gain: Gain,
+    pub domain: Domain,
+}
+
+impl<'a> Spreading<'a> {
+    pub fn new(psp: &'a [i16], samples_per_msg_bit: usize) -> Self {
+        Spreading {
+            psp,
+            samples_per_msg_bit,
+            channel_mode: ChannelMode::Left,
+            gain: Gain::Fixed(DEFAULT_STRENGTH),
+            domain: Domain::Time,
+        }
+    }
+
+    // Проверка до встраивания: ПСП покрывает сегмент, и bits_num бит после преамбулы
+    // помещаются в поток длиной stream_len (в областях преобразований - в его коэффициенты)
+    fn validate(&self, stream_len: usize, bits_num: usize) -> Result<(), StegoError> {
+        if self.samples_per_msg_bit == 0 {
+            return Err(StegoError::InvalidArgument("длина сегмента на бит должна быть положительной".to_string()));
+        }
+        if self.psp.len() < self.samples_per_msg_bit {
+            return Err(StegoError::InvalidArgument(format!(
+                "ПСП короче сегмента на бит ({} < {})", self.psp.len(), self.samples_per_msg_bit,
+            )));
+        }
+
+        let capacity = match self.domain {
+            Domain::Time => stream_len,
+            domain => domain.capacity(stream_len),
+        } / self.samples_per_msg_bit;
+        if PREAMBLE_BITS + bits_num > capacity {
+            return Err(StegoError::ContainerTooSmall { required: bits_num, available: capacity.saturating_sub(PREAMBLE_BITS) });
+        }
+        Ok(())
+    }
+}
+
+// Возвращает отсчеты стегаконтейнера по каналам. Сообщение встраивается в поток,
+// выбранный политикой spreading.channel_mode, после синхронизирующей преамбулы.
+pub fn st(container: &[Vec<f64>], message: &[u8], spreading: &Spreading, n: usize, m: usize) -> Result<Vec<Vec<f64>>, StegoError> {
+    if n * m == 0 {
+        return Ok(container.to_vec());
+    }
+
+    st_frame(container, &message_bits(message, n * m), spreading)
+}
+
+// Встраивание сообщения вместе с заголовком: преамбула, заголовок, затем n*m бит сообщения,
+// закодированные выбранным в заголовке помехоустойчивым кодом
+pub fn st_with_header(container: &[Vec<f64>], message: &[u8], spreading: &Spreading, header: &StegoHeader) -> Result<Vec<Vec<f64>>, StegoError> {
+    st_frame(container, &header.encode_frame(message), spreading)
+}
+
+// Встраивание готовых бит кадра после синхронизирующей преамбулы
+pub fn st_frame(container: &[Vec<f64>], bits: &[bool], spreading: &Spreading) -> Result<Vec<Vec<f64>>, StegoError> {
+    let stream = spreading.channel_mode.stream(container);
+    spreading.validate(stream.len(), bits.len())?;
+
+    // Поток all чередует отсчеты каналов (см. ChannelMode::stream)
+    let channels = if spreading.channel_mode == ChannelMode::All { container.len().max(1) } else { 1 };
+    let stego_stream = embed_into_stream(&stream, channels, PREAMBLE.into_iter().chain(bits.iter().copied()), spreading);
+    Ok(spreading.channel_mode.merge(container, &stego_stream))
+}
+
+// Ровно bits_num бит сообщения: недостающие биты встраиваются как единицы
+pub fn message_bits(message: &[u8], bits_num: usize) -> Vec<bool> {
+    BitVec::<u8, Msb0>::from_slice(message).into_iter()
+        .chain(std::iter::repeat(true))
+        .take(bits_num)
+        .collect()
+}
+
+fn embed_into_stream(stream: &[f64], channels: usize, bits: impl Iterator<Item = bool>, spreading: &Spreading) -> Vec<f64> {
+    match (spreading.domain, spreading.gain) {
+        (Domain::Time, Gain::Fixed(strength)) => embed_bits(stream, bits, spreading.samples_per_msg_bit, spreading.psp, strength),
+        (Domain::Time, Gain::Adaptive { margin_db, sample_rate }) => {
+            let spread = spread_bits(stream.len(), bits, spreading.samples_per_msg_bit, spreading.psp);
+            let watermark = shape_interleaved(stream, &spread, channels, sample_rate, margin_db);
+            stream.iter().zip(watermark.iter()).map(|(x, w)| x + w).collect()
+        }
+        (Domain::Mdct, gain) => {
+            let spread = spread_bits(Domain::Mdct.capacity(stream.len()), bits, spreading.samples_per_msg_bit, spreading.psp);
+            embed_watermark(stream, &spread, relative_strength(Domain::Mdct, gain))
+        }
+        (domain @ Domain::Fft { low, high }, gain) => {
+            let spread = spread_bits(domain.capacity(stream.len()), bits, spreading.samples_per_msg_bit, spreading.psp);
+            spectrum::embed_watermark(stream, &spread, relative_strength(domain, gain), low, high, spreading.psp)
+        }
+    }
+}
+
+// Порог маскирования считается по каждому каналу отдельно: в потоке с чередующимися
+// каналами частоты полос удвоились бы, а разность каналов попала бы к частоте Найквиста
+fn shape_interleaved(stream: &[f64], spread: &[f64], channels: usize, sample_rate: u32, margin_db: f64) -> Vec<f64> {
+    let mut watermark = vec![0.0; stream.len()];
+    for channel in 0..channels {
+        let samples: Vec<f64> = stream.iter().skip(channel).step_by(channels).copied().collect();
+        let chips: Vec<f64> = spread.iter().skip(channel).step_by(channels).copied().collect();
+        let shaped = shape_watermark(&samples, &chips, sample_rate, margin_db);
+        for (w, s) in watermark.iter_mut().skip(channel).step_by(channels).zip(shaped) {
+            *w = s;
+        }
+    }
+    watermark
+}
+
+// В областях преобразований изменение и так пропорционально модулю коэффициента,
+// поэтому при адаптивном усилении запас просто уменьшает силу по умолчанию
+fn relative_strength(domain: Domain, gain: Gain) -> f64 {
+    match gain {
+        Gain::Fixed(strength) => strength,
+        Gain::Adaptive { margin_db, .. } => domain.default_strength() * 10f64.powf(-margin_db / 20.0),
+    }
+}
+
+// ПСП со знаком бита в каждом сегменте; вне сообщения нули
+fn spread_bits(len: usize, bits: impl Iterator<Item = bool>, samples_per_msg_bit: usize, psp: &[i16]) -> Vec<f64> {
+    let mut spread = vec![0.0; len];
 
-    let msg_bits: BitVec<_, Msb0> = BitVec::from_slice(&data.message);
-    let mut msg_bits_iter = msg_bits.into_iter();
+    for (i, bit) in bits.enumerate() {
+        let sign = if bit { 1.0 } else { -1.0 };
+        let segment = &mut spread[i * samples_per_msg_bit..(i + 1) * samples_per_msg_bit];
+        for (chip, &c) in segment.iter_mut().zip(psp.iter()) {
+            *chip = sign * c as f64;
+        }
+    }
+
+    spread
+}
+
+fn embed_bits(amplitudes: &[f64], bits: impl Iterator<Item = bool>, samples_per_msg_bit: usize, psp: &[i16], strength: f64) -> Vec<f64> {
+    let mut result_amplitudes = amplitudes.to_vec();
 
-    for i in 0..(n * m) {
-        let pspmes: Vec<f64> = if msg_bits_iter.next() == Some(false) {
-            psp.iter().map(|&x| -x as f64 * 0.0005).collect()
+    for (i, bit) in bits.enumerate() {
+        let pspmes: Vec<f64> = if !bit {
+            psp.iter().map(|&x| -x as f64 * strength).collect()
         } else {
-            psp.iter().map(|&x| x as f64 * 0.0005).collect()
+            psp.iter().map(|&x| x as f64 * strength).collect()
         };
 
         let start = i * samples_per_msg_bit;
@@ -20,7 +195,6 @@ pub fn st(data: &EncryptData, samples_per_msg_bit: usize, n: usize, m: usize, ps
 
         for j in start..end {
             let original_amp = result_amplitudes[j];
-            // println!("{} -> {}", data.container.amplitudes[j], original_amp + pspmes[j - start] * (original_amp + 2.0));
             result_amplitudes[j] = original_amp + pspmes[j - start] * (original_amp + 2.0);
         }
     }
@@ -31,84 +205,110 @@ pub fn st(data: &EncryptData, samples_per_msg_bit: usize, n: usize, m: usize, ps
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::utils::{EncryptData, WavFile};
+    use crate::chip_generator::ChipGenerator;
     use crate::park_miller_prng::ParkMiller;
+    use crate::sync::PREAMBLE_BITS;
+    use crate::test_signals::sine;
 
     // Вспомогательная функция для создания тестовых данных
-    fn create_test_data() -> (EncryptData, Vec<i16>) {
-        let container = WavFile {
-            name: "test.wav".to_string(),
-            amplitudes: vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8],
-            bits_per_sample: 16,
-            channels: 1,
-            sample_rate: 44100,
-            samples_num: 8,
-        };
+    fn create_test_data() -> (Vec<Vec<f64>>, Vec<u8>, Vec<i16>) {
+        // Преамбула и 8 бит сообщения по одному отсчету на бит
+        let container = vec![(1..=PREAMBLE_BITS + 8).map(|i| i as f64 * 0.04).collect()];
 
         let message = vec![0b10101010];  // Пример сообщения (1 байт)
-        let encrypt_data = EncryptData {
-            container,
-            message,
-        };
 
         // Генерация псевдослучайной последовательности (PSP)
-        let mut generator = ParkMiller::new();
+        let mut generator = ParkMiller::with_seed(42).unwrap();
         let psp = generator.generate_prs(8);  // 8 сэмплов на бит
 
-        (encrypt_data, psp)
+        (container, message, psp)
     }
 
     #[test]
     fn test_st_with_non_empty_message() {
-        let (encrypt_data, psp) = create_test_data();
+        let (container, message, psp) = create_test_data();
         let bits_per_char = 8;  // 8 бит на символ (ASCII)
-        let samples_per_msg_bit = encrypt_data.container.samples_num as usize / (bits_per_char * encrypt_data.message.len());
+        let samples_per_msg_bit = container[0].len() / (PREAMBLE_BITS + bits_per_char * message.len());
 
         // Вызов функции st для создания стего-контейнера
-        let stego_amplitudes = st(&encrypt_data, samples_per_msg_bit as usize, bits_per_char, encrypt_data.message.len(), psp);
+        let stego_amplitudes = st(&container, &message, &Spreading::new(&psp, samples_per_msg_bit), bits_per_char, message.len()).unwrap();
 
         // Проверяем, что длина массива амплитуд не изменилась
-        assert_eq!(stego_amplitudes.len(), encrypt_data.container.amplitudes.len());
+        assert_eq!(stego_amplitudes.len(), container.len());
 
         // Проверяем, что амплитуды изменились
-        assert_ne!(stego_amplitudes, encrypt_data.container.amplitudes);
+        assert_ne!(stego_amplitudes, container);
 
         // Проверяем, что изменения амплитуд соответствуют ожидаемым
-        for i in 0..stego_amplitudes.len() {
-            let original_amp = encrypt_data.container.amplitudes[i];
-            let stego_amp = stego_amplitudes[i];
+        for (original_amp, stego_amp) in container[0].iter().zip(stego_amplitudes[0].iter()) {
             assert_ne!(original_amp, stego_amp);  // Амплитуды должны измениться
         }
     }
 
     #[test]
     fn test_st_with_empty_message() {
-        let container = WavFile {
-            name: "test.wav".to_string(),
-            amplitudes: vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8],
-            bits_per_sample: 16,
-            channels: 1,
-            sample_rate: 44100,
-            samples_num: 8,
-        };
+        let container = vec![vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8]];
 
         let message = vec![];  // Пустое сообщение
-        let encrypt_data = EncryptData {
-            container,
-            message,
-        };
 
         // Генерация псевдослучайной последовательности (PSP)
-        let mut generator = ParkMiller::new();
+        let mut generator = ParkMiller::with_seed(42).unwrap();
         let psp = generator.generate_prs(8);  // 8 сэмплов на бит
 
         let bits_per_char = 8;  // 8 бит на символ (ASCII)
-        let samples_per_msg_bit = encrypt_data.container.samples_num as usize / (bits_per_char * encrypt_data.message.len().max(1));
+        let samples_per_msg_bit = container[0].len() / (bits_per_char * message.len().max(1));
 
         // Вызов функции st для создания стего-контейнера
-        let stego_amplitudes = st(&encrypt_data, samples_per_msg_bit as usize, bits_per_char, encrypt_data.message.len(), psp);
+        let stego_amplitudes = st(&container, &message, &Spreading::new(&psp, samples_per_msg_bit), bits_per_char, message.len()).unwrap();
 
         // Проверяем, что амплитуды не изменились, так как сообщение пустое
-        assert_eq!(stego_amplitudes, encrypt_data.container.amplitudes);
+        assert_eq!(stego_amplitudes, container);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_st_strength_scales_deviation() {
+        let (container, message, psp) = create_test_data();
+        let deviation = |strength: f64| -> f64 {
+            let spreading = Spreading { gain: Gain::Fixed(strength), ..Spreading::new(&psp, 1) };
+            let stego = st(&container, &message, &spreading, 8, message.len()).unwrap();
+            container[0].iter().zip(stego[0].iter()).map(|(x, y)| (x - y).abs()).sum()
+        };
+
+        assert!((deviation(2.0 * DEFAULT_STRENGTH) - 2.0 * deviation(DEFAULT_STRENGTH)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_st_rejects_invalid_spreading() {
+        let (container, message, psp) = create_test_data();
+
+        // ПСП короче сегмента и нулевой сегмент
+        assert!(matches!(st(&container, &message, &Spreading::new(&psp[..4], 8), 8, 1), Err(StegoError::InvalidArgument(_))));
+        assert!(matches!(st(&container, &message, &Spreading::new(&psp, 0), 8, 1), Err(StegoError::InvalidArgument(_))));
+
+        // Преамбула и 8 бит по 2 отсчета не помещаются в PREAMBLE_BITS + 8 отсчетов
+        let available = ((PREAMBLE_BITS + 8) / 2).saturating_sub(PREAMBLE_BITS);
+        assert!(matches!(
+            st(&container, &message, &Spreading::new(&psp, 2), 8, 1),
+            Err(StegoError::ContainerTooSmall { required: 8, available: a }) if a == available
+        ));
+    }
+
+    #[test]
+    fn test_adaptive_gain_shapes_each_channel() {
+        // Громкий левый канал и тихий правый: порог маскирования у каждого свой
+        let container = vec![sine(440.0, 0.3, 40_000), sine(440.0, 0.003, 40_000)];
+        let psp = ParkMiller::with_seed(3).unwrap().generate_prs(1000);
+        let spreading = Spreading {
+            channel_mode: ChannelMode::All,
+            gain: Gain::Adaptive { margin_db: 3.0, sample_rate: 44100 },
+            ..Spreading::new(&psp, 1000)
+        };
+
+        let stego = st_frame(&container, &[true; 8], &spreading).unwrap();
+        let energy = |channel: usize| -> f64 {
+            container[channel].iter().zip(&stego[channel]).map(|(x, y)| (x - y).powi(2)).sum()
+        };
+        // При общем пороге для чередующихся отсчетов мощности в каналах были бы почти равны
+        assert!(energy(0) > 10.0 * energy(1));
+    }
+}
@@ -0,0 +1,826 @@
+use std::sync::Arc;
+use crate::channels::ChannelMode;
+use crate::crypto::{decrypt_payload, encrypt_payload, ENVELOPE_OVERHEAD};
+use crate::dest::{bits_from_correlations, decode_frame, frame_copies, repeated_copies};
+use crate::dsss::SpreadSpectrum;
+use crate::error::StegoError;
+use crate::fec::FecConfig;
+use crate::header::{StegoHeader, FLAG_ENCRYPTED, FLAG_REPEATED, VERSION};
+use crate::hmac_prng::HmacSha256Generator;
+use crate::key::KeySpec;
+use crate::method::StegoMethod;
+use crate::park_miller_prng::ParkMiller;
+use crate::st::{count_bits_per_char, message_bits, Gain};
+use crate::sync::SyncPoint;
+use crate::transform::Domain;
+
+// Встраивание сообщения в отсчеты, разделенные по каналам (значения в диапазоне [-1.0, 1.0]).
+// По умолчанию: заголовок без помехоустойчивого кода, расширение спектра в первом канале
+// во временной области с постоянной силой встраивания, генератор Park-Miller с зерном
+// из текущего времени.
+#[derive(Debug, Clone)]
+pub struct Embedder {
+    // Расширение спектра, которое настраивают channel_mode, domain и gain
+    spread_spectrum: SpreadSpectrum,
+    // Другой алгоритм встраивания вместо расширения спектра
+    method: Option<Arc<dyn StegoMethod>>,
+    fec: FecConfig,
+    with_header: bool,
+    seed: Option<u32>,
+    passphrase: Option<String>,
+    payload_passphrase: Option<String>,
+    min_chips_per_bit: usize,
+    // Постоянное количество позиций на бит вместо деления контейнера между битами кадра
+    chips_per_bit: Option<usize>,
+    repeat: bool,
+}
+
+// Сколько помещается в контейнер при настройках встраивания (см. Embedder::capacity)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capacity {
+    // Наибольшее количество бит кадра вместе с заголовком и помехоустойчивым кодом
+    pub frame_bits: usize,
+    // Наибольшая длина сообщения в байтах для ASCII-текста или зашифрованного сообщения;
+    // текст с многобайтовыми символами UTF-8 занимает больше (см. count_bits_per_char)
+    pub payload_bytes: usize,
+}
+
+// Как сообщение ляжет в контейнер (см. Embedder::plan)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmbeddingPlan {
+    pub frame_bits: usize,
+    // Длина ПСП, которую выберет алгоритм встраивания
+    pub key_len: usize,
+    // None, если алгоритм не распределяет бит по нескольким позициям (см. StegoMethod::chips_per_bit)
+    pub chips_per_bit: Option<usize>,
+    // Количество копий кадра с хотя бы одним битом сообщения; последняя может быть неполной
+    // (см. Embedder::repeat). Столько же копий складывается при извлечении (Extracted::copies)
+    pub copies: usize,
+}
+
+impl EmbeddingPlan {
+    // Ожидаемый выигрыш от расширения спектра и сложения копий: 10 lg N дБ при N позициях на бит
+    // во всех копиях. Бит за концом неполной последней копии получают на одну копию меньше,
+    // поэтому для них это оценка сверху
+    pub fn processing_gain_db(&self) -> Option<f64> {
+        self.chips_per_bit.map(|chips| 10.0 * ((chips * self.copies.max(1)) as f64).log10())
+    }
+}
+
+// Результат встраивания: отсчеты стегаконтейнера и все, что нужно для извлечения
+#[derive(Debug, Clone)]
+pub struct Embedded {
+    pub amplitudes: Vec<Vec<f64>>,
+    pub key: KeySpec,
+    // ПСП, восстановленная по ключу (для ключей старого формата)
+    pub psp: Vec<i16>,
+    // Алгоритм и параметры, которые записываются в ключ
+    pub method: Arc<dyn StegoMethod>,
+    // Отсутствует, если сообщение встроено без заголовка
+    pub header: Option<StegoHeader>,
+    pub bits_per_symbol: usize,
+    pub message_len: usize,
+}
+
+impl Default for Embedder {
+    fn default() -> Self {
+        Embedder {
+            spread_spectrum: SpreadSpectrum::default(),
+            method: None,
+            fec: FecConfig::NONE,
+            with_header: true,
+            seed: None,
+            passphrase: None,
+            payload_passphrase: None,
+            min_chips_per_bit: 1,
+            chips_per_bit: None,
+            repeat: false,
+        }
+    }
+}
+
+impl Embedder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn channel_mode(mut self, channel_mode: ChannelMode) -> Self {
+        self.spread_spectrum.channel_mode = channel_mode;
+        self
+    }
+
+    // Отсчеты сигнала или коэффициенты MDCT, которые переживают сжатие с потерями
+    pub fn domain(mut self, domain: Domain) -> Self {
+        self.spread_spectrum.domain = domain;
+        self
+    }
+
+    // Постоянная амплитуда ПСП или подстройка под порог маскирования контейнера
+    pub fn gain(mut self, gain: Gain) -> Self {
+        self.spread_spectrum.gain = Some(gain);
+        self
+    }
+
+    // Алгоритм встраивания вместо расширения спектра, настроенного channel_mode, domain и gain
+    pub fn method(mut self, method: Arc<dyn StegoMethod>) -> Self {
+        self.method = Some(method);
+        self
+    }
+
+    // Помехоустойчивый код для сообщения, записывается в заголовок
+    pub fn fec(mut self, fec: FecConfig) -> Self {
+        self.fec = fec;
+        self
+    }
+
+    // Без заголовка при извлечении нужно знать количество бит на символ и длину сообщения
+    pub fn without_header(mut self) -> Self {
+        self.with_header = false;
+        self
+    }
+
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    // ПСП генерируется hmac-sha256 с ключом из пароля вместо Park-Miller
+    pub fn passphrase(mut self, passphrase: &str) -> Self {
+        self.passphrase = Some(passphrase.to_string());
+        self
+    }
+
+    // Сообщение шифруется ChaCha20-Poly1305 перед встраиванием
+    pub fn payload_passphrase(mut self, passphrase: &str) -> Self {
+        self.payload_passphrase = Some(passphrase.to_string());
+        self
+    }
+
+    // Не встраивать, если на бит придется меньше min_chips_per_bit позиций контейнера
+    // (для алгоритмов, которые распределяют бит по нескольким позициям)
+    pub fn min_chips_per_bit(mut self, min_chips_per_bit: usize) -> Self {
+        self.min_chips_per_bit = min_chips_per_bit.max(1);
+        self
+    }
+
+    // Постоянное количество позиций на бит: короткое сообщение не растягивается на весь
+    // контейнер, а занимает его начало (для алгоритмов, которые распределяют бит по позициям)
+    pub fn chips_per_bit(mut self, chips_per_bit: usize) -> Self {
+        self.chips_per_bit = Some(chips_per_bit);
+        self
+    }
+
+    // Кадр повторяется до конца контейнера, а при извлечении копии складываются. Имеет смысл
+    // вместе с chips_per_bit: иначе первая копия и так занимает весь контейнер. Только с заголовком.
+    pub fn repeat(mut self) -> Self {
+        self.repeat = true;
+        self
+    }
+
+    fn resolved_method(&self) -> Arc<dyn StegoMethod> {
+        self.method.clone().unwrap_or_else(|| Arc::new(self.spread_spectrum))
+    }
+
+    // Количество бит кадра для сообщения из payload_len символов по bits_per_symbol бит
+    fn frame_bits(&self, payload_len: usize, bits_per_symbol: usize) -> usize {
+        if !self.with_header {
+            return payload_len * bits_per_symbol;
+        }
+        let header = StegoHeader {
+            version: VERSION,
+            flags: 0,
+            bits_per_symbol: bits_per_symbol as u8,
+            fec: self.fec,
+            payload_len: payload_len as u32,
+            checksum: 0,
+        };
+        header.frame_bits()
+    }
+
+    // Длина ПСП для кадра из bits_num бит; 0, если кадр не помещается или на бит
+    // приходится меньше min_chips_per_bit позиций
+    fn key_len(&self, method: &dyn StegoMethod, container: &[Vec<f64>], bits_num: usize) -> usize {
+        let key_len = match self.chips_per_bit {
+            Some(chips) if method.chips_per_bit(chips).is_some() => {
+                if method.capacity_for_key_len(container, chips) >= bits_num { chips } else { 0 }
+            }
+            _ => method.key_len(container, bits_num),
+        };
+        match method.chips_per_bit(key_len) {
+            Some(chips) if chips < self.min_chips_per_bit => 0,
+            _ => key_len,
+        }
+    }
+
+    // Наибольший кадр, который помещается в контейнер. Чем длиннее кадр, тем меньше позиций
+    // на бит, поэтому кадр ищется двоичным поиском.
+    fn max_frame_bits(&self, method: &dyn StegoMethod, container: &[Vec<f64>]) -> usize {
+        let (mut low, mut high) = (0, method.capacity(container));
+        while low < high {
+            let middle = (low + high).div_ceil(2);
+            if self.key_len(method, container, middle) > 0 {
+                low = middle;
+            } else {
+                high = middle - 1;
+            }
+        }
+        low
+    }
+
+    // Емкость контейнера при текущих алгоритме, коде, заголовке и шифровании, до встраивания
+    pub fn capacity(&self, container: &[Vec<f64>]) -> Result<Capacity, StegoError> {
+        check_channels(container)?;
+        let frame_bits = self.max_frame_bits(self.resolved_method().as_ref(), container);
+        let overhead = if self.payload_passphrase.is_some() { ENVELOPE_OVERHEAD } else { 0 };
+        let fits = |bytes: usize| self.frame_bits(bytes + overhead, 8) <= frame_bits;
+
+        let payload_bytes = if fits(0) {
+            let (mut low, mut high) = (0, frame_bits / 8);
+            while low < high {
+                let middle = (low + high).div_ceil(2);
+                if fits(middle) { low = middle } else { high = middle - 1 }
+            }
+            low
+        } else {
+            0
+        };
+        Ok(Capacity { frame_bits, payload_bytes })
+    }
+
+    // Длина кадра и ПСП для сообщения, до встраивания. Ошибка, если сообщение не помещается.
+    pub fn plan(&self, container: &[Vec<f64>], message: &[u8]) -> Result<EmbeddingPlan, StegoError> {
+        check_channels(container)?;
+        let (payload_len, bits_per_symbol) = match &self.payload_passphrase {
+            Some(_) => (message.len() + ENVELOPE_OVERHEAD, 8),
+            None => (message.len(), count_bits_per_char(message)?),
+        };
+        let method = self.resolved_method();
+        self.plan_frame(method.as_ref(), container, self.frame_bits(payload_len, bits_per_symbol))
+    }
+
+    fn plan_frame(&self, method: &dyn StegoMethod, container: &[Vec<f64>], frame_bits: usize) -> Result<EmbeddingPlan, StegoError> {
+        let key_len = self.key_len(method, container, frame_bits);
+        if key_len == 0 {
+            return Err(StegoError::ContainerTooSmall { required: frame_bits, available: self.max_frame_bits(method, container) });
+        }
+
+        let copies = frame_copies(frame_bits, self.embedded_bits(method, container, key_len, frame_bits));
+        Ok(EmbeddingPlan { frame_bits, key_len, chips_per_bit: method.chips_per_bit(key_len), copies })
+    }
+
+    fn repeats(&self) -> bool {
+        self.repeat && self.with_header
+    }
+
+    // Количество встраиваемых бит: кадр или копии кадра на всех позициях, которые помещаются
+    // при этой длине ПСП
+    fn embedded_bits(&self, method: &dyn StegoMethod, container: &[Vec<f64>], key_len: usize, frame_bits: usize) -> usize {
+        if self.repeats() {
+            method.capacity_for_key_len(container, key_len).max(frame_bits)
+        } else {
+            frame_bits
+        }
+    }
+
+    pub fn embed(&self, container: &[Vec<f64>], message: &[u8]) -> Result<Embedded, StegoError> {
+        check_channels(container)?;
+        // Зашифрованное сообщение - произвольные байты, поэтому встраивается по 8 бит на байт
+        let (message, bits_per_symbol, flags) = match &self.payload_passphrase {
+            Some(passphrase) => (encrypt_payload(message, passphrase)?, 8, FLAG_ENCRYPTED),
+            None => (message.to_vec(), count_bits_per_char(message)?, 0),
+        };
+
+        let fec = if self.with_header { self.fec } else { FecConfig::NONE };
+        let flags = if self.repeats() { flags | FLAG_REPEATED } else { flags };
+        let header = StegoHeader::new(&message, bits_per_symbol, flags, fec);
+        let bits = if self.with_header {
+            header.encode_frame(&message)
+        } else {
+            message_bits(&message, bits_per_symbol * message.len())
+        };
+
+        let method = self.resolved_method();
+        let chips = self.plan_frame(method.as_ref(), container, bits.len())?.key_len;
+        let bits_num = self.embedded_bits(method.as_ref(), container, chips, bits.len());
+        let bits: Vec<bool> = bits.iter().copied().cycle().take(bits_num).collect();
+
+        let key = match &self.passphrase {
+            Some(_) => KeySpec::HmacSha256 { salt: HmacSha256Generator::generate_salt()?, chips },
+            None => KeySpec::ParkMiller {
+                seed: self.seed.unwrap_or_else(|| ParkMiller::new().seed()),
+                chips,
+            },
+        };
+        let psp = key.generate_prs(self.passphrase.as_deref())?;
+
+        let amplitudes = method.embed(container, &bits, &psp)?;
+        if self.with_header {
+            verify_frame(method.as_ref(), container, &amplitudes, &psp)?;
+        }
+
+        Ok(Embedded {
+            amplitudes,
+            key,
+            psp,
+            method,
+            header: self.with_header.then_some(header),
+            bits_per_symbol,
+            message_len: message.len(),
+        })
+    }
+}
+
+// Все каналы должны быть одной длины: потоки отсчетов (см. ChannelMode::stream) строятся
+// кадр за кадром по всем каналам
+fn check_channels(channels: &[Vec<f64>]) -> Result<(), StegoError> {
+    let len = channels.first().map_or(0, |channel| channel.len());
+    match channels.iter().find(|channel| channel.len() != len) {
+        Some(channel) => Err(StegoError::InvalidArgument(format!(
+            "каналы контейнера разной длины ({} и {} отсчетов)", len, channel.len(),
+        ))),
+        None => Ok(()),
+    }
+}
+
+// Проверка после встраивания: кадр с заголовком должен извлекаться из стегаконтейнера,
+// отсчеты которого ограничены полной шкалой, как при сохранении в WAV-файл. Иначе об успехе
+// было бы сообщено, а сообщение не нашлось бы (перегрузка, эхо на чистом тоне и т.п.).
+fn verify_frame(method: &dyn StegoMethod, container: &[Vec<f64>], amplitudes: &[Vec<f64>], key: &[i16]) -> Result<(), StegoError> {
+    let saved: Vec<Vec<f64>> = amplitudes.iter()
+        .map(|channel| channel.iter().map(|x| x.clamp(-1.0, 1.0)).collect())
+        .collect();
+    method.demodulate(&saved, Some(container), key)
+        .and_then(|frame| decode_frame(frame.as_ref()))
+        .map(|_| ())
+        .map_err(|e| StegoError::Unrecoverable(Box::new(e)))
+}
+
+// Извлечение сообщения по ПСП. Без исходного контейнера извлечение слепое. Если алгоритм
+// встраивания не указан, перебираются варианты расширения спектра: указанные политика каналов
+// и область встраивания или все.
+#[derive(Debug, Clone)]
+pub struct Extractor<'a> {
+    key: Vec<i16>,
+    container: Option<&'a [Vec<f64>]>,
+    method: Option<Arc<dyn StegoMethod>>,
+    channel_mode: Option<ChannelMode>,
+    domain: Option<Domain>,
+    payload_passphrase: Option<String>,
+    // Количество бит на символ и длина сообщения, встроенного без заголовка
+    without_header: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Extracted {
+    pub message: Vec<u8>,
+    pub header: Option<StegoHeader>,
+    // Алгоритм, которым сообщение было найдено
+    pub method: Arc<dyn StegoMethod>,
+    // Минимальный модуль корреляции по битам (только для сообщений без заголовка)
+    pub min_margin: Option<f64>,
+    // Найденное начало кадра в потоке отсчетов и корреляция с преамбулой
+    pub sync: Option<SyncPoint>,
+    // Количество сложенных копий кадра (см. Embedder::repeat)
+    pub copies: usize,
+}
+
+impl<'a> Extractor<'a> {
+    pub fn new(key: Vec<i16>) -> Self {
+        Extractor {
+            key,
+            container: None,
+            method: None,
+            channel_mode: None,
+            domain: None,
+            payload_passphrase: None,
+            without_header: None,
+        }
+    }
+
+    pub fn from_key_spec(spec: &KeySpec, passphrase: Option<&str>) -> Result<Self, StegoError> {
+        Ok(Self::new(spec.generate_prs(passphrase)?))
+    }
+
+    pub fn container(mut self, container: &'a [Vec<f64>]) -> Self {
+        self.container = Some(container);
+        self
+    }
+
+    pub fn channel_mode(mut self, channel_mode: ChannelMode) -> Self {
+        self.channel_mode = Some(channel_mode);
+        self
+    }
+
+    pub fn domain(mut self, domain: Domain) -> Self {
+        self.domain = Some(domain);
+        self
+    }
+
+    // Алгоритм и параметры из ключа: перебор не нужен
+    pub fn method(mut self, method: Arc<dyn StegoMethod>) -> Self {
+        self.method = Some(method);
+        self
+    }
+
+    pub fn payload_passphrase(mut self, passphrase: &str) -> Self {
+        self.payload_passphrase = Some(passphrase.to_string());
+        self
+    }
+
+    pub fn without_header(mut self, bits_per_symbol: usize, message_len: usize) -> Self {
+        self.without_header = Some((bits_per_symbol, message_len));
+        self
+    }
+
+    pub fn extract(&self, stegocontainer: &[Vec<f64>]) -> Result<Extracted, StegoError> {
+        check_channels(stegocontainer)?;
+        if let Some(container) = self.container {
+            check_channels(container)?;
+        }
+        match self.without_header {
+            Some((bits_per_symbol, message_len)) => self.extract_without_header(stegocontainer, bits_per_symbol, message_len),
+            None => self.extract_with_header(stegocontainer),
+        }
+    }
+
+    fn extract_without_header(&self, stegocontainer: &[Vec<f64>], bits_per_symbol: usize, message_len: usize) -> Result<Extracted, StegoError> {
+        let method = self.method.clone().unwrap_or_else(|| Arc::new(SpreadSpectrum {
+            channel_mode: self.channel_mode.unwrap_or(ChannelMode::Left),
+            domain: self.domain.unwrap_or(Domain::Time),
+            gain: None,
+        }));
+
+        let frame = method.demodulate(stegocontainer, self.container, &self.key)?;
+        let correlations = frame.soft_bits(0, bits_per_symbol * message_len)?;
+        let message = bits_from_correlations(&correlations);
+        let min_margin = correlations.iter().fold(f64::INFINITY, |acc, c| acc.min(c.abs()));
+
+        let message = match &self.payload_passphrase {
+            Some(passphrase) => decrypt_payload(&message, passphrase)?,
+            None => message,
+        };
+
+        Ok(Extracted { message, header: None, method, min_margin: Some(min_margin), sync: frame.sync(), copies: 1 })
+    }
+
+    fn extract_with_header(&self, stegocontainer: &[Vec<f64>]) -> Result<Extracted, StegoError> {
+        // Заголовок с CRC находится только при верном алгоритме и его параметрах
+        let candidates = match &self.method {
+            Some(method) => vec![method.clone()],
+            None => SpreadSpectrum::candidates(self.channel_mode, self.domain),
+        };
+
+        let mut last_error = None;
+        for method in candidates {
+            let decoded = method.demodulate(stegocontainer, self.container, &self.key)
+                .and_then(|frame| Ok((decode_frame(frame.as_ref())?, frame.sync(), frame.capacity())));
+            let ((header, message), sync, capacity) = match decoded {
+                Ok(result) => result,
+                Err(e) => {
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+
+            let message = if header.is_encrypted() {
+                let passphrase = self.payload_passphrase.as_deref().ok_or(StegoError::MissingPassphrase(
+                    "сообщение зашифровано, необходим пароль (--payload-passphrase)",
+                ))?;
+                decrypt_payload(&message, passphrase)?
+            } else {
+                message
+            };
+
+            let copies = 1 + repeated_copies(&header, capacity).count();
+            return Ok(Extracted { message, header: Some(header), method, min_margin: None, sync, copies });
+        }
+
+        Err(last_error.unwrap_or_else(|| StegoError::Header("заголовок стегаконтейнера не найден".to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attack::Attack;
+    use crate::fec::InnerCode;
+    use crate::header::HEADER_BITS;
+    use crate::lsb::LeastSignificantBits;
+    use crate::sync::PREAMBLE_BITS;
+    use crate::echo::EchoHiding;
+    use crate::phase::PhaseCoding;
+    use crate::qim::QuantizationIndexModulation;
+    use crate::test_signals::{music, noise, requantize_16bit, sine};
+
+    // Тихий стереоконтейнер, на котором работает и слепое извлечение
+    fn quiet_stereo(frames: usize) -> Vec<Vec<f64>> {
+        let left = sine(440.0, 0.001, frames);
+        let right = left.iter().map(|x| -0.5 * x).collect();
+        vec![left, right]
+    }
+
+    #[test]
+    fn test_embed_extract_round_trip() {
+        let container = quiet_stereo(100_000);
+        let message = "Привет из библиотеки".as_bytes();
+
+        let embedded = Embedder::new()
+            .channel_mode(ChannelMode::All)
+            .fec(FecConfig { inner: InnerCode::Hamming74, rs_parity: 4 })
+            .seed(42)
+            .embed(&container, message)
+            .expect("Сообщение должно встраиваться");
+        assert_eq!(embedded.key, KeySpec::ParkMiller { seed: 42, chips: embedded.psp.len() });
+        assert_eq!(embedded.amplitudes.len(), 2);
+
+        // Политика каналов определяется перебором, исходный контейнер не нужен
+        let extracted = Extractor::from_key_spec(&embedded.key, None).unwrap()
+            .extract(&embedded.amplitudes)
+            .expect("Сообщение должно извлекаться");
+        assert_eq!(extracted.message, message);
+        assert_eq!(extracted.method.parameters(), ["all", "time"]);
+        assert_eq!(extracted.header, embedded.header);
+    }
+
+    #[test]
+    fn test_embed_extract_encrypted_with_passphrase() {
+        let container = quiet_stereo(60_000);
+        let message = b"secret";
+
+        let embedded = Embedder::new()
+            .passphrase("ключ")
+            .payload_passphrase("пароль")
+            .embed(&container, message)
+            .unwrap();
+        assert!(embedded.header.as_ref().unwrap().is_encrypted());
+
+        assert!(matches!(Extractor::from_key_spec(&embedded.key, None), Err(StegoError::MissingPassphrase(_))));
+
+        let extractor = Extractor::from_key_spec(&embedded.key, Some("ключ")).unwrap()
+            .container(&container)
+            .channel_mode(ChannelMode::Left);
+        assert!(matches!(extractor.extract(&embedded.amplitudes), Err(StegoError::MissingPassphrase(_))));
+
+        let extracted = extractor.payload_passphrase("пароль").extract(&embedded.amplitudes).unwrap();
+        assert_eq!(extracted.message, message);
+    }
+
+    #[test]
+    fn test_embed_extract_without_header() {
+        let container = quiet_stereo(10_000);
+        let message = b"legacy";
+
+        let embedded = Embedder::new().without_header().seed(1).embed(&container, message).unwrap();
+        assert!(embedded.header.is_none());
+        assert_eq!((embedded.bits_per_symbol, embedded.message_len), (8, message.len()));
+
+        let extracted = Extractor::new(embedded.psp.clone())
+            .container(&container)
+            .without_header(embedded.bits_per_symbol, embedded.message_len)
+            .extract(&embedded.amplitudes)
+            .unwrap();
+        assert_eq!(extracted.message, message);
+        assert!(extracted.min_margin.unwrap() > 0.0);
+    }
+
+    #[test]
+    fn test_embed_extract_adaptive_gain() {
+        // Громкий контейнер: порог маскирования заметно выше шага квантования
+        let container = vec![sine(440.0, 0.3, 100_000)];
+        let message = "Адаптивно".as_bytes();
+
+        let embedded = Embedder::new()
+            .gain(Gain::Adaptive { margin_db: 3.0, sample_rate: 44100 })
+            .seed(7)
+            .embed(&container, message)
+            .unwrap();
+
+        let extracted = Extractor::from_key_spec(&embedded.key, None).unwrap()
+            .container(&container)
+            .channel_mode(ChannelMode::Left)
+            .extract(&embedded.amplitudes)
+            .unwrap();
+        assert_eq!(extracted.message, message);
+    }
+
+    #[test]
+    fn test_mdct_domain_survives_lossy_compression() {
+        // Шумоподобный контейнер: у синусоиды почти все коэффициенты средних частот нулевые
+        let container = vec![noise(200_000, 12345).iter().map(|x| 0.2 * x).collect()];
+        let message = "Сжатие".as_bytes();
+        let attack = Attack::Lossy { kbps: 128 };
+
+        let extract = |domain: Domain| {
+            let embedded = Embedder::new().domain(domain).seed(3).embed(&container, message).unwrap();
+            let compressed = attack.apply(&embedded.amplitudes, 44100);
+            Extractor::from_key_spec(&embedded.key, None).unwrap()
+                .container(&container)
+                .channel_mode(ChannelMode::Left)
+                .extract(&compressed)
+        };
+
+        // Область встраивания определяется перебором
+        let extracted = extract(Domain::Mdct).expect("Сообщение должно пережить сжатие");
+        assert_eq!(extracted.message, message);
+        assert_eq!(extracted.method.parameters(), ["left", "mdct"]);
+        // ПСП во временной области кодек стирает
+        assert!(extract(Domain::Time).is_err());
+    }
+
+    #[test]
+    fn test_fft_domain_survives_telephone_band() {
+        let container = vec![noise(100_000, 777).iter().map(|x| 0.2 * x).collect()];
+        let message = b"phone";
+        let domain = Domain::Fft { low: 300.0 / 44100.0, high: 3400.0 / 44100.0 };
+
+        let embedded = Embedder::new().domain(domain).seed(5).embed(&container, message).unwrap();
+        let band_limited = Attack::HighPass { cutoff: 300.0 }.apply(&Attack::LowPass { cutoff: 3400.0 }.apply(&embedded.amplitudes, 44100), 44100);
+
+        // Полоса не входит в перебор по умолчанию, поэтому задается явно
+        let extracted = Extractor::from_key_spec(&embedded.key, None).unwrap()
+            .container(&container)
+            .domain(domain)
+            .extract(&band_limited)
+            .unwrap();
+        assert_eq!(extracted.message, message);
+    }
+
+    #[test]
+    fn test_lsb_method_round_trip() {
+        // Короткий контейнер: расширению спектра его не хватит даже на заголовок
+        let container = quiet_stereo(2_000);
+        let message = "Младшие биты вмещают намного больше".as_bytes();
+        let method: Arc<dyn StegoMethod> = Arc::new(LeastSignificantBits { sample_bits: 16, depth: 2 });
+
+        let embedded = Embedder::new()
+            .method(method.clone())
+            .fec(FecConfig { inner: InnerCode::Hamming74, rs_parity: 0 })
+            .seed(9)
+            .embed(&container, message)
+            .unwrap();
+        assert_eq!(embedded.method.parameters(), ["16", "2"]);
+
+        // Как при сохранении в 16-битный WAV-файл: отсчеты с сообщением уже на сетке квантования
+        let saved = requantize_16bit(&embedded.amplitudes);
+
+        let extracted = Extractor::from_key_spec(&embedded.key, None).unwrap()
+            .method(method)
+            .extract(&saved)
+            .unwrap();
+        assert_eq!(extracted.message, message);
+        assert!(extracted.sync.is_none());
+    }
+
+    #[test]
+    fn test_echo_method_round_trip() {
+        let container = vec![noise(300_000, 21), noise(300_000, 22)];
+        let message = b"echo";
+        let method: Arc<dyn StegoMethod> = Arc::new(EchoHiding::default());
+
+        let embedded = Embedder::new().method(method.clone()).seed(4).embed(&container, message).unwrap();
+        let extracted = Extractor::from_key_spec(&embedded.key, None).unwrap()
+            .method(method.clone())
+            .extract(&requantize_16bit(&embedded.amplitudes))
+            .unwrap();
+        assert_eq!(extracted.message, message);
+
+        // На чистом тоне эхо не оставляет следа в кепстре: встраивание не сообщает об успехе
+        let tone = vec![sine(440.0, 0.5, 300_000)];
+        assert!(matches!(Embedder::new().method(method).seed(4).embed(&tone, message), Err(StegoError::Unrecoverable(_))));
+    }
+
+    #[test]
+    fn test_phase_method_round_trip() {
+        let message = b"phase";
+        let method: Arc<dyn StegoMethod> = Arc::new(PhaseCoding);
+
+        // Тихая музыка и шум на всю шкалу, у которого первый кадр после поворота фаз уменьшается
+        let full_scale: Vec<f64> = noise(20_000, 23).iter().map(|x| 2.0 * x).collect();
+        for container in [vec![music(20_000), music(20_000)], vec![full_scale]] {
+            let embedded = Embedder::new().method(method.clone()).embed(&container, message).unwrap();
+            let extracted = Extractor::from_key_spec(&embedded.key, None).unwrap()
+                .method(method.clone())
+                .extract(&requantize_16bit(&embedded.amplitudes))
+                .unwrap();
+            assert_eq!(extracted.message, message);
+        }
+    }
+
+    #[test]
+    fn test_qim_method_round_trip() {
+        let container = vec![music(100_000), music(100_000)];
+        let message = b"qim";
+        let method: Arc<dyn StegoMethod> = Arc::new(QuantizationIndexModulation::default());
+
+        let embedded = Embedder::new().method(method.clone()).seed(6).embed(&container, message).unwrap();
+        let extracted = Extractor::from_key_spec(&embedded.key, None).unwrap()
+            .method(method.clone())
+            .extract(&requantize_16bit(&embedded.amplitudes))
+            .unwrap();
+        assert_eq!(extracted.message, message);
+
+        // С другим ключом заголовок не проходит проверку
+        let other = Embedder::new().method(method.clone()).seed(7).embed(&container, message).unwrap();
+        assert!(Extractor::from_key_spec(&other.key, None).unwrap().method(method).extract(&requantize_16bit(&embedded.amplitudes)).is_err());
+    }
+
+    #[test]
+    fn test_capacity_and_plan_respect_min_chips_per_bit() {
+        let container = quiet_stereo(100_000);
+        let embedder = Embedder::new().min_chips_per_bit(100);
+
+        // На бит не меньше 100 отсчетов первого канала: 100_000 / (преамбула + кадр) >= 100
+        let capacity = embedder.capacity(&container).unwrap();
+        assert_eq!(capacity.frame_bits, 1000 - PREAMBLE_BITS);
+        assert_eq!(capacity.payload_bytes, (capacity.frame_bits - HEADER_BITS) / 8);
+
+        let message = vec![b'a'; capacity.payload_bytes];
+        let plan = embedder.plan(&container, &message).unwrap();
+        assert!(plan.frame_bits <= capacity.frame_bits);
+        assert!(plan.chips_per_bit.unwrap() >= 100);
+        assert_eq!(plan.processing_gain_db(), Some(10.0 * (plan.key_len as f64).log10()));
+        assert_eq!(embedder.clone().seed(1).embed(&container, &message).unwrap().psp.len(), plan.key_len);
+
+        // Без ограничения то же сообщение встраивается с большим количеством отсчетов на бит
+        assert!(Embedder::new().plan(&container, &message).unwrap().key_len >= plan.key_len);
+
+        let longer = vec![b'a'; capacity.payload_bytes + 1];
+        assert!(matches!(
+            embedder.plan(&container, &longer),
+            Err(StegoError::ContainerTooSmall { required, available }) if required == plan.frame_bits + 8 && available == capacity.frame_bits
+        ));
+        assert!(matches!(embedder.embed(&container, &longer), Err(StegoError::ContainerTooSmall { .. })));
+
+        // Шифрование добавляет соль, nonce и тег
+        let encrypted = embedder.clone().payload_passphrase("пароль").capacity(&container).unwrap();
+        assert_eq!(encrypted.payload_bytes, capacity.payload_bytes - ENVELOPE_OVERHEAD);
+
+        // Младшие биты не распределяются по отсчетам, поэтому ограничение к ним не применяется
+        let lsb = embedder.method(Arc::new(LeastSignificantBits { sample_bits: 16, depth: 1 }));
+        assert_eq!(lsb.capacity(&container).unwrap().frame_bits, 200_000);
+        assert_eq!(lsb.plan(&container, b"lsb").unwrap().chips_per_bit, None);
+    }
+
+    #[test]
+    fn test_fixed_chips_per_bit_and_repeated_frame() {
+        let container = quiet_stereo(100_000);
+        let message = "Копии кадра складываются при извлечении".as_bytes();
+
+        // Кадр с постоянным количеством отсчетов на бит занимает только начало контейнера
+        let once = Embedder::new().chips_per_bit(20).seed(3).embed(&container, message).unwrap();
+        let plan = Embedder::new().chips_per_bit(20).plan(&container, message).unwrap();
+        assert_eq!((once.psp.len(), plan.copies), (20, 1));
+        let used = (PREAMBLE_BITS + plan.frame_bits) * 20;
+        assert_eq!(once.amplitudes[0][used..], container[0][used..]);
+
+        // С повторением копии кадра занимают весь контейнер
+        let repeated = Embedder::new().chips_per_bit(20).repeat();
+        let plan = repeated.plan(&container, message).unwrap();
+        // Последняя копия неполная, но в ней есть биты сообщения после заголовка
+        assert_eq!(plan.copies, (100_000 / 20 - PREAMBLE_BITS - HEADER_BITS).div_ceil(plan.frame_bits));
+        let embedded = repeated.seed(3).embed(&container, message).unwrap();
+        assert_ne!(embedded.amplitudes[0][used..], container[0][used..]);
+
+        // Равномерный шум, при котором одна копия кадра уже не читается, а сумма копий читается
+        let noisy = |amplitudes: &[Vec<f64>]| -> Vec<Vec<f64>> {
+            amplitudes.iter()
+                .map(|channel| channel.iter().zip(noise(channel.len(), 7)).map(|(x, n)| x + 0.007 * n).collect())
+                .collect()
+        };
+        let extract = |embedded: &Embedded| Extractor::from_key_spec(&embedded.key, None).unwrap()
+            .container(&container)
+            .extract(&noisy(&embedded.amplitudes));
+
+        assert!(extract(&once).map_or(true, |extracted| extracted.message != message));
+        let extracted = extract(&embedded).unwrap();
+        assert_eq!(extracted.message, message);
+        assert_eq!(extracted.copies, plan.copies);
+    }
+
+    #[test]
+    fn test_rejects_channels_of_different_length() {
+        let mut container = quiet_stereo(100_000);
+        container[1].truncate(100);
+        let embedder = Embedder::new().channel_mode(ChannelMode::All);
+
+        assert!(matches!(embedder.capacity(&container), Err(StegoError::InvalidArgument(_))));
+        assert!(matches!(embedder.plan(&container, b"Hi"), Err(StegoError::InvalidArgument(_))));
+        assert!(matches!(embedder.embed(&container, b"Hi"), Err(StegoError::InvalidArgument(_))));
+
+        let embedded = embedder.seed(1).embed(&quiet_stereo(100_000), b"Hi").unwrap();
+        let extractor = Extractor::from_key_spec(&embedded.key, None).unwrap();
+        assert!(matches!(extractor.extract(&container), Err(StegoError::InvalidArgument(_))));
+        assert!(matches!(extractor.container(&container).extract(&embedded.amplitudes), Err(StegoError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_embed_rejects_small_container() {
+        let container = quiet_stereo(100);
+
+        let result = Embedder::new().embed(&container, b"too long for this container");
+        assert!(matches!(result, Err(StegoError::ContainerTooSmall { available: 87, .. })));
+
+        let result = Embedder::new().embed(&container, &[0xff, 0xfe]);
+        assert!(matches!(result, Err(StegoError::InvalidMessage(_))));
+    }
+}
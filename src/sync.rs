@@ -0,0 +1,198 @@
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+
+// Синхронизация: перед заголовком (или сообщением) встраивается преамбула - код Баркера
+// длины 13, расширенный той же ПСП. При извлечении преамбула ищется скользящей
+// корреляцией по всему потоку, поэтому обрезка начала файла или задержка не сбивают
+// границы сегментов.
+
+pub const PREAMBLE: [bool; 13] = [
+    true, true, true, true, true, false, false, true, true, false, true, false, true,
+];
+pub const PREAMBLE_BITS: usize = PREAMBLE.len();
+
+// Начало кадра в потоке отсчетов (может быть отрицательным, если начало обрезано)
+// и коэффициент корреляции с преамбулой: 1 - поток в окне совпадает с преамбулой
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncPoint {
+    pub offset: isize,
+    pub peak: f64,
+}
+
+// Преамбула, расширенная ПСП: samples_per_msg_bit отсчетов на бит
+pub fn preamble_template(key: &[i16], samples_per_msg_bit: usize) -> Vec<f64> {
+    PREAMBLE.iter()
+        .flat_map(|&bit| {
+            let sign = if bit { 1.0 } else { -1.0 };
+            key.iter().take(samples_per_msg_bit).map(move |&c| sign * c as f64)
+        })
+        .collect()
+}
+
+// Положение преамбулы в потоке решений (остаток после вычитания контейнера или сам
+// стегаконтейнер при слепом извлечении) - максимум скользящей корреляции, нормированной
+// на энергию потока в окне. Без нормировки громкие участки перевешивали бы преамбулу.
+pub fn find_preamble(decision: &[f64], key: &[i16], samples_per_msg_bit: usize) -> SyncPoint {
+    let template = preamble_template(key, samples_per_msg_bit);
+    let correlation = cross_correlation(decision, &template);
+    let first_lag = 1 - template.len() as isize;
+    let template_energy = template.len() as f64;
+    let energy = prefix_energy(decision);
+
+    correlation.iter().enumerate()
+        .map(|(i, &c)| {
+            let offset = first_lag + i as isize;
+            let start = offset.clamp(0, decision.len() as isize) as usize;
+            let end = (offset + template.len() as isize).clamp(0, decision.len() as isize) as usize;
+            let window_energy = energy[end] - energy[start];
+            let peak = if window_energy > 0.0 { c / (window_energy * template_energy).sqrt() } else { 0.0 };
+            SyncPoint { offset, peak }
+        })
+        .max_by(|a, b| a.peak.total_cmp(&b.peak))
+        .unwrap_or(SyncPoint { offset: 0, peak: 0.0 })
+}
+
+// Накопленная энергия: energy[j] - сумма квадратов первых j отсчетов
+fn prefix_energy(x: &[f64]) -> Vec<f64> {
+    std::iter::once(0.0)
+        .chain(x.iter().scan(0.0, |acc, v| {
+            *acc += v * v;
+            Some(*acc)
+        }))
+        .collect()
+}
+
+// Поиск преамбулы при наличии исходного контейнера. Кадр встраивается с начала контейнера,
+// поэтому начало кадра в стегаконтейнере совпадает с его задержкой delay, и для каждой
+// задержки достаточно проверить остаток (x - y) / (y + 2) в окне преамбулы:
+//   сумма t[j] * (x[j + delay] - y[j]) / (y[j] + 2) по отсчетам j, где x[j + delay] существует.
+// Все суммы раскладываются во взаимные корреляции, поэтому перебор задержек занимает
+// несколько БПФ. Сравнение самих сигналов здесь не подходит: у периодического контейнера
+// задержки на целое число периодов почти неразличимы.
+pub fn find_preamble_informed(container: &[f64], stego: &[f64], key: &[i16], samples_per_msg_bit: usize) -> SyncPoint {
+    let template = preamble_template(key, samples_per_msg_bit);
+    let window = template.len().min(container.len());
+    let weight: Vec<f64> = container[..window].iter().map(|y| 1.0 / (y + 2.0)).collect();
+    let y = &container[..window];
+
+    let product = |f: &dyn Fn(usize) -> f64| -> Vec<f64> { (0..window).map(f).collect() };
+    let weighted_template = product(&|j| template[j] * weight[j]);
+    let weighted_template_container = product(&|j| template[j] * weight[j] * y[j]);
+    let weight_sq = product(&|j| weight[j] * weight[j]);
+    let weight_sq_container = product(&|j| weight[j] * weight[j] * y[j]);
+    let weight_sq_container_sq = product(&|j| (weight[j] * y[j]).powi(2));
+
+    let stego_sq: Vec<f64> = stego.iter().map(|x| x * x).collect();
+    let present = vec![1.0; stego.len()];
+
+    let correlation = cross_correlation(stego, &weighted_template);
+    let correction = cross_correlation(&present, &weighted_template_container);
+    let energy_x = cross_correlation(&stego_sq, &weight_sq);
+    let energy_xy = cross_correlation(stego, &weight_sq_container);
+    let energy_y = cross_correlation(&present, &weight_sq_container_sq);
+    let first_lag = 1 - window as isize;
+    let template_energy = template.len() as f64;
+
+    (0..correlation.len())
+        .map(|i| {
+            let c = correlation[i] - correction[i];
+            let energy = energy_x[i] - 2.0 * energy_xy[i] + energy_y[i];
+            let peak = if energy > 0.0 { c / (energy * template_energy).sqrt() } else { 0.0 };
+            SyncPoint { offset: first_lag + i as isize, peak }
+        })
+        .max_by(|a, b| a.peak.total_cmp(&b.peak))
+        .unwrap_or(SyncPoint { offset: 0, peak: 0.0 })
+}
+
+// Взаимная корреляция через БПФ: элемент с индексом lag + b.len() - 1 равен
+// сумме a[j + lag] * b[j], lag от -(b.len() - 1) до a.len() - 1
+fn cross_correlation(a: &[f64], b: &[f64]) -> Vec<f64> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+
+    let size = (a.len() + b.len()).next_power_of_two();
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(size);
+    let ifft = planner.plan_fft_inverse(size);
+
+    let spectrum = |x: &[f64]| -> Vec<Complex<f64>> {
+        let mut buffer: Vec<Complex<f64>> = x.iter().map(|&v| Complex::new(v, 0.0)).collect();
+        buffer.resize(size, Complex::new(0.0, 0.0));
+        fft.process(&mut buffer);
+        buffer
+    };
+
+    let mut product: Vec<Complex<f64>> = spectrum(a).iter().zip(spectrum(b)).map(|(x, y)| x * y.conj()).collect();
+    ifft.process(&mut product);
+
+    (1 - b.len() as isize..a.len() as isize)
+        .map(|lag| product[lag.rem_euclid(size as isize) as usize].re / size as f64)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signals::sine;
+
+    #[test]
+    fn test_cross_correlation_matches_direct_sum() {
+        let a = [1.0, -2.0, 3.0, 0.5];
+        let b = [0.5, 1.0];
+
+        let direct: Vec<f64> = (-1..4isize)
+            .map(|lag| (0..b.len())
+                .filter_map(|j| a.get((j as isize + lag) as usize).filter(|_| j as isize + lag >= 0).map(|x| x * b[j]))
+                .sum())
+            .collect();
+        let fast = cross_correlation(&a, &b);
+
+        assert_eq!(fast.len(), direct.len());
+        for (x, y) in fast.iter().zip(&direct) {
+            assert!((x - y).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_preamble_is_found_at_offset() {
+        let key = [1, -1, -1, 1, 1, 1, -1, 1];
+        let template = preamble_template(&key, key.len());
+
+        for offset in [0isize, 37, -20] {
+            let mut stream = vec![0.0; 300];
+            for (i, t) in template.iter().enumerate() {
+                let position = offset + i as isize;
+                if position >= 0 {
+                    stream[position as usize] += 0.01 * t;
+                }
+            }
+
+            let sync = find_preamble(&stream, &key, key.len());
+            assert_eq!(sync.offset, offset);
+            assert!(sync.peak > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_informed_search_resolves_periodic_container() {
+        let key: Vec<i16> = (0..16).map(|i| if (i * 5 + 3) % 7 < 3 { 1 } else { -1 }).collect();
+        let template = preamble_template(&key, key.len());
+        // Чистая синусоида: задержки на период отличаются меньше, чем вложенная ПСП
+        let container = sine(440.0, 0.3, 2000);
+        let stego: Vec<f64> = container.iter().enumerate()
+            .map(|(t, &y)| y + template.get(t).map_or(0.0, |c| 0.0005 * c * (y + 2.0)))
+            .collect();
+
+        for delay in [0isize, 150, -100] {
+            let shifted: Vec<f64> = (0..stego.len() as isize)
+                .map(|j| if j - delay >= 0 { stego.get((j - delay) as usize).copied().unwrap_or(0.0) } else { 0.0 })
+                .collect();
+
+            let sync = find_preamble_informed(&container, &shifted, &key, key.len());
+            assert_eq!(sync.offset, delay);
+            // При обрезанном начале часть преамбулы потеряна, и пик ниже
+            assert!(sync.peak > if delay < 0 { 0.5 } else { 0.99 });
+        }
+    }
+}
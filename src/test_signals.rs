@@ -0,0 +1,41 @@
+// Детерминированные сигналы для тестов: одни и те же от запуска к запуску и во всех модулях
+
+use std::f64::consts::PI;
+
+// Равномерный шум в [-0.5, 0.5) от линейного конгруэнтного генератора (константы ANSI C)
+pub fn noise(len: usize, seed: u32) -> Vec<f64> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 8) as f64 / (1u32 << 24) as f64 - 0.5
+        })
+        .collect()
+}
+
+// Последовательность ±1 из того же генератора
+pub fn chips(len: usize, seed: u32) -> Vec<f64> {
+    noise(len, seed).iter().map(|v| v.signum()).collect()
+}
+
+// Синусоида частоты freq Гц при 44.1 кГц
+pub fn sine(freq: f64, amplitude: f64, len: usize) -> Vec<f64> {
+    (0..len).map(|t| amplitude * (2.0 * PI * freq * t as f64 / 44100.0).sin()).collect()
+}
+
+// Два тона вместо музыки: 220 Гц и 1375 Гц, пик около 0.3
+pub fn music(len: usize) -> Vec<f64> {
+    sine(220.0, 0.2, len).iter().zip(sine(1375.0, 0.1, len)).map(|(a, b)| a + b).collect()
+}
+
+// Биты кадра без длинных серий одинаковых значений
+pub fn bits(len: usize) -> Vec<bool> {
+    (0..len).map(|i| (i * 7 + i / 3) % 5 < 2).collect()
+}
+
+// Повторное квантование в 16 бит, как при сохранении WAV-файла
+pub fn requantize_16bit(channels: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    channels.iter()
+        .map(|channel| channel.iter().map(|x| (x * 32768.0).round().clamp(-32768.0, 32767.0) / 32768.0).collect())
+        .collect()
+}
@@ -0,0 +1,230 @@
+use std::f64::consts::PI;
+use std::ops::Range;
+use std::sync::Arc;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use crate::spectrum::{self, DEFAULT_BAND, DEFAULT_FFT_STRENGTH};
+use crate::st::DEFAULT_STRENGTH;
+
+// Встраивание в области MDCT. Перцептивные кодеки (MP3, AAC, Opus) квантуют коэффициенты
+// MDCT и отбрасывают все, что ниже порога маскирования, поэтому ПСП во временной области
+// с амплитудой порядка 5e-4 после сжатия пропадает. Здесь биты расширяются по коэффициентам
+// средних частот, которые кодеки сохраняют, а изменение пропорционально модулю коэффициента:
+// c + strength * chip * |c|, то есть водяной знак повторяет спектр контейнера.
+//
+// Кадры по 2 * HOP отсчетов с синусным окном сдвигаются на HOP. С таким окном и нормировкой
+// преобразование ортогонально: измененные коэффициенты после синтеза и повторного анализа
+// получаются обратно без потерь.
+//
+// Сдвиг сигнала на долю кадра меняет все коэффициенты, поэтому преамбула в этой области
+// находится только при сдвиге на целое число кадров (кодеки с компенсацией задержки его сохраняют).
+
+pub const HOP: usize = 1024;
+// Средние частоты: от 1/16 до 1/4 полосы (около 1.4-5.5 кГц при 44.1 кГц)
+const BAND: Range<usize> = HOP / 16..HOP / 4;
+
+// Сила встраивания по умолчанию в области MDCT: доля модуля коэффициента
+pub const DEFAULT_MDCT_STRENGTH: f64 = 0.1;
+
+// Область, в которой биты расширяются по ПСП
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Domain {
+    // Отсчеты сигнала
+    Time,
+    // Коэффициенты MDCT средних частот
+    Mdct,
+    // Модули бинов БПФ в полосе low..high, заданной в долях частоты дискретизации (см. spectrum)
+    Fft { low: f64, high: f64 },
+}
+
+impl Domain {
+    pub const DOMAINS: [Domain; 3] = [Domain::Time, Domain::Mdct, Domain::Fft { low: DEFAULT_BAND.0, high: DEFAULT_BAND.1 }];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Domain::Time => "time",
+            Domain::Mdct => "mdct",
+            Domain::Fft { .. } => "fft",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Domain::DOMAINS.into_iter().find(|domain| domain.name() == name)
+    }
+
+    pub fn default_strength(&self) -> f64 {
+        match self {
+            Domain::Time => DEFAULT_STRENGTH,
+            Domain::Mdct => DEFAULT_MDCT_STRENGTH,
+            Domain::Fft { .. } => DEFAULT_FFT_STRENGTH,
+        }
+    }
+
+    // Количество позиций для расширения бит в потоке из len отсчетов
+    pub fn capacity(&self, len: usize) -> usize {
+        match self {
+            Domain::Time => len,
+            Domain::Mdct => frame_count(len, HOP) * BAND.len(),
+            Domain::Fft { low, high } => spectrum::capacity(len, *low, *high),
+        }
+    }
+
+    // Позиции, по которым расширяются биты: сами отсчеты, коэффициенты средних частот
+    // или модули бинов полосы всех кадров подряд (порядок бинов задает ключ)
+    pub fn coefficients(&self, stream: &[f64], key: &[i16]) -> Vec<f64> {
+        match self {
+            Domain::Time => stream.to_vec(),
+            Domain::Mdct => analyze(stream, HOP).into_iter().flat_map(|frame| frame[BAND].to_vec()).collect(),
+            Domain::Fft { low, high } => spectrum::magnitudes(stream, *low, *high, key),
+        }
+    }
+}
+
+// Добавляет к коэффициентам средних частот spread (±1 на позициях бит, 0 вне сообщения),
+// умноженную на strength и модуль коэффициента
+pub fn embed_watermark(stream: &[f64], spread: &[f64], strength: f64) -> Vec<f64> {
+    let delta: Vec<Vec<f64>> = analyze(stream, HOP).iter()
+        .zip(spread.chunks(BAND.len()))
+        .map(|(frame, chips)| {
+            let mut delta = vec![0.0; HOP];
+            for (k, chip) in BAND.zip(chips) {
+                delta[k] = strength * chip * frame[k].abs();
+            }
+            delta
+        })
+        .collect();
+
+    let watermark = synthesize(&delta, HOP, stream.len());
+    stream.iter().zip(watermark).map(|(x, w)| x + w).collect()
+}
+
+// Кадры, целиком лежащие внутри сигнала: кадр f занимает отсчеты f * hop..(f + 2) * hop
+fn frame_count(len: usize, hop: usize) -> usize {
+    (len / hop).saturating_sub(1)
+}
+
+// Коэффициенты MDCT всех кадров сигнала
+pub fn analyze(x: &[f64], hop: usize) -> Vec<Vec<f64>> {
+    let mdct = Mdct::new(hop);
+    (0..frame_count(x.len(), hop))
+        .map(|f| mdct.forward(&x[f * hop..(f + 2) * hop]))
+        .collect()
+}
+
+// Обратное преобразование и сложение кадров с перекрытием (TDAC): первая половина
+// первого кадра и вторая половина последнего восстанавливаются только вместе с соседями
+pub fn synthesize(frames: &[Vec<f64>], hop: usize, len: usize) -> Vec<f64> {
+    let mdct = Mdct::new(hop);
+    let mut y = vec![0.0; len];
+    for (f, coefficients) in frames.iter().enumerate() {
+        for (sample, value) in y.iter_mut().skip(f * hop).zip(mdct.inverse(coefficients)) {
+            *sample += value;
+        }
+    }
+    y
+}
+
+// MDCT кадра из 2N отсчетов через БПФ длины 2N:
+//   X[k] = sqrt(2/N) * сумма w[n] x[n] cos(pi/N (n + 1/2 + N/2)(k + 1/2))
+struct Mdct {
+    hop: usize,
+    window: Vec<f64>,
+    fft: Arc<dyn Fft<f64>>,
+    ifft: Arc<dyn Fft<f64>>,
+}
+
+impl Mdct {
+    fn new(hop: usize) -> Self {
+        let mut planner = FftPlanner::new();
+        Mdct {
+            hop,
+            // Синусное окно: w[n]^2 + w[n + N]^2 = 1 (условие Принсена-Брэдли)
+            window: (0..2 * hop).map(|n| (PI * (n as f64 + 0.5) / (2 * hop) as f64).sin()).collect(),
+            fft: planner.plan_fft_forward(2 * hop),
+            ifft: planner.plan_fft_inverse(2 * hop),
+        }
+    }
+
+    // Фаза pi/N (n + n0)(k + 1/2), разложенная на множители до и после БПФ
+    fn shift(&self) -> f64 {
+        0.5 + self.hop as f64 / 2.0
+    }
+
+    fn forward(&self, x: &[f64]) -> Vec<f64> {
+        let n = self.hop as f64;
+        let mut buffer: Vec<Complex<f64>> = x.iter().zip(&self.window).enumerate()
+            .map(|(i, (v, w))| Complex::from_polar(v * w, -PI * i as f64 / (2.0 * n)))
+            .collect();
+        self.fft.process(&mut buffer);
+
+        let scale = (2.0 / n).sqrt();
+        (0..self.hop)
+            .map(|k| scale * (buffer[k] * Complex::from_polar(1.0, -PI * self.shift() * (k as f64 + 0.5) / n)).re)
+            .collect()
+    }
+
+    fn inverse(&self, coefficients: &[f64]) -> Vec<f64> {
+        let n = self.hop as f64;
+        let mut buffer: Vec<Complex<f64>> = coefficients.iter().enumerate()
+            .map(|(k, &c)| Complex::from_polar(c, PI * self.shift() * k as f64 / n))
+            .collect();
+        buffer.resize(2 * self.hop, Complex::new(0.0, 0.0));
+        self.ifft.process(&mut buffer);
+
+        let scale = (2.0 / n).sqrt();
+        buffer.iter().zip(&self.window).enumerate()
+            .map(|(i, (b, w))| scale * w * (b * Complex::from_polar(1.0, PI * (i as f64 + self.shift()) / (2.0 * n))).re)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signals::noise;
+
+    #[test]
+    fn test_mdct_matches_direct_sum() {
+        let hop = 8;
+        let x = noise(2 * hop, 1);
+        let fast = Mdct::new(hop).forward(&x);
+
+        for (k, value) in fast.iter().enumerate() {
+            let direct: f64 = (0..2 * hop)
+                .map(|n| {
+                    let w = (PI * (n as f64 + 0.5) / (2 * hop) as f64).sin();
+                    let phase = PI / hop as f64 * (n as f64 + 0.5 + hop as f64 / 2.0) * (k as f64 + 0.5);
+                    w * x[n] * phase.cos()
+                })
+                .sum::<f64>() * (2.0 / hop as f64).sqrt();
+            assert!((value - direct).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_overlap_add_reconstructs_inner_samples() {
+        let hop = 64;
+        let x = noise(10 * hop, 2);
+        let y = synthesize(&analyze(&x, hop), hop, x.len());
+
+        // Крайние полукадры покрывает только одно окно
+        for i in hop..x.len() - hop {
+            assert!((x[i] - y[i]).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_modified_coefficients_survive_resynthesis() {
+        let stream = noise(8 * HOP, 3);
+        let spread: Vec<f64> = noise(Domain::Mdct.capacity(stream.len()), 4).iter().map(|v| v.signum()).collect();
+
+        let stego = embed_watermark(&stream, &spread, DEFAULT_MDCT_STRENGTH);
+        let original = Domain::Mdct.coefficients(&stream, &[]);
+        let embedded = Domain::Mdct.coefficients(&stego, &[]);
+
+        assert_eq!(embedded.len(), spread.len());
+        for ((c, e), chip) in original.iter().zip(&embedded).zip(&spread) {
+            assert!((e - c - DEFAULT_MDCT_STRENGTH * chip * c.abs()).abs() < 1e-9);
+        }
+    }
+}
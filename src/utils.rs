@@ -2,283 +2,784 @@ use std::fs::File;
 use std::io;
 use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::path::Path;
-use clap::{Arg, ArgAction, ArgMatches, Command, Error, ArgGroup};
+use std::sync::Arc;
+use clap::{Arg, ArgAction, ArgMatches, Command, Error};
 use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
 use plotters::prelude::*;
+use lab_3::attack::{Attack, DEFAULT_ATTACKS};
+use lab_3::bench::{to_csv, BenchRow};
+use lab_3::channels::ChannelMode;
+use lab_3::dsss::{SpreadSpectrum, DSSS_ID};
+use lab_3::echo::{EchoHiding, DEFAULT_DECAY, DEFAULT_DELAYS, ECHO_ID};
+use lab_3::hmac_prng::{HMAC_SHA256_ID, SALT_LEN};
+use lab_3::error::StegoError;
+use lab_3::key::KeySpec;
+use lab_3::lsb::{LeastSignificantBits, LSB_ID};
+use lab_3::method::{self, StegoMethod, METHOD_IDS};
+use lab_3::quality::QualityReport;
+use lab_3::park_miller_prng::{MAX_SEED, PARK_MILLER_ID};
+use lab_3::phase::{PhaseCoding, PHASE_ID};
+use lab_3::qim::{QuantizationIndexModulation, DEFAULT_STEP, QIM_ID};
+use lab_3::st::Gain;
+use lab_3::transform::Domain;
+
+const EXIT_CODES_HELP: &str = "Коды завершения:
+  0  успешно
+  2  ошибка в аргументах или не указан пароль
+  3  ошибка ввода-вывода
+  4  некорректный файл ключа
+  5  неподдерживаемый или поврежденный WAV-файл
+  6  контейнер слишком мал для сообщения
+  7  длины контейнера и стегаконтейнера не совпадают
+  8  заголовок не найден или поврежден
+  9  контрольная сумма не совпадает или ошибки не исправлены
+  10 ошибка расшифрования сообщения
+  11 сообщение не является текстом UTF-8
+  12 встроенное сообщение не извлекается обратно (файлы не записаны)";
 
 pub fn init_cli() -> Result<ArgMatches, Error> {
-    let ret = Command::new("Steganography third lab")
-        .arg(
-            Arg::new("encrypt")
-                .help("Кодирование сообщения")
-                .long("encrypt")
-                .action(ArgAction::SetTrue),
-        )
-        .arg(
-            Arg::new("decrypt")
-                .help("Декодирование сообщения")
-                .long("decrypt")
-                .action(ArgAction::SetTrue),
-        )
-        .group(
-            ArgGroup::new("mode")
-                .args(&["encrypt", "decrypt", "generate-wav"])
-                .required(true)
-                .multiple(false),
-        )
-        .arg(
-            Arg::new("generate-wav")
-                .help("Генерирование WAV-файла с указанными параметрами")
-                .long("generate-wav")
-                .short('g')
-                .action(ArgAction::SetTrue),
-        )
-        .arg(
-            Arg::new("duration")
-                .help("Длина генерируемого WAV-файла")
-                .long("duration")
-                .short('d')
-                .action(ArgAction::Set)
-                .value_parser(clap::value_parser!(f32))
-        )
-        .arg(
-            Arg::new("channels")
-                .help("Количество каналов генерируемого WAV-файла (1 - моно, 2 - стерео)")
-                .long("channels")
-                .action(ArgAction::Set)
-                .value_parser(clap::value_parser!(u16)),
-        )
-        .arg(
-            Arg::new("sample-rate")
-                .help("Частота дискретизации генерируемого WAV-файла")
-                .long("sample-rate")
-                .short('r')
-                .action(ArgAction::Set)
-                .default_value("44100")
-                .value_parser(clap::value_parser!(u32))
-        )
-        .arg(
-            Arg::new("name")
-                .help("Имя генерируемого WAV-файла")
-                .long("name")
-                .short('n')
-                .action(ArgAction::Set)
+    cli().try_get_matches()
+}
+
+pub fn cli() -> Command {
+    Command::new("Steganography third lab")
+        .after_help(EXIT_CODES_HELP)
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommand(
+            Command::new("embed")
+                .about("Встраивание сообщения (-m) в контейнер (-c); ключ для извлечения сохраняется в -k")
+                .arg(container_arg())
+                .arg(stegacontainer_arg())
+                .arg(message_arg("Путь до файла с сообщением"))
+                .arg(key_arg("Путь, по которому сохраняется ключ"))
+                .args(method_args())
+                .args(stream_args())
+                .args(passphrase_args())
+                .arg(
+                    Arg::new("seed")
+                        .help("Зерно генератора park-miller (по умолчанию берется из текущего времени)")
+                        .long("seed")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(u32).range(..=MAX_SEED as i64))
+                )
+                .arg(
+                    Arg::new("generator")
+                        .help("Генератор ПСП: park-miller - линейный конгруэнтный, hmac-sha256 - криптостойкий, по паролю")
+                        .long("generator")
+                        .action(ArgAction::Set)
+                        .value_parser([PARK_MILLER_ID, HMAC_SHA256_ID])
+                        .default_value(PARK_MILLER_ID)
+                )
+                .arg(
+                    Arg::new("key-format")
+                        .help("Формат файла ключа: seed - генератор, зерно и длина ПСП; chips - ПСП целиком (только для park-miller)")
+                        .long("key-format")
+                        .action(ArgAction::Set)
+                        .value_parser(["seed", "chips"])
+                        .default_value("seed")
+                )
+                .args(fec_args())
+                .arg(no_header_arg())
+                .args(chips_args())
+                .arg(
+                    Arg::new("quality-report")
+                        .help("Сохранить оценку искажений стегаконтейнера в JSON-файл")
+                        .long("quality-report")
+                        .action(ArgAction::Set)
+                )
         )
-        .group(
-            ArgGroup::new("wav-generation")
-                .args(&["generate-wav", "duration", "channels", "name", "channels", "sample-rate"])
-                .multiple(true)
-                .requires_all(&["duration", "channels", "name", "sample-rate"]),
+        .subcommand(
+            Command::new("extract")
+                .about("Извлечение сообщения из стегаконтейнера (-s) по ключу (-k) и сохранение в -m")
+                .arg(container_arg())
+                .arg(stegacontainer_arg())
+                .arg(message_arg("Путь, по которому сохраняется извлеченное сообщение"))
+                .arg(key_arg("Путь до файла ключа"))
+                .args(stream_args())
+                .args(passphrase_args())
+                .arg(
+                    Arg::new("blind")
+                        .help("Слепое извлечение сообщения без исходного контейнера (только по стегаконтейнеру и ключу)")
+                        .long("blind")
+                        .action(ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("bits-per-char")
+                        .help("Количество бит на символ вытаскиваемого сообщения")
+                        .long("bits-per-char")
+                        .short('b')
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(usize))
+                        .requires("message-len")
+                )
+                .arg(
+                    Arg::new("message-len")
+                        .help("Длина вытаскиваемого сообщения")
+                        .long("message-len")
+                        .short('l')
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(usize))
+                        .requires("bits-per-char")
+                )
         )
-        .arg(
-            Arg::new("container")
-                .help("Путь до контейнера WAV-формата")
-                .long("container")
-                .short('c')
-                .default_value("container.wav")
-                .action(ArgAction::Set),
+        .subcommand(
+            Command::new("generate")
+                .about("Генерирование WAV-файла: в каждом канале синусоида своей частоты (440 Гц, 880 Гц, ...)")
+                .arg(
+                    Arg::new("name")
+                        .help("Имя генерируемого WAV-файла")
+                        .long("name")
+                        .short('n')
+                        .action(ArgAction::Set)
+                        .required(true)
+                )
+                .arg(
+                    Arg::new("duration")
+                        .help("Длина генерируемого WAV-файла в секундах")
+                        .long("duration")
+                        .short('d')
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(f32))
+                        .required(true)
+                )
+                .arg(
+                    Arg::new("channels")
+                        .help("Количество каналов генерируемого WAV-файла (1 - моно, 2 - стерео)")
+                        .long("channels")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(u16).range(1..))
+                        .default_value("1")
+                )
+                .arg(
+                    Arg::new("sample-rate")
+                        .help("Частота дискретизации генерируемого WAV-файла")
+                        .long("sample-rate")
+                        .short('r')
+                        .action(ArgAction::Set)
+                        .default_value("44100")
+                        .value_parser(clap::value_parser!(u32).range(1..))
+                )
         )
-        .arg(
-            Arg::new("stegacontainer")
-                .help("Путь до стегаконтейнера в WAV-формате")
-                .long("stegacontainer")
-                .short('s')
-                .default_value("stegacontainer.wav")
-                .action(ArgAction::Set),
+        .subcommand(
+            Command::new("info")
+                .about("Формат, количество каналов и длительность WAV-файла")
+                .arg(wav_arg())
         )
-        .arg(
-            Arg::new("message")
-                .help("Путь до файла с сообщением")
-                .long("message")
-                .short('m')
-                .default_value("message.txt")
-                .action(ArgAction::Set),
+        .subcommand(
+            Command::new("capacity")
+                .about("Емкость контейнера (-c) при выбранных алгоритме, коде и шифровании, ничего не записывая. С -m - сколько позиций контейнера придется на бит этого сообщения")
+                .arg(container_arg())
+                .arg(
+                    Arg::new("message")
+                        .help("Путь до файла с сообщением, для которого рассчитывается встраивание")
+                        .long("message")
+                        .short('m')
+                        .action(ArgAction::Set)
+                )
+                .args(method_args())
+                .args(stream_args())
+                .args(fec_args())
+                .arg(no_header_arg())
+                .args(chips_args())
+                .arg(payload_passphrase_arg())
         )
-        .arg(
-            Arg::new("key")
-                .long("key")
-                .short('k')
-                .action(ArgAction::Set)
-                .default_value("key.csv")
-                .requires("decrypt")
+        .subcommand(
+            Command::new("plot")
+                .about("График амплитуд WAV-файла")
+                .arg(wav_arg())
+                .arg(
+                    Arg::new("output")
+                        .help("Путь для графика в PNG (по умолчанию имя WAV-файла с расширением png)")
+                        .long("output")
+                        .short('o')
+                        .action(ArgAction::Set)
+                )
         )
-        .arg(
-            Arg::new("bits-per-char")
-                .help("Количество бит на символ вытаскиваемого сообщения")
-                .long("bits-per-char")
-                .short('b')
-                .action(ArgAction::Set)
-                .value_parser(clap::value_parser!(usize))
-                .requires("decrypt")
+        .subcommand(
+            Command::new("attack")
+                .about("Искажение стегаконтейнера (-s) и сохранение результата в --output")
+                .arg(
+                    Arg::new("attack")
+                        .help(ATTACKS_HELP)
+                        .value_name("ATTACK")
+                        .action(ArgAction::Set)
+                        .required(true)
+                )
+                .arg(stegacontainer_arg())
+                .arg(
+                    Arg::new("output")
+                        .help("Путь для искаженного стегаконтейнера")
+                        .long("output")
+                        .short('o')
+                        .default_value("attacked.wav")
+                        .action(ArgAction::Set)
+                )
         )
-        .arg(
-            Arg::new("message-len")
-                .help("Длина вытаскиваемого сообщения")
-                .long("message-len")
-                .short('l')
-                .action(ArgAction::Set)
-                .value_parser(clap::value_parser!(usize))
-                .requires("decrypt")
+        .subcommand(
+            Command::new("bench")
+                .about("Проверка устойчивости: встраивание сообщения (-m) в контейнер (-c), атаки и доля ошибочных бит при извлечении")
+                .arg(container_arg())
+                .arg(message_arg("Путь до файла с сообщением"))
+                .args(method_args())
+                .args(stream_args())
+                .arg(
+                    Arg::new("bench-attack")
+                        .help(format!("Атака (можно указать несколько раз). По умолчанию - стандартный набор. {}", ATTACKS_HELP))
+                        .long("bench-attack")
+                        .action(ArgAction::Append)
+                )
+                .arg(
+                    Arg::new("bench-strength")
+                        .help("Сила встраивания (можно указать несколько раз). По умолчанию 0.0005, 0.002, 0.005 для --domain time, 0.05, 0.1, 0.2 для --domain mdct и 0.1, 0.2, 0.4 для --domain fft. Для --method lsb сила - количество младших бит (по умолчанию 1, 2, 4), для --method qim - шаг квантователя (по умолчанию 0.002, 0.005, 0.01)")
+                        .long("bench-strength")
+                        .action(ArgAction::Append)
+                        .value_parser(clap::value_parser!(f64))
+                )
+                .arg(
+                    Arg::new("bench-csv")
+                        .help("Сохранить результаты в CSV-файл")
+                        .long("bench-csv")
+                        .action(ArgAction::Set)
+                )
         )
-        .try_get_matches();
-    ret
 }
 
-fn read_file(file: Result<File, io::Error>, buffer: &mut Vec<u8>) -> Result<(), Box<dyn std::error::Error>> {
-    let mut file = file.map_err(|e| {
-        eprintln!("Ошибка при чтении файла: {}", e);
-        Box::new(e) as Box<dyn std::error::Error>
-    })?;
+const ATTACKS_HELP: &str = "Атаки: noise:<ОСШ дБ>, requantize:<бит>, resample:<Гц>, lowpass:<Гц>, highpass:<Гц>, scale:<множитель>, crop:<отсчетов>, shift:<отсчетов>, echo:<задержка мс>:<затухание>, lossy:<кбит/с>";
+
+fn container_arg() -> Arg {
+    Arg::new("container")
+        .help("Путь до контейнера WAV-формата")
+        .long("container")
+        .short('c')
+        .default_value("container.wav")
+        .action(ArgAction::Set)
+}
+
+fn stegacontainer_arg() -> Arg {
+    Arg::new("stegacontainer")
+        .help("Путь до стегаконтейнера в WAV-формате")
+        .long("stegacontainer")
+        .short('s')
+        .default_value("stegacontainer.wav")
+        .action(ArgAction::Set)
+}
+
+fn message_arg(help: &'static str) -> Arg {
+    Arg::new("message")
+        .help(help)
+        .long("message")
+        .short('m')
+        .default_value("message.txt")
+        .action(ArgAction::Set)
+}
+
+fn key_arg(help: &'static str) -> Arg {
+    Arg::new("key")
+        .help(help)
+        .long("key")
+        .short('k')
+        .default_value("key.csv")
+        .action(ArgAction::Set)
+}
+
+fn wav_arg() -> Arg {
+    Arg::new("file")
+        .help("Путь до WAV-файла")
+        .value_name("FILE")
+        .action(ArgAction::Set)
+        .required(true)
+}
+
+fn passphrase_args() -> [Arg; 2] {
+    [
+        Arg::new("passphrase")
+            .help("Пароль, из которого выводится ключ генератора hmac-sha256")
+            .long("passphrase")
+            .short('p')
+            .action(ArgAction::Set),
+        payload_passphrase_arg(),
+    ]
+}
+
+fn payload_passphrase_arg() -> Arg {
+    Arg::new("payload-passphrase")
+        .help("Пароль для шифрования сообщения (ChaCha20-Poly1305) перед встраиванием и проверки при извлечении")
+        .long("payload-passphrase")
+        .action(ArgAction::Set)
+}
 
-    let file_size = file.metadata()?.len();
+fn no_header_arg() -> Arg {
+    Arg::new("no-header")
+        .help("Не встраивать заголовок (для извлечения потребуются --bits-per-char и --message-len)")
+        .long("no-header")
+        .action(ArgAction::SetTrue)
+}
+
+// Количество позиций контейнера на бит кадра и повторение кадра в оставшейся части контейнера
+fn chips_args() -> [Arg; 3] {
+    [
+        Arg::new("min-chips")
+            .help("Наименьшее количество позиций контейнера (отсчетов, коэффициентов) на бит кадра: если сообщение длиннее, оно не встраивается. Для lsb и phase не применяется")
+            .long("min-chips")
+            .action(ArgAction::Set)
+            .value_parser(clap::value_parser!(usize))
+            .default_value("1"),
+        Arg::new("chips-per-bit")
+            .help("Фиксированное количество позиций контейнера на бит кадра вместо растягивания кадра на весь контейнер. Для lsb и phase не применяется")
+            .long("chips-per-bit")
+            .action(ArgAction::Set)
+            .value_parser(clap::value_parser!(usize))
+            .conflicts_with("min-chips"),
+        Arg::new("repeat")
+            .help("Повторять кадр по кругу до конца контейнера; при извлечении копии складываются. Требует --chips-per-bit")
+            .long("repeat")
+            .action(ArgAction::SetTrue)
+            .requires("chips-per-bit")
+            .conflicts_with("no-header"),
+    ]
+}
+
+fn fec_args() -> [Arg; 3] {
+    [
+        Arg::new("fec")
+            .help("Помехоустойчивый код для бит сообщения (записывается в заголовок)")
+            .long("fec")
+            .action(ArgAction::Set)
+            .value_parser(["none", "repetition", "hamming", "convolutional"])
+            .default_value("none")
+            .conflicts_with("no-header"),
+        Arg::new("fec-repeat")
+            .help("Кратность повторения для --fec repetition")
+            .long("fec-repeat")
+            .action(ArgAction::Set)
+            .value_parser(clap::value_parser!(u8).range(1..))
+            .default_value("3"),
+        Arg::new("rs-parity")
+            .help("Количество проверочных байт Рида-Соломона на блок из 255 байт (0 - без кода)")
+            .long("rs-parity")
+            .action(ArgAction::Set)
+            .value_parser(clap::value_parser!(u8).range(0..255))
+            .default_value("0")
+            .conflicts_with("no-header"),
+    ]
+}
+
+// Алгоритм встраивания и его параметры (см. build_method)
+fn method_args() -> [Arg; 7] {
+    [
+        Arg::new("method")
+            .help("Алгоритм встраивания: dsss - расширение спектра, lsb - младшие биты целочисленных отсчетов (большая емкость, но не переживает никаких искажений), echo - сокрытие эхом (слепое извлечение, переживает фильтрацию), phase - фазовое кодирование первого кадра (неслышимо, но вмещает не больше 2047 бит вместе с заголовком), qim - квантование проекций блоков на ПСП (слепое извлечение, переживает шум, но не изменение громкости). Записывается в ключ вместе с параметрами, поэтому при извлечении берется из ключа")
+            .long("method")
+            .action(ArgAction::Set)
+            .value_parser(METHOD_IDS)
+            .default_value(DSSS_ID),
+        Arg::new("lsb-bits")
+            .help("Количество младших бит на отсчет для --method lsb")
+            .long("lsb-bits")
+            .action(ArgAction::Set)
+            .value_parser(clap::value_parser!(u8).range(1..=8))
+            .default_value("1"),
+        Arg::new("echo-delays")
+            .help("Задержки эха для бит 0 и 1 в миллисекундах для --method echo, например 1:1.5. По умолчанию 50 и 75 отсчетов")
+            .long("echo-delays")
+            .action(ArgAction::Set),
+        Arg::new("qim-compensation")
+            .help("Коэффициент компенсации искажений alpha из (0, 1] для --method qim: меньше - тише, но менее устойчиво")
+            .long("qim-compensation")
+            .action(ArgAction::Set)
+            .value_parser(clap::value_parser!(f64))
+            .default_value("0.75"),
+        Arg::new("strength")
+            .help("Сила встраивания для --gain fixed: x + strength * c * (x + 2) во временной области (по умолчанию 0.0005), c + strength * chip * |c| в области MDCT (по умолчанию 0.1). Для --method echo - затухание эха (по умолчанию 0.3), для --method qim - шаг квантователя на отсчет (по умолчанию 0.005)")
+            .long("strength")
+            .action(ArgAction::Set)
+            .value_parser(clap::value_parser!(f64)),
+        Arg::new("gain")
+            .help("Амплитуда ПСП для --method dsss: fixed - постоянная (--strength), adaptive - под порогом маскирования контейнера (психоакустическая модель). В областях mdct и fft модели нет: изменение и так пропорционально модулю коэффициента, и adaptive лишь ослабляет силу по умолчанию на --mask-margin")
+            .long("gain")
+            .action(ArgAction::Set)
+            .value_parser(["fixed", "adaptive"])
+            .default_value("fixed"),
+        Arg::new("mask-margin")
+            .help("Запас ниже порога маскирования в дБ для --gain adaptive")
+            .long("mask-margin")
+            .action(ArgAction::Set)
+            .value_parser(clap::value_parser!(f64))
+            .default_value("3"),
+    ]
+}
+
+// Политика каналов и область: параметры алгоритма при встраивании и сужение перебора
+// при извлечении по ключу старой версии
+fn stream_args() -> [Arg; 3] {
+    [
+        Arg::new("channel-mode")
+            .help("Встраивание в многоканальный файл: left - только первый канал, all - во все каналы, mid-side - в средний сигнал. При извлечении по умолчанию перебираются все варианты")
+            .long("channel-mode")
+            .action(ArgAction::Set)
+            .value_parser(["left", "all", "mid-side"]),
+        Arg::new("domain")
+            .help("Область встраивания: time - отсчеты сигнала, mdct - коэффициенты MDCT средних частот (переживает сжатие с потерями), fft - модули бинов БПФ в полосе --band. При извлечении по умолчанию перебираются все (fft - с полосой по умолчанию)")
+            .long("domain")
+            .action(ArgAction::Set)
+            .value_parser(["time", "mdct", "fft"]),
+        Arg::new("band")
+            .help("Полоса для --domain fft в герцах, например 300:3400 для телефонного канала. По умолчанию от 0.01 до 0.1 частоты дискретизации")
+            .long("band")
+            .action(ArgAction::Set)
+            .requires("domain"),
+    ]
+}
+
+// Значение аргумента командной строки. Аргументы с значением по умолчанию присутствуют всегда,
+// поэтому ошибка означает рассогласование с init_cli
+pub fn required_arg<'a, T: Clone + Send + Sync + 'static>(matches: &'a ArgMatches, id: &str) -> Result<&'a T, StegoError> {
+    match matches.try_get_one::<T>(id) {
+        Ok(Some(value)) => Ok(value),
+        _ => Err(StegoError::InvalidArgument(format!("не указан аргумент --{}", id))),
+    }
+}
+
+// Ошибка ввода-вывода с именем файла, чтобы было понятно, какой из файлов не открылся
+fn file_error(path: &str) -> impl FnOnce(io::Error) -> StegoError + '_ {
+    move |e| StegoError::Io(io::Error::new(e.kind(), format!("{}: {}", path, e)))
+}
+
+fn read_file(path: &str, buffer: &mut Vec<u8>) -> Result<(), StegoError> {
+    let mut file = File::open(Path::new(path)).map_err(file_error(path))?;
+
+    let file_size = file.metadata().map_err(file_error(path))?.len();
     buffer.resize(file_size as usize, 0);
 
-    file.read_exact(buffer).map_err(|e| {
-        eprintln!("Ошибка при чтении файла: {}",  e);
-        Box::new(io::Error::new(io::ErrorKind::InvalidInput, e)) as Box<dyn std::error::Error>
-    })?;
+    file.read_exact(buffer).map_err(file_error(path))?;
 
     Ok(())
 }
 
 
-// Учитывается, что у нас в сообщении не смешиваются латиница и кириллица. (НЕ ФАКТ ЧТО РАБОТАЕТ)
-pub fn count_bits_per_char(bytes: &[u8]) -> Result<usize, Box<dyn std::error::Error>> {
-    let mut max_bits_per_char: usize = 0;
-    match std::str::from_utf8(bytes) {
-        Ok(s) => {
-            for c in s.chars() {
-                let bytes = c.len_utf8();
-                let bits = bytes * 8;
-                if bits > max_bits_per_char {
-                    max_bits_per_char = bits;
+// Входные файлы и параметры подкоманды command, которой нужны контейнер или стегаконтейнер
+pub fn process_files(command: &str, matches: &ArgMatches) -> Result<ProcessResult, StegoError> {
+    match command {
+        "embed" => {
+            let container = get_wav_file_data(required_arg::<String>(matches, "container")?)?;
+            let method = build_method(matches, &container)?;
+            let mut message: Vec<u8> = Vec::new();
+            read_file(required_arg::<String>(matches, "message")?, &mut message)?;
+
+            Ok(ProcessResult::Encrypt(EncryptData { container, message, method }))
+        }
+        "extract" => {
+            let stegocontainer = get_wav_file_data(required_arg::<String>(matches, "stegacontainer")?)?;
+            // При слепом извлечении исходный контейнер не нужен
+            let container = if matches.get_flag("blind") {
+                None
+            } else {
+                Some(get_wav_file_data(required_arg::<String>(matches, "container")?)?)
+            };
+            let domain = parse_domain(matches, stegocontainer.sample_rate)?;
+            let passphrase = matches.get_one::<String>("passphrase").map(|s| s.as_str());
+            let key = read_key_from_file(required_arg::<String>(matches, "key")?, passphrase)?;
+
+            Ok(ProcessResult::Decrypt(DecryptData {
+                container,
+                stegocontainer,
+                key: key.psp,
+                method: key.method,
+                channel_mode: parse_channel_mode(matches),
+                domain,
+            }))
+        }
+        "capacity" => {
+            let container = get_wav_file_data(required_arg::<String>(matches, "container")?)?;
+            let method = build_method(matches, &container)?;
+            let message = match matches.get_one::<String>("message") {
+                Some(path) => {
+                    let mut message = Vec::new();
+                    read_file(path, &mut message)?;
+                    Some(message)
                 }
-                // print_debug_information(format!("Символ: {}, Байты: {}, Биты: {}", c, bytes, bits));
+                None => None,
+            };
+
+            Ok(ProcessResult::Capacity(CapacityData { container, method, message }))
+        }
+        "attack" => {
+            let attack = Attack::parse(required_arg::<String>(matches, "attack")?)?;
+            let stegocontainer = get_wav_file_data(required_arg::<String>(matches, "stegacontainer")?)?;
+
+            Ok(ProcessResult::Attack(AttackData { stegocontainer, attack }))
+        }
+        "bench" => {
+            let container = get_wav_file_data(required_arg::<String>(matches, "container")?)?;
+            let method = build_method(matches, &container)?;
+            let mut message: Vec<u8> = Vec::new();
+            read_file(required_arg::<String>(matches, "message")?, &mut message)?;
+            let attacks = match matches.get_many::<String>("bench-attack") {
+                Some(specs) => specs.map(|spec| Attack::parse(spec)).collect::<Result<_, _>>()?,
+                None => DEFAULT_ATTACKS.to_vec(),
+            };
+
+            Ok(ProcessResult::Bench(BenchData { container, message, method, attacks }))
+        }
+        other => Err(StegoError::InvalidArgument(format!("неизвестная подкоманда {}", other))),
+    }
+}
+
+fn parse_channel_mode(matches: &ArgMatches) -> Option<ChannelMode> {
+    matches.get_one::<String>("channel-mode").and_then(|name| ChannelMode::from_name(name))
+}
+
+// Алгоритм встраивания из --method с параметрами из остальных аргументов и формата контейнера
+fn build_method(matches: &ArgMatches, container: &WavFile) -> Result<Arc<dyn StegoMethod>, StegoError> {
+    let sample_rate = container.sample_rate;
+    let method = required_arg::<String>(matches, "method")?.as_str();
+    let adaptive = required_arg::<String>(matches, "gain")? == "adaptive";
+    if adaptive && method != DSSS_ID {
+        return Err(StegoError::InvalidArgument(format!("--gain adaptive применяется только к --method {}", DSSS_ID)));
+    }
+    if adaptive && matches.contains_id("strength") {
+        return Err(StegoError::InvalidArgument("--strength задает постоянную силу и не сочетается с --gain adaptive".to_string()));
+    }
+
+    match method {
+        DSSS_ID => {
+            let gain = if adaptive {
+                Some(Gain::Adaptive { margin_db: *required_arg::<f64>(matches, "mask-margin")?, sample_rate })
+            } else {
+                matches.get_one::<f64>("strength").map(|&strength| Gain::Fixed(strength))
+            };
+            Ok(Arc::new(SpreadSpectrum {
+                channel_mode: parse_channel_mode(matches).unwrap_or(ChannelMode::Left),
+                domain: parse_domain(matches, sample_rate)?.unwrap_or(Domain::Time),
+                gain,
+            }))
+        }
+        LSB_ID => {
+            if container.sample_format != SampleFormat::Int {
+                return Err(StegoError::InvalidArgument("LSB встраивается только в целочисленные отсчеты".to_string()));
             }
+            Ok(Arc::new(LeastSignificantBits::new(container.bits_per_sample, *required_arg::<u8>(matches, "lsb-bits")?)?))
         }
-        Err(_) => {
-            return Err(Box::from("Ошибка: массив байтов содержит невалидный UTF-8"))
+        ECHO_ID => Ok(Arc::new(EchoHiding::new(
+            parse_channel_mode(matches).unwrap_or(ChannelMode::Left),
+            parse_echo_delays(matches, sample_rate)?,
+            matches.get_one::<f64>("strength").copied().unwrap_or(DEFAULT_DECAY),
+        )?)),
+        PHASE_ID => Ok(Arc::new(PhaseCoding)),
+        QIM_ID => Ok(Arc::new(QuantizationIndexModulation::new(
+            parse_channel_mode(matches).unwrap_or(ChannelMode::Left),
+            matches.get_one::<f64>("strength").copied().unwrap_or(DEFAULT_STEP),
+            *required_arg::<f64>(matches, "qim-compensation")?,
+        )?)),
+        other => Err(StegoError::InvalidArgument(format!("неизвестный метод встраивания {}", other))),
+    }
+}
+
+// Задержки эха из --echo-delays в миллисекундах, переведенные в отсчеты
+fn parse_echo_delays(matches: &ArgMatches, sample_rate: u32) -> Result<(usize, usize), StegoError> {
+    let Some(delays) = matches.get_one::<String>("echo-delays") else {
+        return Ok(DEFAULT_DELAYS);
+    };
+    let invalid = || StegoError::InvalidArgument(format!(
+        "задержки эха \"{}\": ожидается <задержка бита 0 мс>:<задержка бита 1 мс>, различные в отсчетах", delays,
+    ));
+    let (zero, one) = delays.split_once(':').ok_or_else(invalid)?;
+    let to_samples = |ms: &str| -> Result<usize, StegoError> {
+        let ms: f64 = ms.trim().parse().map_err(|_| invalid())?;
+        let samples = (ms * sample_rate as f64 / 1000.0).round();
+        if samples >= 1.0 { Ok(samples as usize) } else { Err(invalid()) }
+    };
+    let delays = (to_samples(zero)?, to_samples(one)?);
+    if delays.0 == delays.1 {
+        return Err(invalid());
+    }
+    Ok(delays)
+}
+
+// Область встраивания из --domain. Полоса --band задается в герцах и переводится
+// в доли частоты дискретизации файла
+fn parse_domain(matches: &ArgMatches, sample_rate: u32) -> Result<Option<Domain>, StegoError> {
+    let domain = matches.get_one::<String>("domain").and_then(|name| Domain::from_name(name));
+
+    match (domain, matches.get_one::<String>("band")) {
+        (Some(Domain::Fft { .. }), Some(band)) => {
+            let invalid = || StegoError::InvalidArgument(format!(
+                "полоса \"{}\": ожидается <нижняя частота Гц>:<верхняя частота Гц> в пределах половины частоты дискретизации", band,
+            ));
+            let (low, high) = band.split_once(':').ok_or_else(invalid)?;
+            let low: f64 = low.trim().parse().map_err(|_| invalid())?;
+            let high: f64 = high.trim().parse().map_err(|_| invalid())?;
+            if !(0.0 <= low && low < high && high <= sample_rate as f64 / 2.0) {
+                return Err(invalid());
+            }
+            Ok(Some(Domain::Fft { low: low / sample_rate as f64, high: high / sample_rate as f64 }))
         }
+        (_, Some(_)) => Err(StegoError::InvalidArgument("полоса --band задается только для --domain fft".to_string())),
+        (domain, None) => Ok(domain),
     }
-    Ok(max_bits_per_char)
-}
-pub fn process_files(matches: &ArgMatches) -> Result<ProcessResult, Box<dyn std::error::Error>> {
-    if matches.get_flag("encrypt") {
-        let wav_path = matches.get_one::<String>("container").unwrap();
-        let message_path = matches.get_one::<String>("message").unwrap();
-        let message_file = File::open(Path::new(message_path));
-
-        let container = get_wav_file_data(wav_path)?;
-        let mut message: Vec<u8> = Vec::new();
-        read_file(message_file, &mut message)?;
-
-        Ok(ProcessResult::Encrypt (EncryptData {
-            container,
-            message,
-        }))
-    } else {
-        let container_wav_path = matches.get_one::<String>("container").unwrap();
-        let stegocontainer_wav_path = matches.get_one::<String>("stegacontainer").unwrap();
-        let key_path = matches.get_one::<String>("key").unwrap();
-        let container = get_wav_file_data(container_wav_path)?;
-        let stegocontainer = get_wav_file_data(stegocontainer_wav_path)?;
-        let key = read_key_from_file(key_path)?;
-
-        Ok(ProcessResult::Decrypt (DecryptData {
-            container,
-            stegocontainer,
-            key
-        }))
+}
+
+fn deinterleave(samples: &[f64], channels: usize) -> Vec<Vec<f64>> {
+    let mut buffers = vec![Vec::with_capacity(samples.len() / channels.max(1)); channels];
+    for (i, &sample) in samples.iter().enumerate() {
+        buffers[i % channels].push(sample);
     }
+    buffers
 }
 
-fn get_wav_file_data(wav_path: &String) -> Result<WavFile, Box<dyn std::error::Error>> {
-    let mut wav = WavReader::open(Path::new(wav_path))?;
+// Полная шкала целочисленного формата: отсчет делится на нее при чтении и умножается при записи.
+// Отсчеты от -2^(bits - 1) до 2^(bits - 1) - 1 переходят в [-1.0, 1.0) без потерь.
+fn int_full_scale(bits_per_sample: u16) -> f64 {
+    (1i64 << (bits_per_sample - 1)) as f64
+}
 
-    let spec = wav.spec();
+fn unsupported_format(spec: &WavSpec) -> StegoError {
+    StegoError::UnsupportedFormat(format!("{:?}, {} бит", spec.sample_format, spec.bits_per_sample))
+}
+
+pub fn get_wav_file_data(wav_path: &str) -> Result<WavFile, StegoError> {
+    let mut wav = WavReader::open(Path::new(wav_path)).map_err(|e| match e {
+        hound::Error::IoError(e) => file_error(wav_path)(e),
+        e => StegoError::from(e),
+    })?;
 
-    let samples: Vec<i16> = wav.samples::<i16>()
-        .map(|result| result.unwrap())
-        .collect();
+    let spec = wav.spec();
+    // Ошибка чтения после разбора заголовка означает, что данных меньше, чем указано в файле
+    let truncated = |e: hound::Error| match e {
+        hound::Error::IoError(e) => StegoError::InvalidWav(format!("файл обрезан: {}", e)),
+        e => StegoError::from(e),
+    };
 
-    let max_amplitude = i16::MAX as f64;
-    let amplitudes: Vec<f64> = samples
-        .into_iter()
-        .map(|sample| sample as f64 / max_amplitude)
-        .collect();
+    let interleaved: Vec<f64> = match (spec.sample_format, spec.bits_per_sample) {
+        (SampleFormat::Float, 32) => wav.samples::<f32>()
+            .map(|sample| sample.map(|s| s as f64))
+            .collect::<Result<_, _>>().map_err(truncated)?,
+        (SampleFormat::Int, bits @ 1..=8) => wav.samples::<i8>()
+            .map(|sample| sample.map(|s| s as f64 / int_full_scale(bits)))
+            .collect::<Result<_, _>>().map_err(truncated)?,
+        (SampleFormat::Int, bits @ 9..=16) => wav.samples::<i16>()
+            .map(|sample| sample.map(|s| s as f64 / int_full_scale(bits)))
+            .collect::<Result<_, _>>().map_err(truncated)?,
+        (SampleFormat::Int, bits @ 17..=32) => wav.samples::<i32>()
+            .map(|sample| sample.map(|s| s as f64 / int_full_scale(bits)))
+            .collect::<Result<_, _>>().map_err(truncated)?,
+        _ => return Err(unsupported_format(&spec)),
+    };
 
     let data = WavFile {
-        name: wav_path.clone(),
-        amplitudes,
+        name: wav_path.to_string(),
+        amplitudes: deinterleave(&interleaved, spec.channels as usize),
         bits_per_sample: spec.bits_per_sample,
+        sample_format: spec.sample_format,
         channels: spec.channels,
         sample_rate: spec.sample_rate,
-        samples_num: wav.len(),
+        samples_num: wav.duration(),
     };
     Ok(data)
 }
 
-pub fn save_amplitudes_to_wav(new_wav: &WavFile) -> Result<(), Box<dyn std::error::Error>> {
+// Стегаконтейнер сохраняется в том же формате отсчетов, что и исходный контейнер
+pub fn save_amplitudes_to_wav(new_wav: &WavFile) -> Result<(), StegoError> {
     let spec = WavSpec {
         channels: new_wav.channels,
         sample_rate: new_wav.sample_rate,
         bits_per_sample: new_wav.bits_per_sample,
-        sample_format: SampleFormat::Int,
+        sample_format: new_wav.sample_format,
     };
 
+    if !matches!((spec.sample_format, spec.bits_per_sample), (SampleFormat::Float, 32) | (SampleFormat::Int, 1..=32)) {
+        return Err(unsupported_format(&spec));
+    }
+
     let mut writer = WavWriter::create(Path::new(&new_wav.name), spec)?;
+    let full_scale = int_full_scale(spec.bits_per_sample);
+
+    let frames = new_wav.amplitudes.iter().map(|channel| channel.len()).min().unwrap_or(0);
+    let interleaved = (0..frames)
+        .flat_map(|frame| new_wav.amplitudes.iter().map(move |channel| channel[frame]));
+
+    for amplitude in interleaved {
+        if spec.sample_format == SampleFormat::Float {
+            writer.write_sample(amplitude as f32)?;
+            continue;
+        }
 
-    for amplitude in &new_wav.amplitudes {
-        // Ограничиваем амплитуды диапазоном [-1.0, 1.0]
-        let amplitude = amplitude.clamp(-1.0, 1.0);
-        let sample = (amplitude * i16::MAX as f64) as i16;
-        writer.write_sample(sample)?;
+        // Ограничиваем отсчеты диапазоном формата
+        let sample = (amplitude * full_scale).round().clamp(-full_scale, full_scale - 1.0);
+        match spec.bits_per_sample {
+            1..=8 => writer.write_sample(sample as i8)?,
+            9..=16 => writer.write_sample(sample as i16)?,
+            _ => writer.write_sample(sample as i32)?,
+        }
     }
 
     writer.finalize()?;
     Ok(())
 }
 
-pub fn plot_wav_amplitudes(wav: &WavFile, plotname: &str) -> Result<(), Box<dyn std::error::Error>> {
+pub fn plot_wav_amplitudes(wav: &WavFile, plotname: &str) -> Result<(), StegoError> {
+    let plot_error = |e: &dyn std::fmt::Display| StegoError::Io(io::Error::other(format!("{}: {}", plotname, e)));
+
     let root = BitMapBackend::new(plotname, (800, 600)).into_drawing_area();
-    root.fill(&WHITE)?;
+    root.fill(&WHITE).map_err(|e| plot_error(&e))?;
+    let frames = wav.amplitudes.first().map_or(0, |channel| channel.len());
     let mut chart = ChartBuilder::on(&root)
         .caption(format!("Амплитуды файла {}", wav.name), ("sans-serif", 24))
-        .build_cartesian_2d(0..wav.amplitudes.len(), -1.2f64..1.2f64)?;
-    chart.configure_mesh().draw().unwrap();
+        .build_cartesian_2d(0..frames, -1.2f64..1.2f64)
+        .map_err(|e| plot_error(&e))?;
+    chart.configure_mesh().draw().map_err(|e| plot_error(&e))?;
     let step = 100;
-    let sampled_amplitudes: Vec<_> = wav.amplitudes.iter().step_by(step).cloned().collect();
-    chart
-        .draw_series(LineSeries::new(
-            sampled_amplitudes.iter().enumerate().map(|(i, &v)| (i * step, v)),
-            &BLACK
-        )).unwrap();
+    // Каналы рисуются поверх друг друга разными цветами
+    for (channel_idx, channel) in wav.amplitudes.iter().enumerate() {
+        let sampled_amplitudes: Vec<_> = channel.iter().step_by(step).cloned().collect();
+        let color = if channel_idx == 0 { BLACK.to_rgba() } else { Palette99::pick(channel_idx).to_rgba() };
+        chart
+            .draw_series(LineSeries::new(
+                sampled_amplitudes.iter().enumerate().map(|(i, &v)| (i * step, v)),
+                &color
+            )).map_err(|e| plot_error(&e))?;
+    }
+    root.present().map_err(|e| plot_error(&e))?;
     Ok(())
 }
 
-pub fn generate_wav(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+pub fn generate_wav(matches: &ArgMatches) -> Result<(), StegoError> {
     let spec = WavSpec {
-        channels: *matches.get_one::<u16>("channels").unwrap(),
-        sample_rate: *matches.get_one::<u32>("sample-rate").unwrap(),
+        channels: *required_arg::<u16>(matches, "channels")?,
+        sample_rate: *required_arg::<u32>(matches, "sample-rate")?,
         bits_per_sample: 16,
         sample_format: SampleFormat::Int,
     };
 
-    let duration: f32 = *matches.get_one::<f32>("duration").unwrap();
+    let duration: f32 = *required_arg::<f32>(matches, "duration")?;
 
-    let mut writer = WavWriter::create(matches.get_one::<String>("name").unwrap(), spec)?;
+    let mut writer = WavWriter::create(required_arg::<String>(matches, "name")?, spec)?;
 
+    // Каждый канал - синусоида своей частоты (440 Гц, 880 Гц, ...), отсчеты каналов чередуются
     for t in 0..((spec.sample_rate as f32 * duration) as u32) {
         let time = t as f32 / spec.sample_rate as f32;
-        let sample = (time * 440.0 * 2.0 * std::f32::consts::PI).sin();
-        let normalized_sample = (sample * i16::MAX as f32) as i16;
-        writer.write_sample(normalized_sample)?;
+        for channel in 0..spec.channels {
+            let frequency = 440.0 * (channel + 1) as f32;
+            let sample = (time * frequency * 2.0 * std::f32::consts::PI).sin();
+            let normalized_sample = (sample * i16::MAX as f32) as i16;
+            writer.write_sample(normalized_sample)?;
+        }
     }
     writer.finalize()?;
     Ok(())
 }
 
-pub fn write_key_to_file(key: &[i16], filename: &str) -> std::io::Result<()> {
+pub fn write_bench_csv(rows: &[BenchRow], filename: &str) -> Result<(), StegoError> {
+    std::fs::write(filename, to_csv(rows)).map_err(file_error(filename))
+}
+
+pub fn write_quality_report(report: &QualityReport, filename: &str) -> Result<(), StegoError> {
+    std::fs::write(filename, report.to_json()).map_err(file_error(filename))
+}
+
+// Во второй строке ключа записываются алгоритм встраивания и его параметры
+pub fn write_key_to_file(key: &[i16], method: &dyn StegoMethod, filename: &str) -> std::io::Result<()> {
     let file = File::create(filename)?;
     let mut writer = BufWriter::new(file);
 
@@ -288,65 +789,217 @@ pub fn write_key_to_file(key: &[i16], filename: &str) -> std::io::Result<()> {
             write!(writer, ",")?;
         }
     }
+    writeln!(writer)?;
+    write!(writer, "{}", method::to_parameters_line(method))?;
     Ok(())
 }
 
-pub fn read_key_from_file(filename: &str) -> std::io::Result<Vec<i16>> {
-    let file = File::open(filename)?;
+pub fn write_key_spec_to_file(spec: &KeySpec, method: &dyn StegoMethod, filename: &str) -> std::io::Result<()> {
+    let file = File::create(filename)?;
+    let mut writer = BufWriter::new(file);
+
+    match spec {
+        KeySpec::ParkMiller { seed, chips } => write!(writer, "{},{},{}", PARK_MILLER_ID, seed, chips)?,
+        KeySpec::HmacSha256 { salt, chips } => {
+            let salt_hex: String = salt.iter().map(|b| format!("{:02x}", b)).collect();
+            write!(writer, "{},{},{}", HMAC_SHA256_ID, salt_hex, chips)?
+        }
+    }
+    writeln!(writer)?;
+    write!(writer, "{}", method::to_parameters_line(method))?;
+    Ok(())
+}
+
+fn parse_key_spec(line: &str) -> Result<KeySpec, StegoError> {
+    let invalid = |what: &str| StegoError::MalformedKey(what.to_string());
+
+    let tokens: Vec<&str> = line.trim().split(',').collect();
+    if tokens.len() != 3 {
+        return Err(invalid("ожидается генератор, параметр и длина ПСП через запятую"));
+    }
+    let chips = tokens[2].parse::<usize>().map_err(|_| invalid("длина ПСП должна быть целым числом"))?;
+
+    match tokens[0] {
+        PARK_MILLER_ID => {
+            let seed = tokens[1].parse::<u32>().map_err(|_| invalid("зерно должно быть целым числом"))?;
+            if seed > MAX_SEED {
+                return Err(invalid(&format!("зерно больше {}", MAX_SEED)));
+            }
+            Ok(KeySpec::ParkMiller { seed, chips })
+        }
+        HMAC_SHA256_ID => {
+            let salt_hex = tokens[1];
+            let invalid_salt = || invalid("соль должна состоять из 32 шестнадцатеричных цифр");
+            if salt_hex.len() != SALT_LEN * 2 || !salt_hex.is_ascii() {
+                return Err(invalid_salt());
+            }
+            let mut salt = [0u8; SALT_LEN];
+            for (i, byte) in salt.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(&salt_hex[2 * i..2 * i + 2], 16).map_err(|_| invalid_salt())?;
+            }
+            Ok(KeySpec::HmacSha256 { salt, chips })
+        }
+        other => Err(StegoError::MalformedKey(format!("неизвестный генератор ПСП {}", other))),
+    }
+}
+
+// Поддерживаются оба формата: компактный (генератор, зерно, длина) и старый,
+// в котором через запятую перечислены все элементы ПСП. Вторая строка с алгоритмом
+// встраивания может отсутствовать (ключи старых версий), тогда алгоритм подбирается перебором.
+pub fn read_key_from_file(filename: &str, passphrase: Option<&str>) -> Result<KeyFile, StegoError> {
+    let file = File::open(filename).map_err(file_error(filename))?;
     let mut reader = BufReader::new(file);
+    let not_text = || StegoError::MalformedKey("ключ должен быть текстовым файлом".to_string());
 
     let mut line = String::new();
-    reader.read_line(&mut line)?;
+    reader.read_line(&mut line).map_err(|_| not_text())?;
+    let mut method_line = String::new();
+    reader.read_line(&mut method_line).map_err(|_| not_text())?;
+
+    let method = match method_line.trim() {
+        "" => None,
+        method_line => {
+            let tokens: Vec<&str> = method_line.split(',').collect();
+            Some(method::from_parameters(tokens[0], &tokens[1..])?)
+        }
+    };
 
+    let first_token = line.trim().split(',').next().unwrap_or("");
+    if first_token.parse::<i16>().is_err() {
+        return Ok(KeyFile { psp: parse_key_spec(&line)?.generate_prs(passphrase)?, method });
+    }
 
-    let key: Vec<i16> = line
-        .trim()
+    let psp = line.trim()
         .split(',')
-        .map(|s| s.parse::<i16>().unwrap())
-        .collect();
+        .map(|s| s.trim().parse::<i16>()
+            .map_err(|_| StegoError::MalformedKey(format!("элемент ПСП \"{}\" не является целым числом", s))))
+        .collect::<Result<_, _>>()?;
+    Ok(KeyFile { psp, method })
+}
 
-    Ok(key)
+pub struct KeyFile {
+    pub psp: Vec<i16>,
+    // Отсутствует в ключах старых версий
+    pub method: Option<Arc<dyn StegoMethod>>,
 }
 
 
 pub enum ProcessResult {
     Encrypt(EncryptData),
     Decrypt(DecryptData),
+    Capacity(CapacityData),
+    Attack(AttackData),
+    Bench(BenchData),
 }
 #[derive(Debug)]
 pub struct EncryptData {
     pub container: WavFile,
     pub message: Vec<u8>,
+    pub method: Arc<dyn StegoMethod>,
 }
 
 pub struct DecryptData {
-    pub container: WavFile,
+    pub container: Option<WavFile>,
     pub stegocontainer: WavFile,
     pub key: Vec<i16>,
+    // Алгоритм из ключа; для ключей старых версий перебирается расширение спектра
+    // с указанными политикой каналов и областью или со всеми
+    pub method: Option<Arc<dyn StegoMethod>>,
+    pub channel_mode: Option<ChannelMode>,
+    pub domain: Option<Domain>,
 }
 
-#[derive(Debug)]
+pub struct CapacityData {
+    pub container: WavFile,
+    pub method: Arc<dyn StegoMethod>,
+    // Сообщение, для которого рассчитывается встраивание
+    pub message: Option<Vec<u8>>,
+}
+
+pub struct AttackData {
+    pub stegocontainer: WavFile,
+    pub attack: Attack,
+}
+
+pub struct BenchData {
+    pub container: WavFile,
+    pub message: Vec<u8>,
+    pub method: Arc<dyn StegoMethod>,
+    pub attacks: Vec<Attack>,
+}
+
+#[derive(Debug, Clone)]
 pub struct WavFile {
     pub name: String,
-    pub amplitudes: Vec<f64>,
+    // Отсчеты, разделенные по каналам
+    pub amplitudes: Vec<Vec<f64>>,
     pub bits_per_sample: u16,
+    pub sample_format: SampleFormat,
     pub channels: u16,
     pub sample_rate: u32,
+    // Количество отсчетов в каждом канале
     pub samples_num: u32,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use lab_3::chip_generator::ChipGenerator;
+    use lab_3::hmac_prng::HmacSha256Generator;
+    use lab_3::park_miller_prng::ParkMiller;
     use std::fs;
     use std::io::Read;
 
+    #[test]
+    fn test_cli_subcommands_take_own_options() {
+        cli().debug_assert();
+        let parse = |args: &[&str]| cli().try_get_matches_from(std::iter::once("lab_3").chain(args.iter().copied()));
+
+        // Ключ записывается при встраивании, параметры генерирования WAV-файла не нужны
+        let matches = parse(&["embed", "-c", "music.wav", "-k", "out.csv", "--method", "echo"]).unwrap();
+        let (command, embed) = matches.subcommand().unwrap();
+        assert_eq!(command, "embed");
+        assert_eq!(required_arg::<String>(embed, "key").unwrap(), "out.csv");
+
+        let matches = parse(&["extract", "--blind", "-b", "8", "-l", "10"]).unwrap();
+        assert_eq!(matches.subcommand_matches("extract").unwrap().get_one::<usize>("message-len"), Some(&10));
+
+        let matches = parse(&["generate", "-n", "out.wav", "-d", "2"]).unwrap();
+        assert_eq!(matches.subcommand_matches("generate").unwrap().get_one::<u16>("channels"), Some(&1));
+        assert!(parse(&["generate", "-n", "out.wav"]).is_err());
+
+        let matches = parse(&["capacity", "--min-chips", "100", "--fec", "hamming"]).unwrap();
+        let capacity = matches.subcommand_matches("capacity").unwrap();
+        assert_eq!(capacity.get_one::<usize>("min-chips"), Some(&100));
+        assert_eq!(capacity.get_one::<String>("message"), None);
+
+        let matches = parse(&["embed", "--chips-per-bit", "4096", "--repeat"]).unwrap();
+        let embed = matches.subcommand_matches("embed").unwrap();
+        assert_eq!(embed.get_one::<usize>("chips-per-bit"), Some(&4096));
+        assert!(embed.get_flag("repeat"));
+        assert!(parse(&["embed", "--chips-per-bit", "4096", "--min-chips", "100"]).is_err());
+        assert!(parse(&["embed", "--repeat"]).is_err());
+        assert!(parse(&["capacity", "--chips-per-bit", "20", "--repeat", "--no-header"]).is_err());
+        assert!(parse(&["extract", "--repeat"]).is_err());
+        // Зерна park-miller больше 2^31 - 2 совпали бы с меньшими
+        assert!(parse(&["embed", "--seed", "2147483647"]).is_err());
+
+        // Параметры встраивания не принимаются при извлечении, и наоборот
+        assert!(parse(&["extract", "--min-chips", "100"]).is_err());
+        assert!(parse(&["extract", "--seed", "1"]).is_err());
+        assert!(parse(&["embed", "--blind"]).is_err());
+        assert!(parse(&["embed", "--no-header", "--fec", "hamming"]).is_err());
+        assert!(parse(&["attack", "-s", "stego.wav"]).is_err());
+        assert!(parse(&[]).is_err());
+    }
+
     #[test]
     fn test_write_key_to_file() {
         let key = vec![1, -1, 2, -2, 3];
         let filename = "test_key.txt";
 
-        let result = write_key_to_file(&key, filename);
+        let method = SpreadSpectrum { channel_mode: ChannelMode::All, ..SpreadSpectrum::default() };
+        let result = write_key_to_file(&key, &method, filename);
         assert!(result.is_ok(), "Функция вернула ошибку: {:?}", result);
 
         let mut file = File::open(filename).expect("Не удалось открыть файл");
@@ -354,18 +1007,175 @@ mod tests {
         file.read_to_string(&mut contents)
             .expect("Не удалось прочитать файл");
 
-        let expected_contents = "1,-1,2,-2,3";
+        let expected_contents = "1,-1,2,-2,3\ndsss,all,time";
         assert_eq!(contents, expected_contents, "Содержимое файла не совпадает с ожидаемым");
 
         fs::remove_file(filename).expect("Не удалось удалить тестовый файл");
     }
 
+    #[test]
+    fn test_key_spec_round_trip() {
+        let spec = KeySpec::ParkMiller { seed: 4242, chips: 300 };
+        let filename = "test_key_spec.csv";
+
+        let method = SpreadSpectrum { domain: Domain::Fft { low: 0.25, high: 0.5 }, ..SpreadSpectrum::default() };
+
+        write_key_spec_to_file(&spec, &method, filename).expect("Не удалось записать ключ");
+
+        let contents = fs::read_to_string(filename).expect("Не удалось прочитать файл");
+        assert_eq!(contents, "park-miller,4242,300\ndsss,left,fft,0.25,0.5");
+
+        let key = read_key_from_file(filename, None).expect("Не удалось прочитать ключ");
+        assert_eq!(key.psp, ParkMiller::with_seed(4242).unwrap().generate_prs(300));
+        assert_eq!(key.method.expect("Алгоритм должен быть записан в ключ").parameters(), method.parameters());
+
+        fs::remove_file(filename).expect("Не удалось удалить тестовый файл");
+    }
+
+    #[test]
+    fn test_hmac_key_spec_round_trip() {
+        let salt = [0xab; SALT_LEN];
+        let spec = KeySpec::HmacSha256 { salt, chips: 128 };
+        let filename = "test_hmac_key_spec.csv";
+
+        write_key_spec_to_file(&spec, &SpreadSpectrum::default(), filename).expect("Не удалось записать ключ");
+
+        let contents = fs::read_to_string(filename).expect("Не удалось прочитать файл");
+        assert_eq!(contents, format!("hmac-sha256,{},128\ndsss,left,time", "ab".repeat(SALT_LEN)));
+
+        // Без пароля восстановить ПСП нельзя
+        assert!(read_key_from_file(filename, None).is_err());
+
+        let key = read_key_from_file(filename, Some("пароль")).expect("Не удалось прочитать ключ");
+        assert_eq!(key.psp, HmacSha256Generator::from_passphrase("пароль", &salt).generate_prs(128));
+
+        fs::remove_file(filename).expect("Не удалось удалить тестовый файл");
+    }
+
+    #[test]
+    fn test_read_legacy_key_from_file() {
+        let key = vec![1, -1, -1, 1, 1];
+        let filename = "test_legacy_key.csv";
+
+        // Ключ старой версии: одна строка без алгоритма встраивания
+        fs::write(filename, "1,-1,-1,1,1").unwrap();
+        let legacy = read_key_from_file(filename, None).expect("Не удалось прочитать ключ");
+        assert_eq!(legacy.psp, key);
+        assert!(legacy.method.is_none());
+
+        fs::remove_file(filename).expect("Не удалось удалить тестовый файл");
+    }
+
+    #[test]
+    fn test_read_malformed_key_from_file() {
+        let filename = "test_malformed_key.csv";
+
+        for contents in ["1,-1,x,1", "park-miller,abc,10", "park-miller,4294967295,10", "lfsr,1,10", "", "park-miller,1,10\nlsb", "park-miller,1,10\ndsss,left,wavelet"] {
+            fs::write(filename, contents).unwrap();
+            let result = read_key_from_file(filename, None);
+            assert!(matches!(result, Err(StegoError::MalformedKey(_))), "Ключ \"{}\" должен быть отклонен", contents);
+        }
+
+        fs::remove_file(filename).expect("Не удалось удалить тестовый файл");
+    }
+
+    #[test]
+    fn test_read_truncated_wav_file() {
+        let filename = "test_truncated.wav";
+        let spec = WavSpec { channels: 1, sample_rate: 8000, bits_per_sample: 16, sample_format: SampleFormat::Int };
+
+        let mut writer = WavWriter::create(filename, spec).unwrap();
+        for t in 0..100 {
+            writer.write_sample(t as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        // Обрезаем файл посреди отсчета
+        let bytes = fs::read(filename).unwrap();
+        fs::write(filename, &bytes[..bytes.len() - 51]).unwrap();
+
+        assert!(matches!(get_wav_file_data(filename), Err(StegoError::InvalidWav(_))));
+        assert!(matches!(get_wav_file_data("missing.wav"), Err(StegoError::Io(_))));
+
+        fs::remove_file(filename).expect("Не удалось удалить тестовый файл");
+    }
+
     #[test]
     fn test_write_key_to_file_invalid_path() {
         let key = vec![1, -1, 2, -2, 3];
         let filename = "/invalid/path/test_key.txt"; // Недопустимый путь
 
-        let result = write_key_to_file(&key, filename);
+        let method = SpreadSpectrum { channel_mode: ChannelMode::All, ..SpreadSpectrum::default() };
+        let result = write_key_to_file(&key, &method, filename);
         assert!(result.is_err(), "Функция должна вернуть ошибку для недопустимого пути");
     }
-}
\ No newline at end of file
+
+    // Исходные целочисленные отсчеты файла, без нормировки
+    fn read_raw_samples(filename: &str) -> (WavSpec, Vec<i64>) {
+        let mut reader = WavReader::open(filename).expect("Не удалось открыть WAV-файл");
+        let spec = reader.spec();
+        let samples = match (spec.sample_format, spec.bits_per_sample) {
+            (SampleFormat::Float, _) => reader.samples::<f32>().map(|s| s.unwrap().to_bits() as i64).collect(),
+            (_, 8) => reader.samples::<i8>().map(|s| s.unwrap() as i64).collect(),
+            (_, 16) => reader.samples::<i16>().map(|s| s.unwrap() as i64).collect(),
+            _ => reader.samples::<i32>().map(|s| s.unwrap() as i64).collect(),
+        };
+        (spec, samples)
+    }
+
+    #[test]
+    fn test_wav_round_trip_preserves_format() {
+        let formats = [
+            (SampleFormat::Int, 8),
+            (SampleFormat::Int, 16),
+            (SampleFormat::Int, 24),
+            (SampleFormat::Int, 32),
+            (SampleFormat::Float, 32),
+        ];
+
+        for (sample_format, bits_per_sample) in formats {
+            let input = format!("test_round_trip_{:?}_{}.wav", sample_format, bits_per_sample);
+            let output = format!("test_round_trip_{:?}_{}_out.wav", sample_format, bits_per_sample);
+            let spec = WavSpec { channels: 2, sample_rate: 8000, bits_per_sample, sample_format };
+
+            // Среди отсчетов есть крайние значения формата, в том числе отрицательная полная шкала
+            let mut writer = WavWriter::create(&input, spec).unwrap();
+            let full_scale = 1i64 << (bits_per_sample - 1);
+            for t in 0..200 {
+                let value = match t {
+                    0 => -1.0,
+                    1 => 1.0,
+                    _ => (t as f64 * 0.05).sin() * 0.9,
+                };
+                let sample = ((value * full_scale as f64) as i64).clamp(-full_scale, full_scale - 1);
+                match (sample_format, bits_per_sample) {
+                    (SampleFormat::Float, _) => writer.write_sample(value as f32).unwrap(),
+                    (_, 8) => writer.write_sample(sample as i8).unwrap(),
+                    (_, 16) => writer.write_sample(sample as i16).unwrap(),
+                    _ => writer.write_sample(sample as i32).unwrap(),
+                }
+            }
+            writer.finalize().unwrap();
+
+            let mut wav = get_wav_file_data(&input).expect("Не удалось прочитать WAV-файл");
+            assert_eq!(wav.sample_format, sample_format);
+            assert_eq!(wav.bits_per_sample, bits_per_sample);
+            assert_eq!(wav.amplitudes.len(), 2);
+            assert_eq!(wav.amplitudes[0].len(), 100);
+            assert!(wav.amplitudes.concat().iter().all(|a| a.abs() <= 1.0));
+            assert_eq!(wav.amplitudes[0][0], -1.0);
+
+            wav.name = output.clone();
+            save_amplitudes_to_wav(&wav).expect("Не удалось сохранить WAV-файл");
+
+            // Неизмененные отсчеты должны сохраниться без потерь и в том же формате
+            let (input_spec, input_samples) = read_raw_samples(&input);
+            let (output_spec, output_samples) = read_raw_samples(&output);
+            assert_eq!(input_spec, output_spec);
+            assert_eq!(input_samples, output_samples, "Отсчеты изменились для {:?} {} бит", sample_format, bits_per_sample);
+
+            fs::remove_file(&input).unwrap();
+            fs::remove_file(&output).unwrap();
+        }
+    }
+}